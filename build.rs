@@ -1,5 +1,58 @@
 use std::io::Result;
 fn main() -> Result<()> {
     prost_build::compile_protos(&["takproto/takmessage.proto"], &["takproto/"])?;
+    generate_cot_type_descriptions("cottypes/CoTtypes.xml")?;
+    println!("cargo:rerun-if-changed=takproto");
     Ok(())
 }
+
+/// Generates the table of COT type descriptions, sorted by type, from the `cot` and `desc`
+/// attributes of the `<cot>` elements of a CoTtypes.xml definition
+///
+/// Other elements and attributes of the definition, such as `full`, are ignored.
+fn generate_cot_type_descriptions(definition: &str) -> Result<()> {
+    use quick_xml::events::Event;
+
+    println!("cargo:rerun-if-changed={definition}");
+
+    let invalid = |e: &dyn std::fmt::Display| std::io::Error::other(format!("{definition}: {e}"));
+    let xml = std::fs::read_to_string(definition)?;
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    let mut descriptions: Vec<(String, String)> = Vec::new();
+    loop {
+        match reader.read_event().map_err(|e| invalid(&e))? {
+            Event::Start(element) | Event::Empty(element) if element.name().as_ref() == b"cot" => {
+                let value = |name: &str| -> Result<Option<String>> {
+                    match element.try_get_attribute(name).map_err(|e| invalid(&e))? {
+                        Some(attribute) => Ok(Some(
+                            attribute
+                                .unescape_value()
+                                .map_err(|e| invalid(&e))?
+                                .into_owned(),
+                        )),
+                        None => Ok(None),
+                    }
+                };
+                if let (Some(cot_type), Some(description)) = (value("cot")?, value("desc")?) {
+                    descriptions.push((cot_type, description));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    descriptions.sort();
+    descriptions.dedup_by(|a, b| a.0 == b.0);
+
+    let mut table = String::from("static COT_TYPE_DESCRIPTIONS: &[(&str, &str)] = &[\n");
+    for (cot_type, description) in descriptions {
+        table.push_str(&format!("    ({cot_type:?}, {description:?}),\n"));
+    }
+    table.push_str("];\n");
+
+    let out_dir = std::env::var("OUT_DIR").map_err(std::io::Error::other)?;
+    std::fs::write(
+        std::path::Path::new(&out_dir).join("cot_type_descriptions.rs"),
+        table,
+    )
+}
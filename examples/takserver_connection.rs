@@ -55,6 +55,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         ignore_invalid: false,
         verify_hostname: true,
         auto_reconnect: true,
+        // Optionally pin the server certificate instead of validating it against the root, e.g.
+        // CertificatePin::certificate_sha256("AB:CD:...")?
+        server_pins: Vec::new(),
+        // Optionally check the server certificate against CRLs and stapled OCSP responses
+        revocation: None,
+        ..Default::default()
     };
 
     println!("Connecting to TAK Server at {}", tak_server_url);
@@ -3,17 +3,24 @@
 
 //! Blocking Cursor on Target Publisher implementation
 
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::thread;
 
 use tokio::runtime::Runtime;
 use url::Url;
 
-use crate::{CotSender, CursorOnTarget, PublishError, connection::TakServerSetting};
+use crate::{
+    ActiveReceiver, ActiveServer, CertificateExpiry, CotSender, CredentialSender, Credentials,
+    CursorOnTarget, Destination, ExpiryReceiver, FailoverSetting, PublishError, UdpSetting,
+    connection::TakServerSetting,
+};
 
 /// Blocking version of CotPublisher that runs a Tokio runtime in a separate thread
 pub struct CotPublisher {
     cot_sender: Option<tokio::sync::mpsc::Sender<CotSender>>,
+    credential_sender: Option<CredentialSender>,
+    expiry_receiver: Option<ExpiryReceiver>,
+    active_receiver: Option<ActiveReceiver>,
     _thread: thread::JoinHandle<Result<(), PublishError>>,
 }
 
@@ -39,24 +46,12 @@ impl CotPublisher {
     /// * `bind_address` - Local IP address for interface to bind to
     ///
     pub fn new_multicast_bind(address: IpAddr, port: u16, bind_address: IpAddr) -> Self {
-        let (sender, receiver) =
-            tokio::sync::mpsc::channel::<CotSender>(crate::BROADCAST_CHANNEL_SIZE);
-
-        let thread_handle = thread::spawn(move || {
-            let runtime = Runtime::new().expect("Failed to create Tokio runtime");
-
-            runtime.block_on(crate::multicast_publisher_task(
-                address,
-                port,
-                bind_address,
-                receiver,
-            ))
-        });
-
-        Self {
-            cot_sender: Some(sender),
-            _thread: thread_handle,
-        }
+        Self::new_multicast_bind_custom_channel_capacity(
+            address,
+            port,
+            bind_address,
+            crate::BROADCAST_CHANNEL_SIZE,
+        )
     }
 
     /// Create a new publisher using multicast with defined bind target, this can be used to
@@ -76,49 +71,157 @@ impl CotPublisher {
         port: u16,
         bind_address: IpAddr,
         channel_capacity: usize,
+    ) -> Self {
+        Self::new_multicast_task(
+            SocketAddr::new(address, port),
+            bind_address,
+            None,
+            channel_capacity,
+        )
+    }
+
+    /// Create a new publisher sending UDP datagrams to a unicast host, such as the UDP input
+    /// port of a TAK server
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - Host name or IP address of the receiver
+    /// * `port` - Port to address packets to, usually 8087
+    /// * `settings` - UDP publisher settings
+    ///
+    pub fn new_udp(host: &str, port: u16, settings: UdpSetting) -> Self {
+        Self::new_udp_custom_channel_capacity(host, port, settings, crate::BROADCAST_CHANNEL_SIZE)
+    }
+
+    /// Create a new publisher sending UDP datagrams to a unicast host with custom channel
+    /// capacity
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - Host name or IP address of the receiver
+    /// * `port` - Port to address packets to, usually 8087
+    /// * `settings` - UDP publisher settings
+    /// * `channel_capacity` - Size of the broadcast channel buffer
+    ///
+    pub fn new_udp_custom_channel_capacity(
+        host: &str,
+        port: u16,
+        settings: UdpSetting,
+        channel_capacity: usize,
     ) -> Self {
         let (sender, receiver) = tokio::sync::mpsc::channel::<CotSender>(channel_capacity);
+        let host = host.to_owned();
 
         let thread_handle = thread::spawn(move || {
             let runtime = Runtime::new().expect("Failed to create Tokio runtime");
 
-            runtime.block_on(crate::multicast_publisher_task(
-                address,
-                port,
-                bind_address,
-                receiver,
-            ))
+            runtime.block_on(crate::udp_publisher_task(host, port, settings, receiver))
         });
 
         Self {
             cot_sender: Some(sender),
+            credential_sender: None,
+            expiry_receiver: None,
+            active_receiver: None,
             _thread: thread_handle,
         }
     }
 
-    /// Create a new TAK server publisher
+    /// Create a new publisher from a destination URL or ATAK connect string
+    ///
+    /// See [`crate::CotPublisher::connect`] for the supported destinations.
     ///
     /// # Arguments
     ///
-    /// * `url` - URL for the TAK server
+    /// * `destination` - Destination, e.g. ssl://takserver.example.com:8089,
+    ///   mcast://239.2.3.1:6969?iface=eth0&ttl=4 or takserver.example.com:8087:tcp
+    ///
+    pub fn connect(destination: &str) -> Result<Self, std::io::Error> {
+        Self::connect_with_settings(destination, TakServerSetting::default())
+    }
+
+    /// Create a new publisher from a destination URL or ATAK connect string, using the given
+    /// settings for TAK server connections
+    ///
+    /// The `tls` flag of the settings is replaced by the one implied by the destination scheme.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Destination, e.g. ssl://takserver.example.com:8089
     /// * `settings` - TAK server settings
     ///
-    pub fn new_takserver(url: Url, settings: TakServerSetting<'static>) -> Self {
-        let (sender, receiver) =
-            tokio::sync::mpsc::channel::<CotSender>(crate::BROADCAST_CHANNEL_SIZE);
+    pub fn connect_with_settings(
+        destination: &str,
+        mut settings: TakServerSetting<'static>,
+    ) -> Result<Self, std::io::Error> {
+        Ok(match Destination::parse(destination)? {
+            Destination::TakServer { url, tls } => {
+                settings.tls = tls;
+                Self::new_takserver(url, settings)
+            }
+            Destination::Udp { host, port } => Self::new_udp(&host, port, UdpSetting::default()),
+            Destination::Multicast {
+                address,
+                port,
+                interface,
+                ttl,
+            } => Self::new_multicast_task(
+                SocketAddr::new(address, port),
+                crate::multicast_bind_address(address, interface.as_deref())?,
+                ttl,
+                crate::BROADCAST_CHANNEL_SIZE,
+            ),
+        })
+    }
+
+    /// Create a new multicast publisher
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Multicast group address and port
+    /// * `bind_address` - Local IP address for interface to bind to
+    /// * `ttl` - Optional time to live of multicast packets
+    /// * `channel_capacity` - Size of the broadcast channel buffer
+    ///
+    fn new_multicast_task(
+        destination: SocketAddr,
+        bind_address: IpAddr,
+        ttl: Option<u32>,
+        channel_capacity: usize,
+    ) -> Self {
+        let (sender, receiver) = tokio::sync::mpsc::channel::<CotSender>(channel_capacity);
 
         let thread_handle = thread::spawn(move || {
             let runtime = Runtime::new().expect("Failed to create Tokio runtime");
 
-            runtime.block_on(crate::takserver_publisher_task(url, settings, receiver))
+            runtime.block_on(crate::multicast_publisher_task(
+                destination,
+                bind_address,
+                ttl,
+                receiver,
+            ))
         });
 
         Self {
             cot_sender: Some(sender),
+            credential_sender: None,
+            expiry_receiver: None,
+            active_receiver: None,
             _thread: thread_handle,
         }
     }
 
+    /// Create a new TAK server publisher
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL for the TAK server
+    /// * `settings` - TAK server settings
+    ///
+    pub fn new_takserver(url: Url, settings: TakServerSetting<'static>) -> Self {
+        Self::new_takserver_custom_channel_capacity(url, settings, crate::BROADCAST_CHANNEL_SIZE)
+    }
+
     /// Create a new TAK server publisher with custom channel capacity
     ///
     /// # Arguments
@@ -131,21 +234,152 @@ impl CotPublisher {
         url: Url,
         settings: TakServerSetting<'static>,
         channel_capacity: usize,
+    ) -> Self {
+        Self::new_takserver_task(
+            vec![(url, settings)],
+            FailoverSetting::default(),
+            channel_capacity,
+        )
+    }
+
+    /// Create a new TAK server publisher using a list of servers
+    ///
+    /// See [`crate::CotPublisher::new_takserver_failover`] for the failover behaviour.
+    ///
+    /// # Arguments
+    ///
+    /// * `servers` - URLs for the TAK servers with their settings, the primary server first
+    /// * `failover` - Failover settings
+    ///
+    pub fn new_takserver_failover(
+        servers: Vec<(Url, TakServerSetting<'static>)>,
+        failover: FailoverSetting,
+    ) -> Result<Self, std::io::Error> {
+        Self::new_takserver_failover_custom_channel_capacity(
+            servers,
+            failover,
+            crate::BROADCAST_CHANNEL_SIZE,
+        )
+    }
+
+    /// Create a new TAK server publisher using a list of servers with custom channel capacity
+    ///
+    /// # Arguments
+    ///
+    /// * `servers` - URLs for the TAK servers with their settings, the primary server first
+    /// * `failover` - Failover settings
+    /// * `channel_capacity` - Size of the broadcast channel buffer
+    ///
+    pub fn new_takserver_failover_custom_channel_capacity(
+        servers: Vec<(Url, TakServerSetting<'static>)>,
+        failover: FailoverSetting,
+        channel_capacity: usize,
+    ) -> Result<Self, std::io::Error> {
+        if servers.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "At least one TAK server is required",
+            ));
+        }
+        Ok(Self::new_takserver_task(
+            servers,
+            failover,
+            channel_capacity,
+        ))
+    }
+
+    /// Create a new TAK server publisher
+    ///
+    /// # Arguments
+    ///
+    /// * `servers` - URLs for the TAK servers with their settings, not empty
+    /// * `failover` - Failover settings
+    /// * `channel_capacity` - Size of the broadcast channel buffer
+    ///
+    fn new_takserver_task(
+        servers: Vec<(Url, TakServerSetting<'static>)>,
+        failover: FailoverSetting,
+        channel_capacity: usize,
     ) -> Self {
         let (sender, receiver) = tokio::sync::mpsc::channel::<CotSender>(channel_capacity);
+        let (credential_sender, credential_receiver) = tokio::sync::watch::channel(None);
+        let (expiry_sender, expiry_receiver) = tokio::sync::watch::channel(None);
+        let (active_sender, active_receiver) = tokio::sync::watch::channel(None);
 
         let thread_handle = thread::spawn(move || {
             let runtime = Runtime::new().expect("Failed to create Tokio runtime");
 
-            runtime.block_on(crate::takserver_publisher_task(url, settings, receiver))
+            runtime.block_on(crate::takserver_publisher_task(
+                servers,
+                failover,
+                receiver,
+                credential_receiver,
+                expiry_sender,
+                active_sender,
+            ))
         });
 
         Self {
             cot_sender: Some(sender),
+            credential_sender: Some(credential_sender),
+            expiry_receiver: Some(expiry_receiver),
+            active_receiver: Some(active_receiver),
             _thread: thread_handle,
         }
     }
 
+    /// Replace the client credentials used by a TAK server publisher, the new credentials are
+    /// used from the next reconnect
+    ///
+    /// Credentials whose private key does not match the certificate are rejected, and the
+    /// previous credentials stay in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `credentials` - New client credentials
+    ///
+    pub fn update_credentials(
+        &self,
+        credentials: Credentials<'static>,
+    ) -> Result<(), PublishError> {
+        let credential_sender =
+            self.credential_sender
+                .as_ref()
+                .ok_or(PublishError::ConnectionError(
+                    "Credentials can only be updated for TAK server publishers".into(),
+                ))?;
+
+        credentials.check_key_matches().map_err(|e| {
+            PublishError::ConnectionError(format!("Invalid client credentials: {e}"))
+        })?;
+
+        credential_sender
+            .send(Some(credentials))
+            .map_err(|_| PublishError::ConnectionError("Publish task has stopped".into()))
+    }
+
+    /// Expiry state of the client certificate used for the most recent TAK server connection
+    ///
+    /// Returns `None` for multicast publishers, before the first connection attempt or when no
+    /// client certificate is used.
+    ///
+    pub fn certificate_expiry(&self) -> Option<CertificateExpiry> {
+        self.expiry_receiver
+            .as_ref()
+            .and_then(|receiver| receiver.borrow().clone())
+    }
+
+    /// TAK server the publisher is currently connected to
+    ///
+    /// Returns `None` for multicast and UDP publishers, and while no TAK server connection is
+    /// established.
+    ///
+    pub fn active_server(&self) -> Option<ActiveServer> {
+        self.active_receiver
+            .as_ref()
+            .and_then(|receiver| receiver.borrow().clone())
+    }
+
     /// Create a new CursorOnTarget for publishing
     ///
     /// # Arguments
@@ -179,3 +413,17 @@ impl CotPublisher {
         }
     }
 }
+
+/// Enrolls a new client certificate with the TAK server, blocking until enrollment completes
+///
+/// # Arguments
+///
+/// * `url` - URL of the TAK server enrollment endpoint, e.g. `https://takserver.example.com:8446`
+/// * `settings` - Account and TLS settings for the enrollment
+///
+pub fn enroll(
+    url: &Url,
+    settings: &crate::EnrollmentSettings,
+) -> Result<Credentials<'static>, std::io::Error> {
+    Runtime::new()?.block_on(crate::enroll(url, settings))
+}
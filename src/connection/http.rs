@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! Minimal HTTP/1.1 client used for the TAK server REST endpoints.
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use url::Url;
+
+/// Response returned by the server
+pub(crate) struct HttpResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Response body with any chunked transfer encoding removed
+    pub body: Vec<u8>,
+}
+
+/// Sends a single request over the stream and reads the response until the server closes the
+/// connection
+///
+/// # Arguments
+///
+/// * `stream` - Connected stream to the server
+/// * `method` - HTTP method, e.g. GET
+/// * `url` - URL of the resource, used for the request path and Host header
+/// * `headers` - Additional request headers
+/// * `body` - Request body, may be empty
+///
+pub(crate) async fn request<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    method: &str,
+    url: &Url,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> Result<HttpResponse, std::io::Error> {
+    let host = url
+        .host_str()
+        .ok_or(std::io::Error::other("Host string was missing"))?;
+    let host = match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_owned(),
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_owned(),
+    };
+
+    let mut request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("\r\n");
+
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+
+    let mut response = Vec::new();
+    match stream.read_to_end(&mut response).await {
+        Ok(_) => {}
+        // Servers commonly close TLS connections without a close_notify alert
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && !response.is_empty() => {}
+        Err(e) => return Err(e),
+    }
+
+    parse_response(&response)
+}
+
+/// Parses the status code and body from a raw HTTP response
+fn parse_response(response: &[u8]) -> Result<HttpResponse, std::io::Error> {
+    let header_end = find(response, b"\r\n\r\n")
+        .ok_or(std::io::Error::other("Incomplete HTTP response headers"))?;
+    let head = std::str::from_utf8(&response[..header_end])
+        .map_err(|e| std::io::Error::other(format!("Invalid HTTP response headers: {e}")))?;
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or(std::io::Error::other("Invalid HTTP status line"))?;
+    let chunked = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().eq_ignore_ascii_case("chunked")
+        })
+    });
+
+    let body = &response[header_end + 4..];
+    let body = if chunked {
+        decode_chunked(body)?
+    } else {
+        body.to_vec()
+    };
+
+    Ok(HttpResponse { status, body })
+}
+
+/// Removes chunked transfer encoding from a response body
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end =
+            find(body, b"\r\n").ok_or(std::io::Error::other("Incomplete chunk size line"))?;
+        let size = std::str::from_utf8(&body[..line_end])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or(std::io::Error::other("Invalid chunk size"))?;
+        body = &body[line_end + 2..];
+
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if body.len() < size {
+            return Err(std::io::Error::other("Incomplete chunked response body"));
+        }
+
+        decoded.extend_from_slice(&body[..size]);
+        body = body[size..].strip_prefix(b"\r\n").unwrap_or(&body[size..]);
+    }
+}
+
+/// Finds the first position of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,991 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides an interface for establishing TCP, TLS, WebSocket and QUIC connections
+//! to TAK servers.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::client::Resumption;
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::ServerCertVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::net::TcpStream;
+use tokio_rustls::{TlsConnector, client::TlsStream};
+use url::Url;
+
+pub(crate) mod http;
+mod proxy;
+#[cfg(feature = "quic")]
+mod quic;
+mod verifier;
+mod websocket;
+
+pub use proxy::{ProxyProtocol, ProxySettings};
+pub use verifier::CertificatePin;
+use verifier::{
+    DangerousAcceptAnyServerCertVerifier, PinnedServerCertVerifier, StapledOcspServerCertVerifier,
+};
+
+/// Type alias for the hook used to adjust the generated TLS client configuration
+pub type ClientConfigHook = Box<dyn Fn(&mut ClientConfig) + Send + Sync>;
+
+/// Tak server connection settings
+pub struct TakServerSetting<'a> {
+    /// Use TLS for the connection
+    pub tls: bool,
+    /// Optional client credentials for mutual TLS authentication
+    pub client_credentials: Option<crate::keys::Credentials<'a>>,
+    /// Optional root certificate source for server certificate validation
+    pub root_cert: Option<crate::keys::Source>,
+    /// Ignore invalid server certificates (self-signed, expired, hostname mismatch) - WARNING this
+    /// disables some protections, but may be necessary for some TAK server configurations. Has no
+    /// effect when `server_pins` is set, the pins are still enforced
+    pub ignore_invalid: bool,
+    /// Verify the server hostname against the certificate (Common Name / SAN) - WARNING this disables
+    /// some protections, but may be necessary for some TAK server configurations
+    pub verify_hostname: bool,
+    /// Automatically reconnect on connection loss
+    pub auto_reconnect: bool,
+    /// Delay between reconnection attempts when `auto_reconnect` is enabled
+    pub reconnect_interval: std::time::Duration,
+    /// Optional credential files which are reloaded on reconnect when they have been modified,
+    /// replacing `client_credentials`
+    pub credential_files: Option<crate::keys::CredentialFiles>,
+    /// Number of days before the client certificate expires at which a warning is emitted
+    pub expiry_warning_days: u32,
+    /// SHA-256 pins for the server certificate or its public key, when not empty the server is
+    /// trusted if it matches any pin instead of being validated against the root certificate,
+    /// even when `ignore_invalid`, `client_config` or `configure_client` is set
+    pub server_pins: Vec<CertificatePin>,
+    /// Optional revocation checking of the server certificate, applied when the server is
+    /// validated against the root certificate (not when pinned or ignoring invalid certificates)
+    pub revocation: Option<RevocationSettings>,
+    /// Crypto provider used for the TLS connection, defaults to the process default provider or
+    /// the provider selected by the `aws_lc_rs` / `ring` features
+    pub crypto_provider: Option<Arc<CryptoProvider>>,
+    /// Caller supplied TLS client configuration, when set it is used as-is and the credential,
+    /// verification and [`TlsOptions`] settings other than `server_name` are ignored, except
+    /// that `server_pins` replace its certificate verifier
+    pub client_config: Option<Arc<ClientConfig>>,
+    /// Hook called with the client configuration built from these settings before it is used,
+    /// allowing any option not covered by [`TlsOptions`] to be changed
+    pub configure_client: Option<ClientConfigHook>,
+    /// Additional TLS protocol options
+    pub tls_options: TlsOptions,
+    /// Optional proxy the TCP connection is tunnelled through before the TLS handshake, not
+    /// used for QUIC connections
+    pub proxy: Option<ProxySettings>,
+    /// Take the proxy from the `HTTPS_PROXY` / `ALL_PROXY` environment variables (honouring
+    /// `NO_PROXY`) when `proxy` is not set
+    pub proxy_from_env: bool,
+    /// Timeouts of the connection steps and of writing messages
+    pub timeouts: TimeoutSettings,
+    /// Options of the TCP socket, not used for QUIC connections
+    pub socket_options: SocketOptions,
+    /// Wait for the TAK server to confirm messages published with `publish_checked`, by
+    /// following each with a ping the server answers once it has received the message
+    pub confirm_delivery: bool,
+}
+
+impl Default for TakServerSetting<'_> {
+    fn default() -> Self {
+        Self {
+            tls: true,
+            client_credentials: None,
+            root_cert: None,
+            ignore_invalid: false,
+            verify_hostname: true,
+            auto_reconnect: true,
+            reconnect_interval: std::time::Duration::from_secs(5),
+            credential_files: None,
+            expiry_warning_days: 30,
+            server_pins: Vec::new(),
+            revocation: None,
+            crypto_provider: None,
+            client_config: None,
+            configure_client: None,
+            tls_options: TlsOptions::default(),
+            proxy: None,
+            proxy_from_env: false,
+            timeouts: TimeoutSettings::default(),
+            socket_options: SocketOptions::default(),
+            confirm_delivery: false,
+        }
+    }
+}
+
+/// TLS protocol versions which can be enabled for the connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+/// TLS protocol options for the TAK server connection
+#[derive(Clone, Debug)]
+pub struct TlsOptions {
+    /// Protocol versions to enable, when empty the provider's safe defaults are used
+    pub protocol_versions: Vec<TlsVersion>,
+    /// ALPN protocols to offer, in order of preference
+    pub alpn_protocols: Vec<Vec<u8>>,
+    /// Server name used for SNI and certificate validation instead of the URL host
+    pub server_name: Option<String>,
+    /// Send the server name indication extension
+    pub enable_sni: bool,
+    /// Allow TLS session resumption on reconnect
+    pub session_resumption: bool,
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        Self {
+            protocol_versions: Vec::new(),
+            alpn_protocols: Vec::new(),
+            server_name: None,
+            enable_sni: true,
+            session_resumption: true,
+        }
+    }
+}
+
+/// Timeouts of the TAK server connection, `None` waits indefinitely
+///
+/// Each timeout is reported as its own [`crate::PublishError`] variant.
+#[derive(Clone, Debug)]
+pub struct TimeoutSettings {
+    /// Establishing the TCP connection, including the proxy tunnel when a proxy is used, or
+    /// resolving the address of a QUIC server
+    pub connect: Option<Duration>,
+    /// TLS handshake, WebSocket upgrade, or QUIC handshake and opening the QUIC stream
+    pub handshake: Option<Duration>,
+    /// Switching the connection to the TAK protocol, from sending the request until the TAK
+    /// server has accepted it
+    pub negotiation: Option<Duration>,
+    /// Writing and flushing a single message
+    pub write: Option<Duration>,
+    /// TAK server confirming a message when `confirm_delivery` is enabled
+    pub confirmation: Option<Duration>,
+}
+
+impl Default for TimeoutSettings {
+    fn default() -> Self {
+        Self {
+            connect: Some(Duration::from_secs(10)),
+            handshake: Some(Duration::from_secs(10)),
+            negotiation: Some(Duration::from_secs(10)),
+            write: Some(Duration::from_secs(30)),
+            confirmation: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// Options of the TCP socket of the TAK server connection
+#[derive(Clone, Debug, Default)]
+pub struct SocketOptions {
+    /// Disable Nagle's algorithm so each message is sent without delay
+    pub nodelay: bool,
+    /// Optional TCP keepalive probing, detecting a dead connection while no messages are sent
+    pub keepalive: Option<TcpKeepalive>,
+    /// Optional size of the socket send buffer in bytes, the system default when `None`
+    pub send_buffer_size: Option<usize>,
+}
+
+/// TCP keepalive probe settings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    /// Idle time before the first keepalive probe is sent
+    pub time: Duration,
+    /// Interval between unanswered keepalive probes
+    pub interval: Duration,
+}
+
+/// Revocation checking settings for the TAK server certificate
+pub struct RevocationSettings {
+    /// Certificate revocation lists, PEM or DER encoded
+    pub crls: Vec<crate::keys::Source>,
+    /// Check revocation of every certificate in the chain rather than only the server certificate
+    pub check_chain: bool,
+    /// Accept certificates whose revocation status cannot be determined, because the provided
+    /// CRLs do not cover them or the stapled OCSP response reports their status as unknown or
+    /// does not cover them
+    pub allow_unknown_status: bool,
+    /// Reject CRLs which are past their next update time
+    pub enforce_crl_expiry: bool,
+    /// Check the OCSP response stapled by the server when one is provided
+    pub ocsp_stapling: bool,
+    /// Fail the connection if the server does not staple an OCSP response, whether an unknown
+    /// status in a stapled response is accepted is set by `allow_unknown_status`
+    pub require_ocsp_staple: bool,
+}
+
+impl Default for RevocationSettings {
+    fn default() -> Self {
+        Self {
+            crls: Vec::new(),
+            check_chain: false,
+            allow_unknown_status: false,
+            enforce_crl_expiry: true,
+            ocsp_stapling: true,
+            require_ocsp_staple: false,
+        }
+    }
+}
+
+/// Enum to handle different connection types
+#[allow(clippy::large_enum_variant)]
+pub enum Connection {
+    Tcp(TcpStream),
+    Tls(TlsStream<TcpStream>),
+    WebSocket(Box<websocket::WebSocketConnection>),
+    #[cfg(feature = "quic")]
+    Quic(Box<quic::QuicConnection>),
+}
+
+impl Connection {
+    /// Whether the stream starts in XML mode and has to be switched to the TAK protocol, the
+    /// WebSocket and QUIC endpoints carry the TAK protocol from the start
+    pub(crate) fn requires_protocol_negotiation(&self) -> bool {
+        matches!(self, Connection::Tcp(_) | Connection::Tls(_))
+    }
+}
+
+// Implement AsyncRead for our Connection enum
+impl tokio::io::AsyncRead for Connection {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<Result<(), io::Error>> {
+        match &mut *self {
+            Connection::Tcp(stream) => std::pin::Pin::new(stream).poll_read(cx, buf),
+            Connection::Tls(stream) => std::pin::Pin::new(stream).poll_read(cx, buf),
+            Connection::WebSocket(stream) => std::pin::Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "quic")]
+            Connection::Quic(stream) => std::pin::Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+// Implement AsyncWrite for our Connection enum
+impl tokio::io::AsyncWrite for Connection {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<Result<usize, io::Error>> {
+        match &mut *self {
+            Connection::Tcp(stream) => std::pin::Pin::new(stream).poll_write(cx, buf),
+            Connection::Tls(stream) => std::pin::Pin::new(stream).poll_write(cx, buf),
+            Connection::WebSocket(stream) => std::pin::Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "quic")]
+            Connection::Quic(stream) => std::pin::Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), io::Error>> {
+        match &mut *self {
+            Connection::Tcp(stream) => std::pin::Pin::new(stream).poll_flush(cx),
+            Connection::Tls(stream) => std::pin::Pin::new(stream).poll_flush(cx),
+            Connection::WebSocket(stream) => std::pin::Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "quic")]
+            Connection::Quic(stream) => std::pin::Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), io::Error>> {
+        match &mut *self {
+            Connection::Tcp(stream) => std::pin::Pin::new(stream).poll_shutdown(cx),
+            Connection::Tls(stream) => std::pin::Pin::new(stream).poll_shutdown(cx),
+            Connection::WebSocket(stream) => std::pin::Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "quic")]
+            Connection::Quic(stream) => std::pin::Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+// Main connection initialization method
+pub async fn create_connection(
+    address: &Url,
+    settings: &TakServerSetting<'static>,
+) -> Result<Connection, crate::PublishError> {
+    if address.scheme() == "quic" {
+        #[cfg(feature = "quic")]
+        return Ok(Connection::Quic(Box::new(
+            quic::connect(address, settings).await?,
+        )));
+        #[cfg(not(feature = "quic"))]
+        return Err(connection_error(std::io::Error::other(
+            "QUIC connections require the quic feature",
+        )));
+    }
+
+    let stream = create_stream(address, settings).await?;
+
+    match address.scheme() {
+        // WebSocket endpoints are upgraded over the TCP or TLS connection
+        "ws" | "wss" => Ok(Connection::WebSocket(Box::new(
+            with_timeout(
+                settings.timeouts.handshake,
+                crate::PublishError::HandshakeTimeout,
+                websocket::connect(stream, address),
+            )
+            .await?,
+        ))),
+        _ => Ok(stream),
+    }
+}
+
+/// Establishes the TCP connection and performs the TLS handshake when enabled
+///
+/// # Arguments
+///
+/// * `address` - URL of the server
+/// * `settings` - Settings for the TAK server connection, including credentials
+///
+async fn create_stream(
+    address: &Url,
+    settings: &TakServerSetting<'static>,
+) -> Result<Connection, crate::PublishError> {
+    let host = address
+        .host_str()
+        .ok_or(std::io::Error::other("Host string was missing"))
+        .map_err(connection_error)?;
+    let port = address
+        .port_or_known_default()
+        .ok_or(std::io::Error::other("Port number was missing"))
+        .map_err(connection_error)?;
+
+    let proxy = match &settings.proxy {
+        Some(proxy) => Some(proxy.clone()),
+        None if settings.proxy_from_env => {
+            ProxySettings::from_env(host).map_err(connection_error)?
+        }
+        None => None,
+    };
+
+    // Establish TCP connection first, tunnelled through the proxy when one is used
+    let tcp_stream = with_timeout(
+        settings.timeouts.connect,
+        crate::PublishError::ConnectTimeout,
+        async {
+            match proxy {
+                Some(proxy) => proxy::connect(&proxy, host, port).await,
+                None => TcpStream::connect(&format!("{host}:{port}")).await,
+            }
+        },
+    )
+    .await?;
+    apply_socket_options(&tcp_stream, &settings.socket_options).map_err(connection_error)?;
+
+    if !settings.tls {
+        // Plain TCP connection
+        return Ok(Connection::Tcp(tcp_stream));
+    }
+
+    let server_name = settings
+        .tls_options
+        .server_name
+        .clone()
+        .or(address.host_str().map(|host| host.to_owned()))
+        .ok_or(std::io::Error::other("Host string was missing"))
+        .map_err(connection_error)?;
+    let server_name = ServerName::try_from(server_name)
+        .map_err(|e| std::io::Error::other(format!("Invalid server name: {e}")))
+        .map_err(connection_error)?;
+
+    let connector = TlsConnector::from(client_config(settings).map_err(connection_error)?);
+    let tls_stream = with_timeout(
+        settings.timeouts.handshake,
+        crate::PublishError::HandshakeTimeout,
+        async {
+            connector
+                .connect(server_name, tcp_stream)
+                .await
+                .map_err(|e| std::io::Error::new(e.kind(), format!("TLS handshake failed: {e}")))
+        },
+    )
+    .await?;
+
+    Ok(Connection::Tls(tls_stream))
+}
+
+/// Applies the socket options to the TCP connection
+///
+/// # Arguments
+///
+/// * `stream` - TCP connection to the server or proxy
+/// * `options` - Socket options from the settings
+///
+fn apply_socket_options(stream: &TcpStream, options: &SocketOptions) -> Result<(), io::Error> {
+    if options.nodelay {
+        stream.set_nodelay(true)?;
+    }
+
+    let socket = socket2::SockRef::from(stream);
+    if let Some(keepalive) = options.keepalive {
+        socket.set_tcp_keepalive(
+            &socket2::TcpKeepalive::new()
+                .with_time(keepalive.time)
+                .with_interval(keepalive.interval),
+        )?;
+    }
+    if let Some(size) = options.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+
+    Ok(())
+}
+
+/// Runs a connection step with an optional timeout
+///
+/// When the timeout expires the [`crate::PublishError`] identifying the step is returned,
+/// other errors of the step are returned as a [`crate::PublishError::ConnectionError`].
+///
+/// # Arguments
+///
+/// * `timeout` - Optional timeout of the step
+/// * `error` - Constructor of the error identifying the step
+/// * `future` - The connection step
+///
+pub(crate) async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    error: fn(Duration) -> crate::PublishError,
+    future: impl std::future::Future<Output = Result<T, io::Error>>,
+) -> Result<T, crate::PublishError> {
+    let result = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| error(timeout))?,
+        None => future.await,
+    };
+    result.map_err(connection_error)
+}
+
+/// Converts the error of a connection step to a [`crate::PublishError::ConnectionError`]
+pub(crate) fn connection_error(e: io::Error) -> crate::PublishError {
+    crate::PublishError::ConnectionError(format!("Creating connection to TAK server: {e}"))
+}
+
+/// Returns the TLS client configuration of the connection, the caller supplied configuration
+/// or one built from the settings
+///
+/// When server pins are set they replace the certificate verifier of either configuration, so
+/// neither a supplied configuration nor the `configure_client` hook can bypass them.
+///
+/// # Arguments
+///
+/// * `settings` - Settings for the TAK server connection
+///
+pub(crate) fn client_config(
+    settings: &TakServerSetting<'static>,
+) -> Result<Arc<ClientConfig>, std::io::Error> {
+    let mut client_config = match &settings.client_config {
+        Some(client_config) if settings.server_pins.is_empty() => return Ok(client_config.clone()),
+        Some(client_config) => ClientConfig::clone(client_config),
+        None => build_client_config(settings)?,
+    };
+
+    if !settings.server_pins.is_empty() {
+        let verifier = PinnedServerCertVerifier::new(
+            settings.server_pins.clone(),
+            client_config.crypto_provider(),
+        );
+        client_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(verifier));
+    }
+    Ok(Arc::new(client_config))
+}
+
+/// Builds the TLS client configuration from the connection settings
+///
+/// # Arguments
+///
+/// * `settings` - Settings for the TAK server connection, including credentials
+///
+fn build_client_config(
+    settings: &TakServerSetting<'static>,
+) -> Result<ClientConfig, std::io::Error> {
+    let provider = match &settings.crypto_provider {
+        Some(provider) => provider.clone(),
+        None => default_crypto_provider()?,
+    };
+
+    // Pinning and ignore_invalid replace root certificate validation with a custom verifier,
+    // otherwise the server is validated against the root store. Pins take precedence so that
+    // setting ignore_invalid as well never silently disables them
+    let verifier: Arc<dyn ServerCertVerifier> = if !settings.server_pins.is_empty() {
+        Arc::new(PinnedServerCertVerifier::new(
+            settings.server_pins.clone(),
+            &provider,
+        ))
+    } else if settings.ignore_invalid {
+        Arc::new(DangerousAcceptAnyServerCertVerifier::new(&provider))
+    } else {
+        let root_certs = load_root_certs(
+            settings.root_cert.as_ref(),
+            settings.client_credentials.as_ref(),
+        )?;
+        build_webpki_verifier(root_certs, settings.revocation.as_ref(), provider.clone())?
+    };
+
+    let config = ClientConfig::builder_with_provider(provider);
+    let config = if settings.tls_options.protocol_versions.is_empty() {
+        config.with_safe_default_protocol_versions()
+    } else {
+        let versions: Vec<&'static rustls::SupportedProtocolVersion> = settings
+            .tls_options
+            .protocol_versions
+            .iter()
+            .map(|version| match version {
+                TlsVersion::Tls12 => &rustls::version::TLS12,
+                TlsVersion::Tls13 => &rustls::version::TLS13,
+            })
+            .collect();
+        config.with_protocol_versions(&versions)
+    }
+    .map_err(|e| std::io::Error::other(format!("Failed to build client config: {e}")))?;
+
+    let config = config
+        .dangerous()
+        .with_custom_certificate_verifier(verifier);
+
+    // Build client config based on whether we have client credentials
+    let mut client_config = if let Some(client_credentials) = &settings.client_credentials {
+        // Mutual TLS configuration
+        let client_certs = vec![client_credentials.certificate.to_owned()];
+        let private_key = client_credentials.private_key.clone_key();
+        config
+            .with_client_auth_cert(client_certs, private_key)
+            .map_err(|e| std::io::Error::other(format!("Failed to build client config: {e}")))?
+    } else {
+        // Regular TLS configuration (no client auth)
+        config.with_no_client_auth()
+    };
+
+    client_config.alpn_protocols = settings.tls_options.alpn_protocols.clone();
+    client_config.enable_sni = settings.tls_options.enable_sni;
+    if !settings.tls_options.session_resumption {
+        client_config.resumption = Resumption::disabled();
+    }
+
+    if let Some(configure_client) = &settings.configure_client {
+        configure_client(&mut client_config);
+    }
+
+    Ok(client_config)
+}
+
+/// Returns the crypto provider used when the settings do not supply one
+///
+/// The process default provider is preferred, falling back to the provider enabled by the
+/// `aws_lc_rs` or `ring` feature
+pub(crate) fn default_crypto_provider() -> Result<Arc<CryptoProvider>, std::io::Error> {
+    if let Some(provider) = CryptoProvider::get_default() {
+        return Ok(provider.clone());
+    }
+
+    #[cfg(feature = "aws_lc_rs")]
+    let provider = Ok(Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+    #[cfg(all(feature = "ring", not(feature = "aws_lc_rs")))]
+    let provider = Ok(Arc::new(rustls::crypto::ring::default_provider()));
+    #[cfg(not(any(feature = "aws_lc_rs", feature = "ring")))]
+    let provider = Err(std::io::Error::other(
+        "No crypto provider available, enable the aws_lc_rs or ring feature or set crypto_provider",
+    ));
+
+    provider
+}
+
+/// Loads the root certificates used to validate the server certificate
+///
+/// The root certificate may be provided directly or from the client credentials if a p12
+/// package is used
+///
+/// # Arguments
+///
+/// * `root_cert` - Optional root certificate source from the settings
+/// * `client_credentials` - Optional client credentials which may carry the root certificate
+///
+fn load_root_certs(
+    root_cert: Option<&crate::keys::Source>,
+    client_credentials: Option<&crate::keys::Credentials<'static>>,
+) -> Result<Vec<CertificateDer<'static>>, std::io::Error> {
+    if let Some(root_cert_source) = root_cert {
+        crate::keys::parse_certificates(root_cert_source.load()?)
+    } else if let Some(client_creds) = client_credentials {
+        client_creds.root_cert.clone().ok_or(std::io::Error::other(
+            "No root certificate provided for TLS connection",
+        ))
+    } else {
+        Err(std::io::Error::other(
+            "No root certificate provided for TLS connection",
+        ))
+    }
+}
+
+/// Builds the WebPKI verifier for the root certificates, including any revocation checks
+///
+/// # Arguments
+///
+/// * `root_certs` - Trusted root certificates
+/// * `revocation` - Optional revocation checking settings
+/// * `provider` - Crypto provider used for signature verification
+///
+fn build_webpki_verifier(
+    root_certs: Vec<CertificateDer<'static>>,
+    revocation: Option<&RevocationSettings>,
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn ServerCertVerifier>, std::io::Error> {
+    let mut root_store = RootCertStore::empty();
+    for cert in root_certs.iter().cloned() {
+        root_store.add(cert).map_err(|e| {
+            std::io::Error::other(format!(
+                "Failed to add certificate to root certificate store: {e}"
+            ))
+        })?;
+    }
+
+    let builder = WebPkiServerVerifier::builder_with_provider(Arc::new(root_store), provider);
+    let Some(revocation) = revocation else {
+        return Ok(builder.build().map_err(|e| {
+            std::io::Error::other(format!("Failed to build certificate verifier: {e}"))
+        })?);
+    };
+
+    let mut crls = Vec::new();
+    for source in &revocation.crls {
+        crls.append(&mut crate::keys::parse_crls(source.load_bytes()?)?);
+    }
+
+    let mut builder = builder.with_crls(crls);
+    if !revocation.check_chain {
+        builder = builder.only_check_end_entity_revocation();
+    }
+    if revocation.allow_unknown_status {
+        builder = builder.allow_unknown_revocation_status();
+    }
+    if revocation.enforce_crl_expiry {
+        builder = builder.enforce_revocation_expiration();
+    }
+    let webpki = builder
+        .build()
+        .map_err(|e| std::io::Error::other(format!("Failed to build certificate verifier: {e}")))?;
+
+    if !revocation.ocsp_stapling {
+        return Ok(webpki);
+    }
+
+    Ok(Arc::new(StapledOcspServerCertVerifier::new(
+        webpki,
+        &root_certs,
+        revocation.require_ocsp_staple,
+        revocation.allow_unknown_status,
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::Source;
+    use crate::test_util::{TestCa, TestIdentity, handshake_connection, server_config};
+    use rustls::{CipherSuite, ProtocolVersion};
+
+    /// Settings validating the server against the CA
+    fn settings(ca: &TestCa) -> TakServerSetting<'static> {
+        TakServerSetting {
+            root_cert: Some(Source::String(ca.cert_pem())),
+            ..Default::default()
+        }
+    }
+
+    /// Client configuration trusting only the CA
+    fn trusting(ca: &TestCa) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.cert_der()).unwrap();
+        Arc::new(
+            ClientConfig::builder_with_provider(default_crypto_provider().unwrap())
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        )
+    }
+
+    /// Pin of the certificate of an identity
+    fn pin(identity: &TestIdentity) -> CertificatePin {
+        CertificatePin::Certificate(openssl::sha::sha256(&identity.cert_der()))
+    }
+
+    /// Connects to the server with the client configuration of the settings
+    fn connect(
+        settings: &TakServerSetting<'static>,
+        server: &TestIdentity,
+    ) -> Result<rustls::Connection, rustls::Error> {
+        handshake_connection(
+            ClientConfig::clone(&client_config(settings).unwrap()),
+            server_config(server, None, Vec::new()),
+        )
+    }
+
+    /// Crypto provider limited to one cipher suite
+    fn provider_with_suite(suite: CipherSuite) -> Arc<CryptoProvider> {
+        let provider = default_crypto_provider().unwrap();
+        Arc::new(CryptoProvider {
+            cipher_suites: provider
+                .cipher_suites
+                .iter()
+                .filter(|supported| supported.suite() == suite)
+                .copied()
+                .collect(),
+            ..CryptoProvider::clone(&provider)
+        })
+    }
+
+    #[test]
+    fn supplied_client_config_used_as_is() {
+        let ca = TestCa::new("Test CA");
+        let other_ca = TestCa::new("Other CA");
+        let server = ca.issue("server");
+
+        // The supplied configuration decides which servers are trusted, not root_cert
+        let supplied = trusting(&ca);
+        let settings = TakServerSetting {
+            client_config: Some(supplied.clone()),
+            ..self::settings(&other_ca)
+        };
+        assert!(Arc::ptr_eq(&client_config(&settings).unwrap(), &supplied));
+        assert!(connect(&settings, &server).is_ok());
+
+        let settings = TakServerSetting {
+            client_config: Some(trusting(&other_ca)),
+            ..self::settings(&ca)
+        };
+        assert!(connect(&settings, &server).is_err());
+    }
+
+    #[test]
+    fn pins_apply_to_supplied_client_config() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server");
+        let other = ca.issue("other");
+
+        let settings = TakServerSetting {
+            client_config: Some(trusting(&ca)),
+            server_pins: vec![pin(&other)],
+            ..Default::default()
+        };
+        assert!(connect(&settings, &server).is_err());
+
+        let settings = TakServerSetting {
+            client_config: Some(trusting(&TestCa::new("Other CA"))),
+            server_pins: vec![pin(&server)],
+            ..Default::default()
+        };
+        assert!(connect(&settings, &server).is_ok());
+    }
+
+    #[test]
+    fn pins_apply_after_configure_client() {
+        let ca = TestCa::new("Test CA");
+        let server = TestCa::new("Untrusted CA").issue("server");
+        let accept_any: ClientConfigHook = Box::new(|config| {
+            let verifier = DangerousAcceptAnyServerCertVerifier::new(config.crypto_provider());
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(verifier));
+        });
+
+        let settings = TakServerSetting {
+            configure_client: Some(accept_any),
+            ..settings(&ca)
+        };
+        assert!(connect(&settings, &server).is_ok());
+
+        let settings = TakServerSetting {
+            server_pins: vec![pin(&ca.issue("other"))],
+            ..settings
+        };
+        assert!(connect(&settings, &server).is_err());
+    }
+
+    #[test]
+    fn ignore_invalid_accepts_untrusted_server() {
+        let ca = TestCa::new("Test CA");
+        let server = TestCa::new("Untrusted CA").issue("server");
+        assert!(connect(&settings(&ca), &server).is_err());
+
+        let settings = TakServerSetting {
+            ignore_invalid: true,
+            ..settings(&ca)
+        };
+        assert!(connect(&settings, &server).is_ok());
+    }
+
+    #[test]
+    fn crypto_provider_selection() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server");
+
+        let config = client_config(&settings(&ca)).unwrap();
+        assert_eq!(
+            config.crypto_provider().cipher_suites,
+            default_crypto_provider().unwrap().cipher_suites
+        );
+
+        let provider = provider_with_suite(CipherSuite::TLS13_CHACHA20_POLY1305_SHA256);
+        let settings = TakServerSetting {
+            crypto_provider: Some(provider.clone()),
+            ..settings(&ca)
+        };
+        assert!(Arc::ptr_eq(
+            client_config(&settings).unwrap().crypto_provider(),
+            &provider
+        ));
+        let connection = connect(&settings, &server).unwrap();
+        assert_eq!(
+            connection.negotiated_cipher_suite().unwrap().suite(),
+            CipherSuite::TLS13_CHACHA20_POLY1305_SHA256
+        );
+    }
+
+    #[cfg(feature = "ring")]
+    #[test]
+    fn ring_crypto_provider() {
+        let ca = TestCa::new("Test CA");
+        let settings = TakServerSetting {
+            crypto_provider: Some(Arc::new(rustls::crypto::ring::default_provider())),
+            ..settings(&ca)
+        };
+        assert!(connect(&settings, &ca.issue("server")).is_ok());
+    }
+
+    #[test]
+    fn protocol_versions() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server");
+
+        for (versions, expected) in [
+            (vec![], ProtocolVersion::TLSv1_3),
+            (vec![TlsVersion::Tls12], ProtocolVersion::TLSv1_2),
+            (vec![TlsVersion::Tls13], ProtocolVersion::TLSv1_3),
+            (
+                vec![TlsVersion::Tls12, TlsVersion::Tls13],
+                ProtocolVersion::TLSv1_3,
+            ),
+        ] {
+            let mut settings = settings(&ca);
+            settings.tls_options.protocol_versions = versions.clone();
+            let connection = connect(&settings, &server).unwrap();
+            assert_eq!(
+                connection.protocol_version(),
+                Some(expected),
+                "{versions:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn cipher_suite_restriction() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server");
+        let suite = CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384;
+
+        let mut settings = TakServerSetting {
+            crypto_provider: Some(provider_with_suite(suite)),
+            ..settings(&ca)
+        };
+        settings.tls_options.protocol_versions = vec![TlsVersion::Tls12];
+        let connection = connect(&settings, &server).unwrap();
+        assert_eq!(connection.negotiated_cipher_suite().unwrap().suite(), suite);
+
+        // No cipher suite of the provider can be used with TLS 1.3
+        settings.tls_options.protocol_versions = vec![TlsVersion::Tls13];
+        assert!(build_client_config(&settings).is_err());
+    }
+
+    /// Settings of a connection with a short timeout for each connection step
+    fn short_timeouts(tls: bool, timeout: Duration) -> TakServerSetting<'static> {
+        TakServerSetting {
+            tls,
+            timeouts: TimeoutSettings {
+                connect: Some(timeout),
+                handshake: Some(timeout),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_times_out_when_the_server_does_not_accept() {
+        // The backlog of the listener holds a single connection which is never accepted, so
+        // further connection attempts are not answered
+        let socket = tokio::net::TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = socket.listen(0).unwrap();
+        let address = listener.local_addr().unwrap();
+        let _queued = std::net::TcpStream::connect(address).unwrap();
+
+        let timeout = Duration::from_millis(300);
+        let url = Url::parse(&format!("tcp://{address}")).unwrap();
+        let result = create_connection(&url, &short_timeouts(false, timeout)).await;
+        assert!(
+            matches!(result, Err(crate::PublishError::ConnectTimeout(t)) if t == timeout),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn handshake_times_out_when_the_server_does_not_answer() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            // Hold the connection open without answering the client hello
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            drop(stream);
+        });
+
+        let timeout = Duration::from_millis(300);
+        let url = Url::parse(&format!("ssl://localhost:{}", address.port())).unwrap();
+        let settings = TakServerSetting {
+            root_cert: Some(Source::String(TestCa::new("TAK CA").cert_pem())),
+            ..short_timeouts(true, timeout)
+        };
+        let result = create_connection(&url, &settings).await;
+        assert!(
+            matches!(result, Err(crate::PublishError::HandshakeTimeout(t)) if t == timeout),
+            "{:?}",
+            result.err()
+        );
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn failed_connections_are_connection_errors() {
+        // Nothing listens on the port once the listener is dropped
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("tcp://{}", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+
+        let result = create_connection(&url, &short_timeouts(false, Duration::from_secs(10))).await;
+        assert!(
+            matches!(result, Err(crate::PublishError::ConnectionError(_))),
+            "{:?}",
+            result.err()
+        );
+    }
+}
@@ -0,0 +1,343 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! QUIC transport for TAK server streaming, available with the `quic` feature.
+//!
+//! The TAK protocol stream is carried over a single bidirectional QUIC stream with the same
+//! magic byte and varint framing as TCP/TLS connections. When the local network addresses change
+//! the endpoint is moved to a new socket, migrating the connection to the new network path
+//! rather than establishing a new connection.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use quinn::crypto::rustls::QuicClientConfig;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use url::Url;
+
+use super::{TakServerSetting, connection_error};
+
+/// Interval of keep alive packets, keeping NAT bindings open while no messages are published
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+/// Interval at which the local network addresses are checked for changes
+const ADDRESS_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// TAK protocol stream carried over a QUIC connection
+pub struct QuicConnection {
+    connection: quinn::Connection,
+    send: quinn::SendStream,
+    recv: quinn::RecvStream,
+    migration_task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for QuicConnection {
+    fn drop(&mut self) {
+        self.migration_task.abort();
+        self.connection.close(0u32.into(), b"");
+    }
+}
+
+/// Establishes a QUIC connection to the TAK server and opens the stream used for publishing
+///
+/// # Arguments
+///
+/// * `address` - URL of the TAK server, e.g. quic://takserver.example.com:8090, the port
+///   defaults to 8090 when not given
+/// * `settings` - Settings for the TAK server connection, including credentials
+///
+pub(crate) async fn connect(
+    address: &Url,
+    settings: &TakServerSetting<'static>,
+) -> Result<QuicConnection, crate::PublishError> {
+    let host = address
+        .host_str()
+        .ok_or(io::Error::other("Host string was missing"))
+        .map_err(connection_error)?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = address
+        .port()
+        .unwrap_or(crate::destination::DEFAULT_QUIC_PORT);
+    let remote = super::with_timeout(
+        settings.timeouts.connect,
+        crate::PublishError::ConnectTimeout,
+        async {
+            tokio::net::lookup_host((host, port))
+                .await?
+                .next()
+                .ok_or(io::Error::other(format!(
+                    "{host} did not resolve to any address"
+                )))
+        },
+    )
+    .await?;
+
+    let server_name = settings
+        .tls_options
+        .server_name
+        .clone()
+        .unwrap_or(host.to_owned());
+
+    let client_config = super::client_config(settings).map_err(connection_error)?;
+    let crypto = QuicClientConfig::try_from(client_config)
+        .map_err(|e| io::Error::other(format!("TLS configuration cannot be used for QUIC: {e}")))
+        .map_err(connection_error)?;
+
+    let mut transport = quinn::TransportConfig::default();
+    transport.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+    let mut config = quinn::ClientConfig::new(Arc::new(crypto));
+    config.transport_config(Arc::new(transport));
+
+    let endpoint = quinn::Endpoint::client(SocketAddr::new(unspecified(remote.is_ipv6()), 0))
+        .map_err(connection_error)?;
+    let connecting = endpoint
+        .connect_with(config, remote, &server_name)
+        .map_err(|e| io::Error::other(format!("Failed connecting to {remote}: {e}")))
+        .map_err(connection_error)?;
+    let connection = super::with_timeout(
+        settings.timeouts.handshake,
+        crate::PublishError::HandshakeTimeout,
+        async {
+            connecting
+                .await
+                .map_err(|e| io::Error::other(format!("QUIC handshake failed: {e}")))
+        },
+    )
+    .await?;
+
+    let (send, recv) = super::with_timeout(
+        settings.timeouts.handshake,
+        crate::PublishError::HandshakeTimeout,
+        async {
+            connection
+                .open_bi()
+                .await
+                .map_err(|e| io::Error::other(format!("Failed opening QUIC stream: {e}")))
+        },
+    )
+    .await?;
+
+    Ok(QuicConnection {
+        connection,
+        send,
+        recv,
+        migration_task: tokio::spawn(migrate_on_network_change(endpoint, remote.is_ipv6())),
+    })
+}
+
+/// Moves the endpoint to a new socket whenever the local network addresses change, the server
+/// validates the new path and the connection continues without a new handshake
+///
+/// # Arguments
+///
+/// * `endpoint` - Endpoint of the connection
+/// * `ipv6` - Bind the new socket to the IPv6 unspecified address
+///
+async fn migrate_on_network_change(endpoint: quinn::Endpoint, ipv6: bool) {
+    let mut addresses = local_addresses();
+
+    loop {
+        tokio::time::sleep(ADDRESS_CHECK_INTERVAL).await;
+
+        let current = local_addresses();
+        if current == addresses {
+            continue;
+        }
+        addresses = current;
+
+        match std::net::UdpSocket::bind(SocketAddr::new(unspecified(ipv6), 0))
+            .and_then(|socket| endpoint.rebind(socket))
+        {
+            Ok(()) => crate::handle_warning("Local network changed, migrated QUIC connection"),
+            Err(e) => crate::handle_error(&format!("Failed migrating QUIC connection: {e}")),
+        }
+    }
+}
+
+/// Sorted non-loopback addresses of the local network interfaces
+fn local_addresses() -> Vec<IpAddr> {
+    let mut addresses: Vec<IpAddr> = if_addrs::get_if_addrs()
+        .unwrap_or_default()
+        .iter()
+        .filter(|interface| !interface.is_loopback())
+        .map(|interface| interface.ip())
+        .collect();
+    addresses.sort();
+    addresses
+}
+
+/// Unspecified address of the address family
+fn unspecified(ipv6: bool) -> IpAddr {
+    if ipv6 {
+        IpAddr::from(Ipv6Addr::UNSPECIFIED)
+    } else {
+        IpAddr::from(Ipv4Addr::UNSPECIFIED)
+    }
+}
+
+impl AsyncRead for QuicConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        // The inherent poll_write of SendStream returns quinn's own error type
+        AsyncWrite::poll_write(Pin::new(&mut self.send), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.send).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.send).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::frame;
+    use crate::keys::Source;
+    use crate::test_util::{TestCa, read_frame, server_config};
+    use quinn::crypto::rustls::QuicServerConfig;
+    use rustls::pki_types::CertificateDer;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    /// Client certificate and payload received by the test server, or why it failed
+    type ServerResult = Result<(CertificateDer<'static>, Vec<u8>), String>;
+
+    /// Starts a QUIC server on 127.0.0.1 requiring a client certificate issued by the CA
+    ///
+    /// The server accepts one connection, reports the client certificate and the TAK protocol
+    /// payload read from the first bidirectional stream, and answers with a framed payload.
+    fn start_server(ca: &TestCa) -> (Url, oneshot::Receiver<ServerResult>) {
+        let crypto =
+            QuicServerConfig::try_from(server_config(&ca.issue("takserver"), Some(ca), Vec::new()))
+                .unwrap();
+        let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        let endpoint = quinn::Endpoint::server(config, "127.0.0.1:0".parse().unwrap()).unwrap();
+        let url = format!("quic://{}", endpoint.local_addr().unwrap());
+        let (sender, receiver) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let result = async {
+                let incoming = endpoint.accept().await.ok_or("Endpoint closed")?;
+                let connection = incoming.await.map_err(|e| e.to_string())?;
+                let certificates = connection
+                    .peer_identity()
+                    .and_then(|identity| identity.downcast::<Vec<CertificateDer>>().ok())
+                    .ok_or("No client certificate")?;
+
+                let (mut send, mut recv) =
+                    connection.accept_bi().await.map_err(|e| e.to_string())?;
+                let payload = read_frame(&mut recv).await.map_err(|e| e.to_string())?;
+
+                send.write_all(&frame(b"reply"))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                send.finish().map_err(|e| e.to_string())?;
+                connection.closed().await;
+                Ok((certificates[0].clone(), payload))
+            }
+            .await;
+            let _ = sender.send(result);
+        });
+        (Url::parse(&url).unwrap(), receiver)
+    }
+
+    #[tokio::test]
+    async fn framed_stream_over_mutual_tls() {
+        let ca = TestCa::new("TAK CA");
+        let client = ca.issue("client");
+        let (url, server) = start_server(&ca);
+        let settings = TakServerSetting {
+            client_credentials: Some(client.credentials()),
+            root_cert: Some(Source::String(ca.cert_pem())),
+            ..Default::default()
+        };
+
+        // A payload of 200 bytes has a two byte varint length
+        let payload = vec![0x5a; 200];
+        let mut connection = connect(&url, &settings).await.unwrap();
+        connection.write_all(&frame(&payload)).await.unwrap();
+        connection.flush().await.unwrap();
+
+        let mut reply = Vec::new();
+        connection.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, frame(b"reply"));
+        drop(connection);
+
+        let (certificate, received) = server.await.unwrap().unwrap();
+        assert_eq!(certificate, client.cert_der());
+        assert_eq!(received, payload);
+    }
+
+    #[tokio::test]
+    async fn server_rejects_client_without_credentials() {
+        let ca = TestCa::new("TAK CA");
+        let (url, server) = start_server(&ca);
+        let settings = TakServerSetting {
+            root_cert: Some(Source::String(ca.cert_pem())),
+            ..Default::default()
+        };
+
+        // The client only learns of the rejection once it uses the connection
+        if let Ok(mut connection) = connect(&url, &settings).await {
+            let _ = connection.write_all(&frame(b"payload")).await;
+            let _ = connection.read_to_end(&mut Vec::new()).await;
+        }
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn opening_the_stream_is_bounded_by_the_handshake_timeout() {
+        // The server completes the handshake but does not allow the client to open a stream
+        let ca = TestCa::new("TAK CA");
+        let crypto =
+            QuicServerConfig::try_from(server_config(&ca.issue("takserver"), None, Vec::new()))
+                .unwrap();
+        let mut transport = quinn::TransportConfig::default();
+        transport.max_concurrent_bidi_streams(0u32.into());
+        let mut config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        config.transport_config(Arc::new(transport));
+        let endpoint = quinn::Endpoint::server(config, "127.0.0.1:0".parse().unwrap()).unwrap();
+        let url = Url::parse(&format!("quic://{}", endpoint.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let connection = endpoint.accept().await.unwrap().await.unwrap();
+            connection.closed().await;
+        });
+
+        let timeout = Duration::from_millis(500);
+        let mut settings = TakServerSetting {
+            root_cert: Some(Source::String(ca.cert_pem())),
+            ..Default::default()
+        };
+        settings.timeouts.handshake = Some(timeout);
+        let result = connect(&url, &settings).await;
+        assert!(
+            matches!(result, Err(crate::PublishError::HandshakeTimeout(t)) if t == timeout),
+            "{:?}",
+            result.err()
+        );
+        server.abort();
+    }
+}
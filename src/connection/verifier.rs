@@ -0,0 +1,646 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! Custom server certificate verifiers used when building the TLS client configuration.
+
+use std::sync::Arc;
+
+use openssl::hash::MessageDigest;
+use openssl::ocsp::{OcspCertId, OcspCertStatus, OcspFlag, OcspResponse, OcspResponseStatus};
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{X509, X509VerifyResult};
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+
+/// Allowed clock skew in seconds when checking the validity period of a stapled OCSP response
+const OCSP_CLOCK_SKEW_SECONDS: u32 = 300;
+
+/// SHA-256 fingerprint used to pin the TAK server certificate
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CertificatePin {
+    /// SHA-256 digest of the DER encoded server certificate
+    Certificate([u8; 32]),
+    /// SHA-256 digest of the DER encoded SubjectPublicKeyInfo of the server certificate, this
+    /// survives certificate renewal as long as the key pair is reused
+    PublicKey([u8; 32]),
+}
+
+impl CertificatePin {
+    /// Creates a pin from the SHA-256 fingerprint of the server certificate
+    ///
+    /// # Arguments
+    ///
+    /// * `fingerprint` - Hex encoded digest, colons and whitespace are ignored
+    ///   (e.g. the output of `openssl x509 -noout -fingerprint -sha256`)
+    ///
+    pub fn certificate_sha256(fingerprint: &str) -> Result<Self, std::io::Error> {
+        Ok(Self::Certificate(parse_fingerprint(fingerprint)?))
+    }
+
+    /// Creates a pin from the SHA-256 fingerprint of the server public key (SPKI)
+    ///
+    /// # Arguments
+    ///
+    /// * `fingerprint` - Hex encoded digest, colons and whitespace are ignored
+    ///
+    pub fn public_key_sha256(fingerprint: &str) -> Result<Self, std::io::Error> {
+        Ok(Self::PublicKey(parse_fingerprint(fingerprint)?))
+    }
+}
+
+impl std::fmt::Display for CertificatePin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CertificatePin::Certificate(digest) => {
+                write!(f, "certificate sha256 {}", format_fingerprint(digest))
+            }
+            CertificatePin::PublicKey(digest) => {
+                write!(f, "public key sha256 {}", format_fingerprint(digest))
+            }
+        }
+    }
+}
+
+/// Parses a hex encoded SHA-256 digest, ignoring colons and whitespace
+fn parse_fingerprint(fingerprint: &str) -> Result<[u8; 32], std::io::Error> {
+    let hex: Vec<u8> = fingerprint
+        .bytes()
+        .filter(|c| *c != b':' && !c.is_ascii_whitespace())
+        .collect();
+
+    if hex.len() != 64 {
+        return Err(std::io::Error::other(format!(
+            "Fingerprint must be 32 bytes of hex, got {} characters: {fingerprint}",
+            hex.len()
+        )));
+    }
+
+    let mut digest = [0u8; 32];
+    for (byte, pair) in digest.iter_mut().zip(hex.chunks(2)) {
+        let pair = std::str::from_utf8(pair)
+            .map_err(|e| std::io::Error::other(format!("Invalid fingerprint: {e}")))?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|e| {
+            std::io::Error::other(format!("Invalid fingerprint {fingerprint}: {e}"))
+        })?;
+    }
+    Ok(digest)
+}
+
+/// Formats a digest as colon separated upper case hex, matching the `openssl` tool output
+fn format_fingerprint(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Verifier which accepts the server certificate only if it matches one of the configured pins
+///
+/// Chain validation is replaced by the pin check, which allows self-signed TAK server
+/// certificates to be trusted without disabling verification altogether. Handshake signatures
+/// are still verified against the presented certificate.
+#[derive(Debug)]
+pub(crate) struct PinnedServerCertVerifier {
+    pins: Vec<CertificatePin>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl PinnedServerCertVerifier {
+    pub(crate) fn new(pins: Vec<CertificatePin>, provider: &CryptoProvider) -> Self {
+        Self {
+            pins,
+            algorithms: provider.signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer,
+        _intermediates: &[CertificateDer],
+        _server_name: &ServerName,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let certificate = CertificatePin::Certificate(openssl::sha::sha256(end_entity));
+        let public_key = openssl::x509::X509::from_der(end_entity)
+            .and_then(|cert| cert.public_key())
+            .and_then(|key| key.public_key_to_der())
+            .map(|spki| CertificatePin::PublicKey(openssl::sha::sha256(&spki)))
+            .map_err(|e| {
+                rustls::Error::General(format!("Failed to parse server certificate: {e}"))
+            })?;
+
+        if self
+            .pins
+            .iter()
+            .any(|pin| *pin == certificate || *pin == public_key)
+        {
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        Err(rustls::Error::General(format!(
+            "Server certificate did not match any pinned fingerprint, saw {certificate} and {public_key}"
+        )))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// Verifier which performs the standard WebPKI validation (including any CRLs it was built with)
+/// and then checks the OCSP response stapled by the server
+#[derive(Debug)]
+pub(crate) struct StapledOcspServerCertVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    roots: Vec<X509>,
+    require_staple: bool,
+    allow_unknown_status: bool,
+}
+
+impl StapledOcspServerCertVerifier {
+    /// Creates the verifier
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - WebPKI verifier used for chain and CRL validation
+    /// * `roots` - Trusted root certificates used to validate the OCSP responder
+    /// * `require_staple` - Fail the handshake if the server does not staple a response
+    /// * `allow_unknown_status` - Accept a stapled response which reports the status of the
+    ///   certificate as unknown or does not cover it
+    ///
+    pub(crate) fn new(
+        inner: Arc<WebPkiServerVerifier>,
+        roots: &[CertificateDer],
+        require_staple: bool,
+        allow_unknown_status: bool,
+    ) -> Result<Self, std::io::Error> {
+        let roots = roots
+            .iter()
+            .map(|cert| X509::from_der(cert))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| std::io::Error::other(format!("Failed to parse root certificate: {e}")))?;
+
+        Ok(Self {
+            inner,
+            roots,
+            require_staple,
+            allow_unknown_status,
+        })
+    }
+
+    /// Checks the stapled OCSP response for the end entity certificate and returns the status
+    /// the responder reported for it, unknown when the response does not cover the certificate
+    fn check_ocsp(
+        &self,
+        end_entity: &CertificateDer,
+        intermediates: &[CertificateDer],
+        ocsp_response: &[u8],
+    ) -> Result<OcspCertStatus, String> {
+        let subject = X509::from_der(end_entity)
+            .map_err(|e| format!("Failed to parse server certificate: {e}"))?;
+        let mut chain = Stack::new().map_err(|e| e.to_string())?;
+        for cert in intermediates {
+            let cert = X509::from_der(cert)
+                .map_err(|e| format!("Failed to parse intermediate certificate: {e}"))?;
+            chain.push(cert).map_err(|e| e.to_string())?;
+        }
+
+        let issuer = chain
+            .iter()
+            .map(|cert| cert.to_owned())
+            .chain(self.roots.iter().cloned())
+            .find(|cert| cert.issued(&subject) == X509VerifyResult::OK)
+            .ok_or("Issuer of the server certificate not found for OCSP check")?;
+
+        let mut store = X509StoreBuilder::new().map_err(|e| e.to_string())?;
+        for root in &self.roots {
+            store.add_cert(root.clone()).map_err(|e| e.to_string())?;
+        }
+        let store = store.build();
+
+        let response = OcspResponse::from_der(ocsp_response)
+            .map_err(|e| format!("Failed to parse stapled OCSP response: {e}"))?;
+        if response.status() != OcspResponseStatus::SUCCESSFUL {
+            return Err(format!(
+                "Stapled OCSP response was unsuccessful (status {})",
+                response.status().as_raw()
+            ));
+        }
+
+        let basic = response
+            .basic()
+            .map_err(|e| format!("Failed to parse stapled OCSP response: {e}"))?;
+        basic
+            .verify(&chain, &store, OcspFlag::empty())
+            .map_err(|e| format!("Stapled OCSP response signature is invalid: {e}"))?;
+
+        // Responders identify the certificate by a SHA-1 CertID unless the request used another
+        // digest, some are configured to use SHA-256
+        let mut status = None;
+        for digest in [MessageDigest::sha1(), MessageDigest::sha256()] {
+            let id = OcspCertId::from_cert(digest, &subject, &issuer).map_err(|e| e.to_string())?;
+            status = basic.find_status(&id);
+            if status.is_some() {
+                break;
+            }
+        }
+        // A response which does not cover the server certificate says nothing about its status
+        let Some(status) = status else {
+            return Ok(OcspCertStatus::UNKNOWN);
+        };
+        status
+            .check_validity(OCSP_CLOCK_SKEW_SECONDS, None)
+            .map_err(|e| format!("Stapled OCSP response is outside its validity period: {e}"))?;
+
+        Ok(status.status)
+    }
+}
+
+impl ServerCertVerifier for StapledOcspServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer,
+        intermediates: &[CertificateDer],
+        server_name: &ServerName,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        if ocsp_response.is_empty() {
+            if self.require_staple {
+                return Err(rustls::Error::General(
+                    "Server did not staple an OCSP response".into(),
+                ));
+            }
+            return Ok(verified);
+        }
+
+        match self
+            .check_ocsp(end_entity, intermediates, ocsp_response)
+            .map_err(rustls::Error::General)?
+        {
+            OcspCertStatus::GOOD => Ok(verified),
+            OcspCertStatus::REVOKED => {
+                Err(rustls::Error::InvalidCertificate(CertificateError::Revoked))
+            }
+            _ if self.allow_unknown_status => Ok(verified),
+            _ => Err(rustls::Error::InvalidCertificate(
+                CertificateError::UnknownRevocationStatus,
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+
+    fn root_hint_subjects(&self) -> Option<&[rustls::DistinguishedName]> {
+        self.inner.root_hint_subjects()
+    }
+}
+
+// Custom certificate verifier for when ignore_invalid is true
+#[derive(Debug)]
+pub(crate) struct DangerousAcceptAnyServerCertVerifier {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl DangerousAcceptAnyServerCertVerifier {
+    pub(crate) fn new(provider: &CryptoProvider) -> Self {
+        Self {
+            algorithms: provider.signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for DangerousAcceptAnyServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer,
+        _intermediates: &[CertificateDer],
+        _server_name: &ServerName,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+
+    fn requires_raw_public_keys(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> Option<&[rustls::DistinguishedName]> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{
+        RevocationSettings, TakServerSetting, build_client_config, default_crypto_provider,
+    };
+    use crate::keys::Source;
+    use crate::test_util::{TEST_HOST, TestCa, TestIdentity, handshake, server_config};
+    use openssl::x509::X509Crl;
+
+    fn verify(verifier: &impl ServerCertVerifier, cert: &CertificateDer) -> bool {
+        let server_name = ServerName::try_from(TEST_HOST).unwrap();
+        verifier
+            .verify_server_cert(cert, &[], &server_name, &[], UnixTime::now())
+            .is_ok()
+    }
+
+    #[test]
+    fn pinned_verifier_accepts_matching_fingerprint() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server").cert_der();
+        let provider = default_crypto_provider().unwrap();
+
+        let fingerprint = format_fingerprint(&openssl::sha::sha256(&server));
+        let pin = CertificatePin::certificate_sha256(&fingerprint).unwrap();
+        assert!(verify(
+            &PinnedServerCertVerifier::new(vec![pin], &provider),
+            &server
+        ));
+
+        let spki = X509::from_der(&server)
+            .unwrap()
+            .public_key()
+            .unwrap()
+            .public_key_to_der()
+            .unwrap();
+        let pin = CertificatePin::PublicKey(openssl::sha::sha256(&spki));
+        assert!(verify(
+            &PinnedServerCertVerifier::new(vec![pin], &provider),
+            &server
+        ));
+    }
+
+    #[test]
+    fn pinned_verifier_rejects_other_fingerprint() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server").cert_der();
+        let other = ca.issue("other").cert_der();
+        let provider = default_crypto_provider().unwrap();
+
+        let pins = vec![
+            CertificatePin::Certificate(openssl::sha::sha256(&other)),
+            CertificatePin::PublicKey(openssl::sha::sha256(&other)),
+        ];
+        assert!(!verify(
+            &PinnedServerCertVerifier::new(pins, &provider),
+            &server
+        ));
+    }
+
+    #[test]
+    fn fingerprint_parsing() {
+        let digest = [0xabu8; 32];
+        let formatted = format_fingerprint(&digest);
+        assert_eq!(parse_fingerprint(&formatted).unwrap(), digest);
+        assert_eq!(parse_fingerprint(&"AB".repeat(32)).unwrap(), digest);
+        assert!(parse_fingerprint("AB:CD").is_err());
+        assert!(parse_fingerprint(&"ZZ".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn pins_are_enforced_when_ignoring_invalid_certificates() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server");
+        let other = ca.issue("other");
+
+        let settings = |pin: &X509| TakServerSetting {
+            ignore_invalid: true,
+            server_pins: vec![CertificatePin::Certificate(openssl::sha::sha256(
+                &pin.to_der().unwrap(),
+            ))],
+            ..Default::default()
+        };
+
+        let config = build_client_config(&settings(&other.cert)).unwrap();
+        assert!(handshake(config, server_config(&server, None, Vec::new())).is_err());
+
+        let config = build_client_config(&settings(&server.cert)).unwrap();
+        assert!(handshake(config, server_config(&server, None, Vec::new())).is_ok());
+    }
+
+    /// Settings validating the server against the CA with revocation checking
+    fn revocation_settings(
+        ca: &TestCa,
+        revocation: RevocationSettings,
+    ) -> TakServerSetting<'static> {
+        TakServerSetting {
+            root_cert: Some(Source::String(ca.cert_pem())),
+            revocation: Some(revocation),
+            ..Default::default()
+        }
+    }
+
+    /// Connects to a server stapling an OCSP response built for its certificate
+    fn connect_with_staple(
+        require_ocsp_staple: bool,
+        allow_unknown_status: bool,
+        ocsp: impl FnOnce(&TestCa, &TestIdentity) -> Vec<u8>,
+    ) -> Result<(), rustls::Error> {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server");
+        let settings = revocation_settings(
+            &ca,
+            RevocationSettings {
+                allow_unknown_status,
+                require_ocsp_staple,
+                ..Default::default()
+            },
+        );
+        let ocsp = ocsp(&ca, &server);
+        handshake(
+            build_client_config(&settings).unwrap(),
+            server_config(&server, None, ocsp),
+        )
+    }
+
+    #[test]
+    fn crl_rejects_revoked_server_certificate() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server");
+        let other = ca.issue("other");
+
+        for (revoked, accepted) in [(&other, true), (&server, false)] {
+            let crl = X509Crl::from_der(&ca.crl(&[&revoked.cert])).unwrap();
+            let crl = String::from_utf8(crl.to_pem().unwrap()).unwrap();
+            let settings = revocation_settings(
+                &ca,
+                RevocationSettings {
+                    crls: vec![Source::String(crl)],
+                    ..Default::default()
+                },
+            );
+            let result = handshake(
+                build_client_config(&settings).unwrap(),
+                server_config(&server, None, Vec::new()),
+            );
+            assert_eq!(result.is_ok(), accepted, "{result:?}");
+        }
+    }
+
+    #[test]
+    fn crl_of_other_issuer_leaves_status_unknown() {
+        let ca = TestCa::new("Test CA");
+        let server = ca.issue("server");
+        let crl = X509Crl::from_der(&TestCa::new("Other CA").crl(&[])).unwrap();
+        let crl = String::from_utf8(crl.to_pem().unwrap()).unwrap();
+
+        for allow_unknown_status in [false, true] {
+            let settings = revocation_settings(
+                &ca,
+                RevocationSettings {
+                    crls: vec![Source::String(crl.clone())],
+                    allow_unknown_status,
+                    ..Default::default()
+                },
+            );
+            let result = handshake(
+                build_client_config(&settings).unwrap(),
+                server_config(&server, None, Vec::new()),
+            );
+            assert_eq!(result.is_ok(), allow_unknown_status, "{result:?}");
+        }
+    }
+
+    #[test]
+    fn ocsp_good_response_is_accepted() {
+        for digest in [MessageDigest::sha1(), MessageDigest::sha256()] {
+            let result = connect_with_staple(true, false, |ca, server| {
+                ca.ocsp_response(&server.cert, digest, OcspCertStatus::GOOD)
+            });
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
+
+    #[test]
+    fn ocsp_revoked_response_is_rejected() {
+        for (require_staple, allow_unknown_status) in [(false, false), (true, true)] {
+            let result = connect_with_staple(require_staple, allow_unknown_status, |ca, server| {
+                ca.ocsp_response(
+                    &server.cert,
+                    MessageDigest::sha256(),
+                    OcspCertStatus::REVOKED,
+                )
+            });
+            assert_eq!(
+                result,
+                Err(rustls::Error::InvalidCertificate(CertificateError::Revoked))
+            );
+        }
+    }
+
+    /// Asserts a stapled response is rejected with an unknown status unless unknown statuses are
+    /// allowed, whether or not a staple is required
+    fn assert_unknown_status(ocsp: impl Fn(&TestCa, &TestIdentity) -> Vec<u8>) {
+        for require_staple in [false, true] {
+            assert_eq!(
+                connect_with_staple(require_staple, false, &ocsp),
+                Err(rustls::Error::InvalidCertificate(
+                    CertificateError::UnknownRevocationStatus
+                ))
+            );
+            assert!(connect_with_staple(require_staple, true, &ocsp).is_ok());
+        }
+    }
+
+    #[test]
+    fn ocsp_unknown_response_fails_unless_allowed() {
+        assert_unknown_status(|ca, server| {
+            ca.ocsp_response(&server.cert, MessageDigest::sha1(), OcspCertStatus::UNKNOWN)
+        });
+    }
+
+    #[test]
+    fn ocsp_response_for_other_certificate_is_unknown() {
+        assert_unknown_status(|ca, _| {
+            let other = ca.issue("other");
+            ca.ocsp_response(&other.cert, MessageDigest::sha1(), OcspCertStatus::GOOD)
+        });
+    }
+
+    #[test]
+    fn missing_staple_fails_only_when_required() {
+        // A missing staple is not an unknown status
+        assert!(connect_with_staple(false, false, |_, _| Vec::new()).is_ok());
+        assert!(connect_with_staple(true, true, |_, _| Vec::new()).is_err());
+    }
+}
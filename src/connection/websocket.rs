@@ -0,0 +1,286 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! WebSocket transport for TAK server streaming endpoints.
+//!
+//! The publisher writes the same framed TAK protocol stream as it does over TCP/TLS (magic byte,
+//! varint length, message). Each complete message is sent as a single binary frame without the
+//! stream framing, and received binary frames are framed again so the connection reads like any
+//! other TAK protocol stream.
+
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures_util::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use url::Url;
+
+use super::Connection;
+
+/// TAK protocol stream carried over a WebSocket
+pub struct WebSocketConnection {
+    stream: WebSocketStream<Connection>,
+    /// Written bytes which do not yet form a complete TAK protocol message
+    write_buffer: Vec<u8>,
+    /// Complete messages waiting to be sent as binary frames
+    pending: VecDeque<Vec<u8>>,
+    /// Received messages framed as a TAK protocol stream, not yet read
+    read_buffer: VecDeque<u8>,
+}
+
+/// Performs the WebSocket handshake over an established TCP or TLS connection
+///
+/// # Arguments
+///
+/// * `stream` - Connection to the TAK server
+/// * `url` - URL of the WebSocket endpoint, e.g. wss://takserver.example.com:8443/takproto/1
+///
+pub(crate) async fn connect(
+    stream: Connection,
+    url: &Url,
+) -> Result<WebSocketConnection, io::Error> {
+    let (stream, _) = tokio_tungstenite::client_async(url.as_str(), stream)
+        .await
+        .map_err(|e| io::Error::other(format!("WebSocket handshake failed: {e}")))?;
+
+    Ok(WebSocketConnection {
+        stream,
+        write_buffer: Vec::new(),
+        pending: VecDeque::new(),
+        read_buffer: VecDeque::new(),
+    })
+}
+
+impl WebSocketConnection {
+    /// Moves every complete TAK protocol message from the write buffer to the pending frames
+    fn extract_messages(&mut self) -> Result<(), io::Error> {
+        loop {
+            let Some(&magic) = self.write_buffer.first() else {
+                return Ok(());
+            };
+            if magic != crate::TCP_MAGIC[0] {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "WebSocket connections only carry TAK protocol messages",
+                ));
+            }
+
+            let Some(payload) = crate::framing::decode_frame(&self.write_buffer)? else {
+                return Ok(());
+            };
+
+            self.pending
+                .push_back(self.write_buffer[payload.clone()].to_vec());
+            self.write_buffer.drain(..payload.end);
+        }
+    }
+
+    /// Starts sending the pending frames and flushes them to the connection
+    fn poll_send_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        while !self.pending.is_empty() {
+            ready!(Pin::new(&mut self.stream).poll_ready(cx)).map_err(websocket_error)?;
+            if let Some(message) = self.pending.pop_front() {
+                Pin::new(&mut self.stream)
+                    .start_send(Message::Binary(message.into()))
+                    .map_err(websocket_error)?;
+            }
+        }
+
+        Pin::new(&mut self.stream)
+            .poll_flush(cx)
+            .map_err(websocket_error)
+    }
+}
+
+impl AsyncRead for WebSocketConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        while self.read_buffer.is_empty() {
+            match ready!(Pin::new(&mut self.stream).poll_next(cx)) {
+                Some(Ok(Message::Binary(message))) => {
+                    if u32::try_from(message.len()).is_err() {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "WebSocket message too large",
+                        )));
+                    }
+                    self.read_buffer.extend(crate::framing::frame(&message));
+                }
+                Some(Ok(Message::Text(message))) => {
+                    self.read_buffer.extend(message.as_bytes());
+                }
+                // Control frames are answered by the WebSocket implementation
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Err(e)) => return Poll::Ready(Err(websocket_error(e))),
+            }
+        }
+
+        let count = buf.remaining().min(self.read_buffer.len());
+        let data: Vec<u8> = self.read_buffer.drain(..count).collect();
+        buf.put_slice(&data);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for WebSocketConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        // Apply back pressure while earlier messages are still waiting for the connection
+        ready!(self.poll_send_pending(cx))?;
+
+        self.write_buffer.extend_from_slice(buf);
+        self.extract_messages()?;
+
+        // Sending is completed by later writes or a flush if the connection is not ready
+        if let Poll::Ready(Err(e)) = self.poll_send_pending(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.poll_send_pending(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        ready!(self.poll_send_pending(cx))?;
+        Pin::new(&mut self.stream)
+            .poll_close(cx)
+            .map_err(websocket_error)
+    }
+}
+
+/// Converts a WebSocket error to an IO error
+fn websocket_error(e: WsError) -> io::Error {
+    match e {
+        WsError::Io(e) => e,
+        e => io::Error::other(format!("WebSocket error: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{TakServerSetting, create_connection};
+    use crate::framing::frame;
+    use futures_util::{SinkExt, StreamExt};
+    use prost::Message as _;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Starts a WebSocket server on 127.0.0.1 which echoes binary frames, returning the URL of
+    /// its endpoint and a receiver of every frame it received
+    async fn start_echo_server() -> (Url, mpsc::UnboundedReceiver<Message>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/takproto/1", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(message)) = stream.next().await {
+                if message.is_close() {
+                    break;
+                }
+                sender.send(message.clone()).unwrap();
+                if message.is_binary() {
+                    stream.send(message).await.unwrap();
+                }
+            }
+        });
+        (Url::parse(&url).unwrap(), receiver)
+    }
+
+    #[tokio::test]
+    async fn framed_messages_are_sent_as_single_binary_frames() {
+        let (url, mut frames) = start_echo_server().await;
+        let settings = TakServerSetting {
+            tls: false,
+            ..Default::default()
+        };
+        let mut connection = create_connection(&url, &settings).await.unwrap();
+
+        // A message of 300 bytes has a two byte length, the last message is written a byte at a
+        // time
+        let payloads = [b"first".to_vec(), vec![0xab; 300], b"third".to_vec()];
+        let stream: Vec<u8> = payloads.iter().flat_map(|payload| frame(payload)).collect();
+        let last = frame(&payloads[2]);
+        connection
+            .write_all(&stream[..stream.len() - last.len()])
+            .await
+            .unwrap();
+        for byte in &last {
+            connection
+                .write_all(std::slice::from_ref(byte))
+                .await
+                .unwrap();
+        }
+        connection.flush().await.unwrap();
+
+        let mut echoed = vec![0u8; stream.len()];
+        connection.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(echoed, stream);
+        connection.shutdown().await.unwrap();
+
+        let mut received = Vec::new();
+        while let Some(frame) = frames.recv().await {
+            received.push(frame);
+        }
+        let expected: Vec<Message> = payloads
+            .into_iter()
+            .map(|payload| Message::Binary(payload.into()))
+            .collect();
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn unframed_data_is_rejected() {
+        let (url, _frames) = start_echo_server().await;
+        let settings = TakServerSetting {
+            tls: false,
+            ..Default::default()
+        };
+        let mut connection = create_connection(&url, &settings).await.unwrap();
+
+        let error = connection.write_all(b"<event/>").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn publisher_sends_each_message_as_a_binary_frame() {
+        let (url, mut frames) = start_echo_server().await;
+        let publisher = crate::CotPublisher::connect(url.as_str()).unwrap();
+
+        for uid in ["first", "second"] {
+            let mut cot = publisher.create_cot(uid, "a-f-G-U-C").unwrap();
+            cot.set_position(51.5, -0.12);
+            cot.publish_checked().await.unwrap();
+        }
+
+        let mut uids = Vec::new();
+        while uids.len() < 2 {
+            let frame = frames.recv().await.unwrap();
+            let Message::Binary(frame) = frame else {
+                panic!("Received a frame which is not binary: {frame:?}");
+            };
+            let message = crate::tak_proto::TakMessage::decode(frame).unwrap();
+            uids.push(message.cot_event.unwrap().uid);
+        }
+        assert_eq!(uids, ["first", "second"]);
+    }
+}
@@ -0,0 +1,538 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides the COT type (e.g. `a-f-G-U-C`), a hierarchy of atoms separated by `-`.
+//!
+//! Types of physical entities start with the atom `a`, followed by the affiliation, the battle
+//! dimension and the function codes, each narrowing the one before. Other types, such as
+//! `b-m-p-s-p-i` for a map point or `t-x-c-t` for a ping, are kept as they are.
+
+use std::fmt;
+use std::str::FromStr;
+
+// Table of COT type descriptions generated by build.rs from cottypes/CoTtypes.xml
+include!(concat!(env!("OUT_DIR"), "/cot_type_descriptions.rs"));
+
+/// First atom of the types of physical entities
+const ATOM: &str = "a";
+/// Separator of the atoms of a type
+const SEPARATOR: char = '-';
+
+/// COT type, validated to consist of non-empty alphanumeric atoms, with a known affiliation and
+/// dimension for atom types
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CotType(String);
+
+/// Affiliation of an entity, the second atom of an atom type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Affiliation {
+    Pending,
+    Unknown,
+    AssumedFriend,
+    Friend,
+    Neutral,
+    Suspect,
+    Hostile,
+    Joker,
+    Faker,
+    None,
+    Other,
+}
+
+/// Battle dimension of an entity, the third atom of an atom type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    Space,
+    Air,
+    Ground,
+    SeaSurface,
+    Subsurface,
+    Sof,
+    Other,
+}
+
+impl CotType {
+    /// Creates the atom type of an entity with the given affiliation and dimension, such as
+    /// `a-h-G`
+    ///
+    /// # Arguments
+    ///
+    /// * `affiliation` - Affiliation of the entity
+    /// * `dimension` - Battle dimension of the entity
+    ///
+    pub fn atom(affiliation: Affiliation, dimension: Dimension) -> Self {
+        Self(format!("{ATOM}-{affiliation}-{dimension}"))
+    }
+
+    /// Appends function codes to the type (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `function` - Function codes separated by `-`, e.g. `U-C-I`
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if a code is empty or not alphanumeric, such as in `U--C`
+    ///
+    pub fn function(self, function: &str) -> Result<Self, std::io::Error> {
+        format!("{self}{SEPARATOR}{function}").parse()
+    }
+
+    /// Returns the type with the affiliation replaced, such as to turn a track hostile
+    ///
+    /// Types which are not atom types are returned unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `affiliation` - New affiliation of the entity
+    ///
+    pub fn with_affiliation(self, affiliation: Affiliation) -> Self {
+        if !self.is_atom() {
+            return self;
+        }
+
+        let mut atoms: Vec<&str> = self.atoms().collect();
+        let affiliation = affiliation.as_str();
+        match atoms.get_mut(1) {
+            Some(atom) => *atom = affiliation,
+            None => atoms.push(affiliation),
+        }
+        Self(atoms.join("-"))
+    }
+
+    /// The type as used in COT messages
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Atoms of the type
+    pub fn atoms(&self) -> impl Iterator<Item = &str> {
+        self.0.split(SEPARATOR)
+    }
+
+    /// Whether the type describes a physical entity, starting with the atom `a`
+    pub fn is_atom(&self) -> bool {
+        self.atoms().next() == Some(ATOM)
+    }
+
+    /// Affiliation of an atom type
+    pub fn affiliation(&self) -> Option<Affiliation> {
+        if !self.is_atom() {
+            return None;
+        }
+        self.atoms().nth(1)?.parse().ok()
+    }
+
+    /// Battle dimension of an atom type
+    pub fn dimension(&self) -> Option<Dimension> {
+        if !self.is_atom() {
+            return None;
+        }
+        self.atoms().nth(2)?.parse().ok()
+    }
+
+    /// Function codes of an atom type separated by `-`, e.g. `U-C-I`, empty when the type ends
+    /// at the dimension
+    pub fn function_id(&self) -> Option<String> {
+        if !self.is_atom() {
+            return None;
+        }
+        Some(self.atoms().skip(3).collect::<Vec<_>>().join("-"))
+    }
+
+    /// Human readable description of the type, e.g. `Friendly Ground Equipment Vehicle Armored
+    /// Tank` for `a-f-G-E-V-A-T`
+    ///
+    /// When the exact type is unknown the description of its nearest known ancestor is used,
+    /// so `a-f-G-U-C-I-Q` is described as `Friendly Ground Unit Combat Infantry`. Returns
+    /// `None` when no ancestor is known either.
+    ///
+    /// The descriptions are a subset of the standard CoTtypes.xml type tree written for this
+    /// crate, see `cottypes/CoTtypes.xml`: the dimensions and common functions of atom types,
+    /// and the map point, drawing, alarm, chat, ping and TAK protocol types used by ATAK. Other
+    /// types are described by their nearest ancestor in the subset.
+    pub fn describe(&self) -> Option<String> {
+        let mut atoms: Vec<&str> = self.atoms().collect();
+        let affiliation = self.affiliation();
+        if affiliation.is_some() {
+            // Atom types are described independent of the affiliation
+            atoms[1] = ".";
+        }
+
+        let description = (1..=atoms.len()).rev().find_map(|length| {
+            let ancestor = atoms[..length].join("-");
+            COT_TYPE_DESCRIPTIONS
+                .binary_search_by_key(&ancestor.as_str(), |(cot_type, _)| cot_type)
+                .ok()
+                .map(|index| COT_TYPE_DESCRIPTIONS[index].1)
+        });
+
+        match (affiliation, description) {
+            (Some(affiliation), Some(description)) => {
+                Some(format!("{} {description}", affiliation.name()))
+            }
+            (Some(affiliation), None) => Some(affiliation.name().into()),
+            (None, description) => description.map(Into::into),
+        }
+    }
+
+    /// Whether the type matches a pattern of atoms separated by `-`
+    ///
+    /// A `*` or `.` atom matches any single atom, and a trailing `*` matches any number of
+    /// atoms including none, so `a-h-*` matches every hostile atom type and `a-.-G` every ground
+    /// entity without function codes.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - Pattern to match, e.g. `a-h-*`
+    ///
+    pub fn matches(&self, pattern: &str) -> bool {
+        let mut atoms = self.atoms();
+        let mut patterns = pattern.split(SEPARATOR).peekable();
+
+        while let Some(pattern) = patterns.next() {
+            if pattern == "*" && patterns.peek().is_none() {
+                return true;
+            }
+            match atoms.next() {
+                Some(_) if pattern == "*" || pattern == "." => {}
+                Some(atom) if atom == pattern => {}
+                _ => return false,
+            }
+        }
+        atoms.next().is_none()
+    }
+}
+
+impl fmt::Display for CotType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for CotType {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<CotType> for String {
+    fn from(cot_type: CotType) -> Self {
+        cot_type.0
+    }
+}
+
+impl FromStr for CotType {
+    type Err = std::io::Error;
+
+    /// Parses a COT type, rejecting empty atoms such as in `a-f-G-U-C-` and unknown
+    /// affiliations or dimensions of atom types
+    fn from_str(cot_type: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid COT type {cot_type}: {reason}"),
+            )
+        };
+
+        if let Some(atom) = cot_type.split(SEPARATOR).find(|atom| !is_valid_atom(atom)) {
+            return Err(invalid(if atom.is_empty() {
+                "empty atom".into()
+            } else {
+                format!("invalid atom {atom}")
+            }));
+        }
+
+        let cot_type = Self(cot_type.into());
+        if cot_type.is_atom() {
+            if let Some(affiliation) = cot_type.atoms().nth(1) {
+                affiliation
+                    .parse::<Affiliation>()
+                    .map_err(|e| invalid(e.to_string()))?;
+            }
+            if let Some(dimension) = cot_type.atoms().nth(2) {
+                dimension
+                    .parse::<Dimension>()
+                    .map_err(|e| invalid(e.to_string()))?;
+            }
+        }
+        Ok(cot_type)
+    }
+}
+
+impl Affiliation {
+    /// All affiliations
+    pub const ALL: [Affiliation; 11] = [
+        Affiliation::Pending,
+        Affiliation::Unknown,
+        Affiliation::AssumedFriend,
+        Affiliation::Friend,
+        Affiliation::Neutral,
+        Affiliation::Suspect,
+        Affiliation::Hostile,
+        Affiliation::Joker,
+        Affiliation::Faker,
+        Affiliation::None,
+        Affiliation::Other,
+    ];
+
+    /// Human readable name of the affiliation, e.g. `Friendly`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Affiliation::Pending => "Pending",
+            Affiliation::Unknown => "Unknown",
+            Affiliation::AssumedFriend => "Assumed Friend",
+            Affiliation::Friend => "Friendly",
+            Affiliation::Neutral => "Neutral",
+            Affiliation::Suspect => "Suspect",
+            Affiliation::Hostile => "Hostile",
+            Affiliation::Joker => "Joker",
+            Affiliation::Faker => "Faker",
+            Affiliation::None => "None Specified",
+            Affiliation::Other => "Other",
+        }
+    }
+
+    /// Atom of the affiliation as used in COT types, e.g. `h` for hostile
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Affiliation::Pending => "p",
+            Affiliation::Unknown => "u",
+            Affiliation::AssumedFriend => "a",
+            Affiliation::Friend => "f",
+            Affiliation::Neutral => "n",
+            Affiliation::Suspect => "s",
+            Affiliation::Hostile => "h",
+            Affiliation::Joker => "j",
+            Affiliation::Faker => "k",
+            Affiliation::None => "o",
+            Affiliation::Other => "x",
+        }
+    }
+}
+
+impl Dimension {
+    /// All battle dimensions
+    pub const ALL: [Dimension; 7] = [
+        Dimension::Space,
+        Dimension::Air,
+        Dimension::Ground,
+        Dimension::SeaSurface,
+        Dimension::Subsurface,
+        Dimension::Sof,
+        Dimension::Other,
+    ];
+
+    /// Atom of the dimension as used in COT types, e.g. `G` for ground
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dimension::Space => "P",
+            Dimension::Air => "A",
+            Dimension::Ground => "G",
+            Dimension::SeaSurface => "S",
+            Dimension::Subsurface => "U",
+            Dimension::Sof => "F",
+            Dimension::Other => "X",
+        }
+    }
+}
+
+impl fmt::Display for Affiliation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Affiliation {
+    type Err = std::io::Error;
+
+    /// Parses an affiliation by its atom, e.g. `h`
+    fn from_str(atom: &str) -> Result<Self, Self::Err> {
+        Affiliation::ALL
+            .into_iter()
+            .find(|affiliation| affiliation.as_str() == atom)
+            .ok_or(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unknown affiliation {atom}"),
+            ))
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = std::io::Error;
+
+    /// Parses a battle dimension by its atom, e.g. `G`
+    fn from_str(atom: &str) -> Result<Self, Self::Err> {
+        Dimension::ALL
+            .into_iter()
+            .find(|dimension| dimension.as_str() == atom)
+            .ok_or(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unknown dimension {atom}"),
+            ))
+    }
+}
+
+/// Whether an atom is non-empty and alphanumeric
+fn is_valid_atom(atom: &str) -> bool {
+    !atom.is_empty() && atom.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Describes a COT type
+    fn describe(cot_type: &str) -> Option<String> {
+        cot_type.parse::<CotType>().unwrap().describe()
+    }
+
+    #[test]
+    fn describe_known_types() {
+        assert_eq!(
+            describe("a-f-G-E-V-A-T").as_deref(),
+            Some("Friendly Ground Equipment Vehicle Armored Tank")
+        );
+        assert_eq!(
+            describe("a-h-G-U-C-I").as_deref(),
+            Some("Hostile Ground Unit Combat Infantry")
+        );
+        assert_eq!(
+            describe("b-m-p-s-p-i").as_deref(),
+            Some("Map Point Sensor Point of Interest")
+        );
+        assert_eq!(
+            describe("b-m-p-s-m").as_deref(),
+            Some("Map Point Spot Map Marker")
+        );
+        assert_eq!(describe("t-x-c-t").as_deref(), Some("Ping"));
+    }
+
+    #[test]
+    fn describe_nearest_ancestor() {
+        assert_eq!(
+            describe("a-f-G-E-V-A-T-x").as_deref(),
+            Some("Friendly Ground Equipment Vehicle Armored Tank")
+        );
+        assert_eq!(
+            describe("a-u-G-U-C-I-Q-9").as_deref(),
+            Some("Unknown Ground Unit Combat Infantry")
+        );
+        assert_eq!(describe("b-m-p-x").as_deref(), Some("Map Point"));
+        // Atom types without a known dimension are described by their affiliation
+        assert_eq!(describe("a-n").as_deref(), Some("Neutral"));
+    }
+
+    #[test]
+    fn describe_unknown_root() {
+        assert_eq!(describe("y-x-z"), None);
+        assert_eq!(describe("q"), None);
+    }
+
+    #[test]
+    fn descriptions_sorted_and_unique() {
+        assert!(
+            COT_TYPE_DESCRIPTIONS
+                .windows(2)
+                .all(|pair| pair[0].0 < pair[1].0)
+        );
+    }
+
+    #[test]
+    fn parse_atom_types() {
+        let cot_type: CotType = "a-f-G-U-C".parse().unwrap();
+        assert_eq!(cot_type.affiliation(), Some(Affiliation::Friend));
+        assert_eq!(cot_type.dimension(), Some(Dimension::Ground));
+        assert_eq!(cot_type.function_id().as_deref(), Some("U-C"));
+
+        let ping: CotType = "t-x-c-t".parse().unwrap();
+        assert!(!ping.is_atom());
+        assert_eq!(ping.affiliation(), None);
+        assert_eq!(ping.function_id(), None);
+    }
+
+    #[test]
+    fn reject_invalid_types() {
+        for cot_type in [
+            "a-f-G-U-C-",
+            "",
+            "a--G",
+            "a-f-G-U C",
+            "a-q-G",
+            "a-f-Q",
+            "a-F-G",
+        ] {
+            assert!(cot_type.parse::<CotType>().is_err(), "{cot_type}");
+        }
+    }
+
+    #[test]
+    fn match_wildcards() {
+        let hostile: CotType = "a-h-G-U-C".parse().unwrap();
+        assert!(hostile.matches("a-h-*"));
+        assert!(hostile.matches("a-*-G-U-C"));
+        assert!(!hostile.matches("a-f-*"));
+        assert!(!hostile.matches("a-.-G"));
+        assert!(!hostile.matches("a-h-G-U-C-I"));
+        assert!("a-h".parse::<CotType>().unwrap().matches("a-h-*"));
+
+        let ground: CotType = "a-n-G".parse().unwrap();
+        assert!(ground.matches("a-.-G"));
+        assert!(!ground.matches("a-.-A"));
+        assert!(!"b-m-p-s-p-i".parse::<CotType>().unwrap().matches("a-.-G"));
+    }
+
+    #[test]
+    fn replace_affiliation() {
+        let cot_type: CotType = "a-f-G-U-C".parse().unwrap();
+        assert_eq!(
+            cot_type.with_affiliation(Affiliation::Hostile).as_str(),
+            "a-h-G-U-C"
+        );
+        let root: CotType = "a".parse().unwrap();
+        assert_eq!(root.with_affiliation(Affiliation::Suspect).as_str(), "a-s");
+
+        // Types which are not atom types are unchanged
+        let point: CotType = "b-m-p-s-p-i".parse().unwrap();
+        assert_eq!(
+            point.with_affiliation(Affiliation::Hostile).as_str(),
+            "b-m-p-s-p-i"
+        );
+    }
+
+    #[test]
+    fn build_atom_types() {
+        let cot_type = CotType::atom(Affiliation::Hostile, Dimension::Ground)
+            .function("U-C-I")
+            .unwrap();
+        assert_eq!(cot_type.as_str(), "a-h-G-U-C-I");
+        assert_eq!(cot_type, "a-h-G-U-C-I".parse::<CotType>().unwrap());
+
+        // Empty and invalid function codes are rejected rather than dropped
+        for function in ["M--Q", "M-F?-Q", "", "M-"] {
+            let atom = CotType::atom(Affiliation::Friend, Dimension::Air);
+            assert!(atom.function(function).is_err(), "{function}");
+        }
+    }
+
+    #[test]
+    fn affiliation_and_dimension_round_trip() {
+        for affiliation in Affiliation::ALL {
+            assert_eq!(
+                affiliation.as_str().parse::<Affiliation>().unwrap(),
+                affiliation
+            );
+        }
+        for dimension in Dimension::ALL {
+            assert_eq!(dimension.as_str().parse::<Dimension>().unwrap(), dimension);
+        }
+        assert!("z".parse::<Affiliation>().is_err());
+        assert!("g".parse::<Dimension>().is_err());
+    }
+}
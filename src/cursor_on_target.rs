@@ -3,13 +3,29 @@
 
 //! This module provides a Cursor on Target (COT) message structure and related types.;
 
-use crate::{CotSender, PublishError};
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    CotSender, CotType, DeliveryPath, DetailConflict, Group, PublishError, Sidc, Status, Takv,
+    TeamColor, TeamRole, Track,
+};
+
+/// Type alias for the receiver of the delivery result of a checked publish
+type DeliveryReceiver = tokio::sync::oneshot::Receiver<Result<DeliveryPath, PublishError>>;
 
 /// Cursor on Target (COT) message structure and related types
 #[derive(Debug, Default)]
 pub struct CursorOnTarget {
-    /// Time in milliseconds after which this COT message should be considered stale
+    /// Time in milliseconds after which this COT message should be considered stale, counted
+    /// from the start time. Ignored when an explicit stale time is set
     pub stale_time_ms: u64,
+    /// Optional time the event was generated, the time of encoding when `None`
+    pub time: Option<SystemTime>,
+    /// Optional time the event starts being valid, the event time when `None`
+    pub start: Option<SystemTime>,
+    /// Optional time the event stops being valid, the start time plus `stale_time_ms` when
+    /// `None`
+    pub stale: Option<SystemTime>,
     /// Unique identifier for this COT entity
     pub uid: String,
     /// Contact information including callsign and endpoint
@@ -22,6 +38,17 @@ pub struct CursorOnTarget {
     pub position: Option<Position>,
     /// Precision location metadata including source information
     pub precision_location: Option<PrecisionLocation>,
+    /// Team colour and role of the entity
+    pub group: Option<Group>,
+    /// Speed and course of the entity
+    pub track: Option<Track>,
+    /// Battery status of the device
+    pub status: Option<Status>,
+    /// Software and device publishing the entity
+    pub takv: Option<Takv>,
+    /// Optional 2525 symbol sent as the `__milsym` detail, keeping the parts of the SIDC which
+    /// the COT type cannot carry
+    pub milsym: Option<Sidc>,
     /// How this COT was generated (e.g., "m-g" for machine-generated)
     pub how: String,
     /// Access restrictions for this COT message
@@ -69,16 +96,41 @@ pub struct PrecisionLocation {
     pub geopointsrc: String,
 }
 
+/// Element of the detail block of a COT message, such as `<remarks>` or `<link>`
+///
+/// Attribute values and text are escaped when the element is serialised, so they may contain
+/// any characters. Names are written as they are and must be valid XML names.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DetailElement {
+    /// Name of the element (e.g., "remarks")
+    pub name: String,
+    /// Attribute names and values in the order they are written
+    pub attributes: Vec<(String, String)>,
+    /// Text content, written before the children. Parsed elements have either text or
+    /// children
+    pub text: String,
+    /// Child elements
+    pub children: Vec<DetailElement>,
+}
+
 impl Clone for CursorOnTarget {
     fn clone(&self) -> Self {
         Self {
             stale_time_ms: self.stale_time_ms,
+            time: self.time,
+            start: self.start,
+            stale: self.stale,
             uid: self.uid.clone(),
             contact: self.contact.clone(),
             r#type: self.r#type.clone(),
             xml_detail: self.xml_detail.clone(),
             position: self.position.clone(),
             precision_location: self.precision_location.clone(),
+            group: self.group,
+            track: self.track,
+            status: self.status,
+            takv: self.takv.clone(),
+            milsym: self.milsym.clone(),
             how: self.how.clone(),
             access: self.access.clone(),
             qos: self.qos.clone(),
@@ -131,7 +183,7 @@ impl CursorOnTarget {
     ///
     /// # Errors
     ///
-    /// Returns an `std::io::Error` if:
+    /// Returns a [`PublishError`] if:
     /// - The publish sender has not been configured
     /// - The mpsc channel fails to send (all receivers dropped)
     /// - The mpsc channel is full (should not happen with sufficient buffer size)
@@ -153,7 +205,7 @@ impl CursorOnTarget {
     ///
     /// # Errors
     ///
-    /// Returns an `std::io::Error` if:
+    /// Returns a [`PublishError`] if:
     /// - The publish sender has not been configured
     /// - The mpsc channel fails to send (all receivers dropped)
     /// - The mpsc channel is full (should not happen with sufficient buffer size)
@@ -170,43 +222,117 @@ impl CursorOnTarget {
     /// Publishes this COT message to all subscribers via the mpsc channel
     /// and waits for confirmation of the COT being sent
     ///
+    /// The confirmation is given once the message has been sent, for TAK servers once it has
+    /// been written and flushed to the connection, or answered by the server when
+    /// `confirm_delivery` is enabled in the [`crate::TakServerSetting`].
+    ///
+    /// Returns the transport the message was delivered through, which differs from the
+    /// publisher's primary transport when a TAK server publisher used its multicast fallback.
+    ///
     /// # Errors
     ///
-    /// Returns an `std::io::Error` if:
+    /// Returns a [`PublishError`] if:
     /// - The publish sender has not been configured
     /// - The mpsc channel fails to send (all receivers dropped)
     /// - The mpsc channel is full (should not happen with sufficient buffer size)
+    /// - The message could not be delivered
     ///
-    pub async fn publish_checked(&self) -> Result<(), PublishError> {
-        let (response_sender, response_receiver) =
-            tokio::sync::oneshot::channel::<Result<(), PublishError>>();
-
-        self.publish_sender
-            .as_ref()
-            .ok_or(PublishError::SendError("Publish sender is not set".into()))?
-            .send((self.clone(), Some(response_sender)))
-            .await
-            .map_err(|e| PublishError::SendError(e.to_string()))?;
+    pub async fn publish_checked(&self) -> Result<DeliveryPath, PublishError> {
+        self.send_checked().await?.await.map_err(dropped_error)?
+    }
 
-        response_receiver
+    /// Publishes this COT message to all subscribers via the mpsc channel
+    /// and waits up to the timeout for confirmation of the COT being sent
+    ///
+    /// See [`publish_checked`](Self::publish_checked) for when the confirmation is given. The
+    /// timeout includes the time the message is queued, such as while a TAK server publisher
+    /// is reconnecting.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum time to wait for the confirmation
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PublishError::DeliveryTimeout`] when the message is not confirmed in time, or
+    /// the errors of [`publish_checked`](Self::publish_checked).
+    ///
+    pub async fn publish_checked_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<DeliveryPath, PublishError> {
+        tokio::time::timeout(timeout, self.publish_checked())
             .await
-            .map_err(|e| PublishError::SendError(e.to_string()))?
+            .map_err(|_| PublishError::DeliveryTimeout(timeout))?
     }
 
     /// Publishes this COT message to all subscribers via the mpsc channel
     /// and waits for confirmation of the COT being sent
     ///
+    /// See [`publish_checked`](Self::publish_checked) for when the confirmation is given.
+    ///
+    /// Returns the transport the message was delivered through, which differs from the
+    /// publisher's primary transport when a TAK server publisher used its multicast fallback.
+    ///
     /// # Errors
     ///
-    /// Returns an `std::io::Error` if:
+    /// Returns a [`PublishError`] if:
     /// - The publish sender has not been configured
     /// - The mpsc channel fails to send (all receivers dropped)
     /// - The mpsc channel is full (should not happen with sufficient buffer size)
+    /// - The message could not be delivered
+    ///
+    #[cfg(feature = "blocking")]
+    pub fn blocking_publish_checked(&self) -> Result<DeliveryPath, PublishError> {
+        self.blocking_send_checked()?
+            .blocking_recv()
+            .map_err(dropped_error)?
+    }
+
+    /// Publishes this COT message to all subscribers via the mpsc channel
+    /// and waits up to the timeout for confirmation of the COT being sent
+    ///
+    /// See [`publish_checked_timeout`](Self::publish_checked_timeout). The timeout includes
+    /// the time spent waiting for space in the channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum time to wait for the confirmation
     ///
     #[cfg(feature = "blocking")]
-    pub fn blocking_publish_checked(&self) -> Result<(), PublishError> {
+    pub fn blocking_publish_checked_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<DeliveryPath, PublishError> {
+        // The timeout covers waiting for space in the channel as well as for the confirmation
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .map_err(|e| PublishError::SendError(format!("Failed to create Tokio runtime: {e}")))?
+            .block_on(self.publish_checked_timeout(timeout))
+    }
+
+    /// Sends a copy of this COT message with a oneshot sender for the delivery result
+    async fn send_checked(&self) -> Result<DeliveryReceiver, PublishError> {
+        let (response_sender, response_receiver) =
+            tokio::sync::oneshot::channel::<Result<DeliveryPath, PublishError>>();
+
+        self.publish_sender
+            .as_ref()
+            .ok_or(PublishError::SendError("Publish sender is not set".into()))?
+            .send((self.clone(), Some(response_sender)))
+            .await
+            .map_err(|e| PublishError::SendError(e.to_string()))?;
+
+        Ok(response_receiver)
+    }
+
+    /// Sends a copy of this COT message with a oneshot sender for the delivery result, blocking
+    /// while the channel is full
+    #[cfg(feature = "blocking")]
+    fn blocking_send_checked(&self) -> Result<DeliveryReceiver, PublishError> {
         let (response_sender, response_receiver) =
-            tokio::sync::oneshot::channel::<Result<(), PublishError>>();
+            tokio::sync::oneshot::channel::<Result<DeliveryPath, PublishError>>();
 
         self.publish_sender
             .as_ref()
@@ -214,9 +340,7 @@ impl CursorOnTarget {
             .blocking_send((self.clone(), Some(response_sender)))
             .map_err(|e| PublishError::SendError(e.to_string()))?;
 
-        response_receiver
-            .blocking_recv()
-            .map_err(|e| PublishError::SendError(e.to_string()))?
+        Ok(response_receiver)
     }
 
     /// Sets or clears the contact information for this COT entity
@@ -252,6 +376,42 @@ impl CursorOnTarget {
         self.xml_detail = xml_detail.map(|v| v.into());
     }
 
+    /// Parses the XML detail block of this COT message into its elements
+    ///
+    /// Returns no elements when the detail block is not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if the detail block is not well-formed XML
+    ///
+    pub fn detail(&self) -> Result<Vec<DetailElement>, std::io::Error> {
+        self.xml_detail
+            .as_deref()
+            .map_or(Ok(Vec::new()), DetailElement::parse)
+    }
+
+    /// Finds the elements set both as a typed field, such as with
+    /// [`set_contact`](Self::set_contact), and in the XML detail block
+    ///
+    /// Receivers ignore the typed field of such an element, so it is not sent and the element
+    /// of the XML detail is used instead.
+    ///
+    pub fn detail_conflicts(&self) -> Vec<DetailConflict> {
+        crate::detail::detail_conflicts(self)
+    }
+
+    /// Sets or clears the XML detail block of this COT message from its elements, escaping
+    /// attribute values and text
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - Detail elements, the detail block is cleared when empty
+    ///
+    pub fn set_detail(&mut self, elements: &[DetailElement]) {
+        self.xml_detail =
+            (!elements.is_empty()).then(|| elements.iter().map(DetailElement::to_xml).collect());
+    }
+
     /// Sets the basic position (latitude and longitude) for this COT entity
     ///
     /// If a position already exists, only the latitude and longitude are updated.
@@ -320,6 +480,195 @@ impl CursorOnTarget {
         });
     }
 
+    /// Sets or clears the team colour and role of this COT entity
+    ///
+    /// If both `name` and `role` are `None`, the group is cleared. A missing colour defaults
+    /// to Cyan and a missing role to Team Member, as in ATAK.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Optional team colour
+    /// * `role` - Optional role within the team
+    ///
+    pub fn set_group(&mut self, name: Option<TeamColor>, role: Option<TeamRole>) {
+        if name.is_none() && role.is_none() {
+            self.group = None;
+            return;
+        }
+
+        self.group = Some(Group {
+            name: name.unwrap_or_default(),
+            role: role.unwrap_or_default(),
+        });
+    }
+
+    /// Sets the speed and course of this COT entity
+    ///
+    /// A speed or course which is not finite, such as NaN, leaves the track out of the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `speed` - Speed in meters per second
+    /// * `course` - Course in degrees clockwise from true north
+    ///
+    pub fn set_track(&mut self, speed: f64, course: f64) {
+        self.track = Some(Track::new(speed, course));
+    }
+
+    /// Sets the speed in knots and course of this COT entity
+    ///
+    /// # Arguments
+    ///
+    /// * `knots` - Speed in knots
+    /// * `course` - Course in degrees clockwise from true north
+    ///
+    pub fn set_track_knots(&mut self, knots: f64, course: f64) {
+        self.track = Some(Track::from_knots(knots, course));
+    }
+
+    /// Sets the speed in kilometers per hour and course of this COT entity
+    ///
+    /// # Arguments
+    ///
+    /// * `kmh` - Speed in kilometers per hour
+    /// * `course` - Course in degrees clockwise from true north
+    ///
+    pub fn set_track_kmh(&mut self, kmh: f64, course: f64) {
+        self.track = Some(Track::from_kmh(kmh, course));
+    }
+
+    /// Sets the speed in miles per hour and course of this COT entity
+    ///
+    /// # Arguments
+    ///
+    /// * `mph` - Speed in miles per hour
+    /// * `course` - Course in degrees clockwise from true north
+    ///
+    pub fn set_track_mph(&mut self, mph: f64, course: f64) {
+        self.track = Some(Track::from_mph(mph, course));
+    }
+
+    /// Sets or clears the battery level of this COT entity
+    ///
+    /// # Arguments
+    ///
+    /// * `battery` - Optional battery level in percent, capped at 100
+    ///
+    pub fn set_battery(&mut self, battery: Option<u32>) {
+        self.status = battery.map(|battery| Status {
+            battery: battery.min(100),
+        });
+    }
+
+    /// Sets the software and device publishing this COT entity
+    ///
+    /// Missing values default to those of [`Takv::default`]: this crate's name and version,
+    /// running on the host operating system and architecture.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Optional device model
+    /// * `platform` - Optional name of the publishing software
+    /// * `os` - Optional operating system
+    /// * `version` - Optional version of the publishing software
+    ///
+    pub fn set_takv(
+        &mut self,
+        device: Option<&str>,
+        platform: Option<&str>,
+        os: Option<&str>,
+        version: Option<&str>,
+    ) {
+        let defaults = Takv::default();
+        self.takv = Some(Takv {
+            device: device.map_or(defaults.device, Into::into),
+            platform: platform.map_or(defaults.platform, Into::into),
+            os: os.map_or(defaults.os, Into::into),
+            version: version.map_or(defaults.version, Into::into),
+        });
+    }
+
+    /// Predicts the position of this COT entity from its position and track
+    ///
+    /// Returns `None` when the position or track is not set.
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - Time since the position fix
+    ///
+    pub fn dead_reckoned_position(&self, elapsed: Duration) -> Option<Position> {
+        Some(self.track?.dead_reckon(self.position.as_ref()?, elapsed))
+    }
+
+    /// Sets or clears the explicit time, start and stale of this COT message, such as for
+    /// replaying recorded data or scheduling future events
+    ///
+    /// Times which are `None` are set when the message is encoded: the time to the current
+    /// time, the start to the time, and the stale to the start plus `stale_time_ms`.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Optional time the event was generated
+    /// * `start` - Optional time the event starts being valid
+    /// * `stale` - Optional time the event stops being valid
+    ///
+    pub fn set_times(
+        &mut self,
+        time: Option<SystemTime>,
+        start: Option<SystemTime>,
+        stale: Option<SystemTime>,
+    ) {
+        self.time = time;
+        self.start = start;
+        self.stale = stale;
+    }
+
+    /// Parses the COT type of this entity
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if the type is malformed, such as `a-f-G-U-C-`
+    ///
+    pub fn cot_type(&self) -> Result<CotType, std::io::Error> {
+        self.r#type.parse()
+    }
+
+    /// Sets the COT type of this entity
+    ///
+    /// # Arguments
+    ///
+    /// * `cot_type` - COT type, e.g. `CotType::atom(Affiliation::Hostile, Dimension::Ground)`
+    ///
+    pub fn set_cot_type(&mut self, cot_type: &CotType) {
+        self.r#type = cot_type.to_string();
+    }
+
+    /// Human readable description of the COT type of this entity, see [`CotType::describe`]
+    ///
+    /// Returns `None` when the type is malformed or neither it nor an ancestor is known.
+    ///
+    pub fn describe(&self) -> Option<String> {
+        self.cot_type().ok()?.describe()
+    }
+
+    /// Sets the COT type of this entity from a 2525 symbol identification code
+    ///
+    /// # Arguments
+    ///
+    /// * `sidc` - Symbol identification code of a warfighting symbol, e.g. `SFGPUCI--------`
+    /// * `full_fidelity` - Whether to also send the SIDC as the `__milsym` detail, keeping the
+    ///   status, modifiers and country of the symbol which the COT type cannot carry
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if the SIDC has no COT type equivalent
+    ///
+    pub fn set_sidc(&mut self, sidc: &Sidc, full_fidelity: bool) -> Result<(), std::io::Error> {
+        self.set_cot_type(&sidc.to_cot_type()?);
+        self.milsym = full_fidelity.then(|| sidc.clone());
+        Ok(())
+    }
+
     /// Sets the unique identifier (UID) for this COT entity
     /// 
     /// # Arguments
@@ -330,3 +679,388 @@ impl CursorOnTarget {
         self.uid = uid.into();
     }
 }
+
+impl DetailElement {
+    /// Creates an element without attributes, text or children
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the element (e.g., "remarks")
+    ///
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets an attribute of the element (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the attribute
+    /// * `value` - Value of the attribute, escaped when serialised
+    ///
+    pub fn with_attribute(mut self, name: &str, value: impl ToString) -> Self {
+        self.set_attribute(name, value);
+        self
+    }
+
+    /// Sets the text content of the element (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Text content, escaped when serialised
+    ///
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    /// Appends a child element (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `child` - Child element
+    ///
+    pub fn with_child(mut self, child: DetailElement) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Value of an attribute of the element
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the attribute
+    ///
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets an attribute of the element, replacing the value of an existing attribute
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the attribute
+    /// * `value` - Value of the attribute, escaped when serialised
+    ///
+    pub fn set_attribute(&mut self, name: &str, value: impl ToString) {
+        let value = value.to_string();
+        match self
+            .attributes
+            .iter_mut()
+            .find(|(attribute, _)| attribute == name)
+        {
+            Some((_, existing)) => *existing = value,
+            None => self.attributes.push((name.into(), value)),
+        }
+    }
+
+    /// Removes an attribute of the element, returning its value
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the attribute
+    ///
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        let index = self
+            .attributes
+            .iter()
+            .position(|(attribute, _)| attribute == name)?;
+        Some(self.attributes.remove(index).1)
+    }
+
+    /// First child element with the given name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the child element
+    ///
+    pub fn child(&self, name: &str) -> Option<&DetailElement> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// First child element with the given name, for modification
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the child element
+    ///
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut DetailElement> {
+        self.children.iter_mut().find(|child| child.name == name)
+    }
+
+    /// Serialises the element to XML, escaping attribute values and text
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        self.write_xml(&mut xml);
+        xml
+    }
+
+    /// Parses the elements of an XML detail block
+    ///
+    /// Comments, processing instructions and whitespace between elements are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `xml` - XML detail block, without the enclosing `<detail>` element
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if the XML is not well-formed, or an element has both text
+    /// and child elements (mixed content), which cannot be written back in its original order
+    ///
+    pub fn parse(xml: &str) -> Result<Vec<DetailElement>, std::io::Error> {
+        use quick_xml::events::Event;
+
+        let invalid = |e: String| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid XML detail: {e}"),
+            )
+        };
+
+        let mut reader = quick_xml::Reader::from_str(xml);
+        let mut open: Vec<DetailElement> = Vec::new();
+        let mut elements = Vec::new();
+
+        loop {
+            let (element, text) = match reader.read_event() {
+                Ok(Event::Start(start)) => {
+                    open.push(element_from_start(&start).map_err(invalid)?);
+                    continue;
+                }
+                Ok(Event::Empty(start)) => {
+                    (Some(element_from_start(&start).map_err(invalid)?), None)
+                }
+                Ok(Event::End(_)) => (open.pop(), None),
+                Ok(Event::Text(text)) => {
+                    let text = text.unescape().map_err(|e| invalid(e.to_string()))?;
+                    (None, Some(text.into_owned()))
+                }
+                Ok(Event::CData(cdata)) => {
+                    let text = cdata.decode().map_err(|e| invalid(e.to_string()))?;
+                    (None, Some(text.into_owned()))
+                }
+                Ok(Event::Eof) => match open.last() {
+                    Some(element) => {
+                        return Err(invalid(format!("unclosed element {}", element.name)));
+                    }
+                    None => return Ok(elements),
+                },
+                Ok(_) => continue,
+                Err(e) => return Err(invalid(e.to_string())),
+            };
+
+            // Text and children of the same element would be reordered when serialised
+            let mixed = |parent: &DetailElement| {
+                invalid(format!(
+                    "element {} has both text and child elements",
+                    parent.name
+                ))
+            };
+            if let Some(element) = element {
+                match open.last_mut() {
+                    Some(parent) if !parent.text.is_empty() => return Err(mixed(parent)),
+                    Some(parent) => parent.children.push(element),
+                    None => elements.push(element),
+                }
+            }
+            if let Some(text) = text.filter(|text| !text.trim().is_empty()) {
+                match open.last_mut() {
+                    Some(parent) if !parent.children.is_empty() => return Err(mixed(parent)),
+                    Some(parent) => parent.text.push_str(&text),
+                    None => {
+                        return Err(invalid(format!(
+                            "text outside of an element: {}",
+                            text.trim()
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes the element and its children as XML
+    fn write_xml(&self, xml: &mut String) {
+        use quick_xml::escape::escape;
+
+        xml.push('<');
+        xml.push_str(&self.name);
+        for (name, value) in &self.attributes {
+            xml.push_str(&format!(" {name}=\"{}\"", escape(value.as_str())));
+        }
+
+        if self.text.is_empty() && self.children.is_empty() {
+            xml.push_str("/>");
+            return;
+        }
+
+        xml.push('>');
+        xml.push_str(&escape(self.text.as_str()));
+        for child in &self.children {
+            child.write_xml(xml);
+        }
+        xml.push_str(&format!("</{}>", self.name));
+    }
+}
+
+impl std::fmt::Display for DetailElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_xml())
+    }
+}
+
+/// Creates a detail element from the start tag of an XML element
+fn element_from_start(start: &quick_xml::events::BytesStart) -> Result<DetailElement, String> {
+    let mut element = DetailElement::new(&String::from_utf8_lossy(start.name().as_ref()));
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| e.to_string())?;
+        let value = attribute.unescape_value().map_err(|e| e.to_string())?;
+        element.attributes.push((
+            String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+            value.into_owned(),
+        ));
+    }
+    Ok(element)
+}
+
+/// Error returned when the publish task dropped a message without reporting its delivery, such
+/// as when the publisher is dropped or the connection is replaced while the message is queued
+fn dropped_error(_: tokio::sync::oneshot::error::RecvError) -> PublishError {
+    PublishError::SendError("COT message was dropped before it was delivered".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Characters escaped in attribute values and text
+    const SPECIAL: &str = "Fish & <Chips> \"to go\" isn't 'cheap'";
+
+    #[test]
+    fn escaped_round_trip() {
+        let element = DetailElement::new("remarks")
+            .with_attribute("source", SPECIAL)
+            .with_text(SPECIAL);
+        let xml = element.to_xml();
+        assert_eq!(
+            xml,
+            "<remarks source=\"Fish &amp; &lt;Chips&gt; &quot;to go&quot; isn&apos;t &apos;cheap&apos;\">\
+             Fish &amp; &lt;Chips&gt; &quot;to go&quot; isn&apos;t &apos;cheap&apos;</remarks>"
+        );
+        assert_eq!(DetailElement::parse(&xml).unwrap(), vec![element]);
+    }
+
+    #[test]
+    fn nested_round_trip() {
+        let elements = vec![
+            DetailElement::new("link")
+                .with_attribute("uid", "ANDROID-1")
+                .with_attribute("relation", "p-p"),
+            DetailElement::new("__chat")
+                .with_attribute("chatroom", "All Chat Rooms")
+                .with_child(
+                    DetailElement::new("chatgrp")
+                        .with_attribute("uid0", "ANDROID-1")
+                        .with_child(DetailElement::new("member").with_text("<alpha>")),
+                )
+                .with_child(DetailElement::new("hierarchy")),
+            DetailElement::new("archive"),
+        ];
+        let xml: String = elements.iter().map(DetailElement::to_xml).collect();
+        assert_eq!(
+            xml,
+            "<link uid=\"ANDROID-1\" relation=\"p-p\"/>\
+             <__chat chatroom=\"All Chat Rooms\"><chatgrp uid0=\"ANDROID-1\">\
+             <member>&lt;alpha&gt;</member></chatgrp><hierarchy/></__chat><archive/>"
+        );
+        assert_eq!(DetailElement::parse(&xml).unwrap(), elements);
+    }
+
+    #[test]
+    fn parse_skips_comments_and_whitespace() {
+        let elements = DetailElement::parse(
+            "<?xml-stylesheet href=\"a\"?>\n  <!-- comment -->\n  <a>\n    <b/>\n  </a>\n",
+        )
+        .unwrap();
+        assert_eq!(
+            elements,
+            vec![DetailElement::new("a").with_child(DetailElement::new("b"))]
+        );
+        assert_eq!(DetailElement::parse("").unwrap(), Vec::new());
+        assert_eq!(
+            DetailElement::parse("<remarks><![CDATA[a < b]]></remarks>").unwrap(),
+            vec![DetailElement::new("remarks").with_text("a < b")]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_mixed_content() {
+        for xml in [
+            "<remarks>Contact <b>east</b> of hill</remarks>",
+            "<remarks>Contact <b>east</b></remarks>",
+            "<remarks><b>east</b> of hill</remarks>",
+            "<a><b>Contact <i>east</i></b></a>",
+        ] {
+            let error = DetailElement::parse(xml).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{xml}");
+        }
+
+        // Whitespace between child elements is not text
+        assert_eq!(
+            DetailElement::parse("<a>\n  <b>east</b>\n</a>").unwrap(),
+            vec![DetailElement::new("a").with_child(DetailElement::new("b").with_text("east"))]
+        );
+    }
+
+    #[test]
+    fn parse_malformed() {
+        for xml in [
+            "<remarks>",
+            "<a><b></a></b>",
+            "<a></b>",
+            "</a>",
+            "text",
+            "<a/>text",
+            "<a x=\"1\" x=\"2\"/>",
+            "<a x=1/>",
+            "<a>&bogus;</a>",
+            "<a x=\"&bogus;\"/>",
+        ] {
+            let error = DetailElement::parse(xml).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{xml}");
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn blocking_publish_checked_timeout_includes_a_full_channel() {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let cot = CursorOnTarget::default().with_sender(sender);
+        cot.blocking_publish().unwrap();
+
+        let timeout = Duration::from_millis(200);
+        let started = std::time::Instant::now();
+        let error = cot.blocking_publish_checked_timeout(timeout).unwrap_err();
+        assert!(
+            matches!(error, PublishError::DeliveryTimeout(t) if t == timeout),
+            "{error}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Once there is space the message is confirmed by the task receiving it
+        receiver.try_recv().unwrap();
+        let task = std::thread::spawn(move || {
+            let (_, response_sender) = receiver.blocking_recv().unwrap();
+            response_sender.unwrap().send(Ok(DeliveryPath::Udp)).ok();
+        });
+        let path = cot.blocking_publish_checked_timeout(Duration::from_secs(10));
+        assert_eq!(path.unwrap(), DeliveryPath::Udp);
+        task.join().unwrap();
+    }
+}
@@ -0,0 +1,420 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module imports TAK server connections from ATAK connection data packages.
+//!
+//! A data package is a zip holding a `MANIFEST/manifest.xml`, a `.pref` preference file with
+//! the `cot_streams` connection entries and the `.p12` client certificate and truststore files
+//! the preferences refer to.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use quick_xml::events::Event;
+use url::Url;
+
+use crate::connection::TakServerSetting;
+use crate::destination::Destination;
+use crate::keys::{Credentials, Source};
+
+/// Path of the manifest inside a data package
+const MANIFEST_PATH: &str = "MANIFEST/manifest.xml";
+/// Password ATAK assumes for certificate files when the preferences don't specify one
+const DEFAULT_CERTIFICATE_PASSWORD: &str = "atakatak";
+/// Largest uncompressed entry read from a data package
+const MAX_ENTRY_SIZE: u64 = 8 * 1024 * 1024;
+/// Largest uncompressed size of all entries read from a data package
+const MAX_PACKAGE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// TAK server connection imported from a data package
+pub struct DataPackageServer {
+    /// Description of the connection
+    pub description: String,
+    /// Whether the connection is enabled in the preferences
+    pub enabled: bool,
+    /// URL of the TAK server, using the protocol of the connect string as the scheme
+    pub url: Url,
+    /// Connection settings including the credentials from the package
+    pub settings: TakServerSetting<'static>,
+}
+
+/// Loads the TAK server connections from a data package file
+///
+/// # Arguments
+///
+/// * `path` - Path to the data package zip
+///
+pub fn load_data_package(path: &str) -> Result<Vec<DataPackageServer>, std::io::Error> {
+    let file = std::fs::File::open(path).map_err(|e| {
+        std::io::Error::new(e.kind(), format!("Failed to open data package {path}: {e}"))
+    })?;
+    load_data_package_from_reader(file)
+}
+
+/// Loads the TAK server connections from a data package
+///
+/// Entries are read into memory, so packages with an entry larger than 8 MiB or more than
+/// 32 MiB of entries in total are rejected.
+///
+/// # Arguments
+///
+/// * `reader` - Reader over the data package zip
+///
+pub fn load_data_package_from_reader<R: Read + Seek>(
+    reader: R,
+) -> Result<Vec<DataPackageServer>, std::io::Error> {
+    let mut archive = zip::ZipArchive::new(reader)
+        .map_err(|e| std::io::Error::other(format!("Invalid data package: {e}")))?;
+
+    let mut files = HashMap::new();
+    let mut package_size = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| std::io::Error::other(format!("Invalid data package entry: {e}")))?;
+        if entry.is_dir() {
+            continue;
+        }
+
+        // The size in the zip header is not trusted, reading stops past the limit
+        let name = entry.name().to_owned();
+        let mut content = Vec::new();
+        (&mut entry)
+            .take(MAX_ENTRY_SIZE + 1)
+            .read_to_end(&mut content)?;
+        package_size += content.len() as u64;
+        if content.len() as u64 > MAX_ENTRY_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Data package entry {name} is larger than {MAX_ENTRY_SIZE} bytes"),
+            ));
+        }
+        if package_size > MAX_PACKAGE_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Data package entries are larger than {MAX_PACKAGE_SIZE} bytes in total"),
+            ));
+        }
+        files.insert(name, content);
+    }
+
+    let preference_files: Vec<String> = match files.get(MANIFEST_PATH) {
+        Some(manifest) => parse_manifest(&String::from_utf8_lossy(manifest))?,
+        None => files.keys().cloned().collect(),
+    }
+    .into_iter()
+    .filter(|name| name.ends_with(".pref"))
+    .collect();
+
+    let mut preferences = HashMap::new();
+    for name in &preference_files {
+        let content = find_file(&files, name)?;
+        preferences.extend(parse_preferences(&String::from_utf8_lossy(content))?);
+    }
+
+    let count = match preferences.get("count") {
+        Some(count) => count
+            .parse::<usize>()
+            .map_err(|e| std::io::Error::other(format!("Invalid connection count: {e}")))?,
+        None => (0..)
+            .take_while(|index| preferences.contains_key(&format!("connectString{index}")))
+            .count(),
+    };
+    if count == 0 {
+        return Err(std::io::Error::other(
+            "Data package does not contain any TAK server connections",
+        ));
+    }
+
+    (0..count)
+        .map(|index| load_server(&files, &preferences, index))
+        .collect()
+}
+
+/// Builds the connection at `index` of the `cot_streams` preferences
+fn load_server(
+    files: &HashMap<String, Vec<u8>>,
+    preferences: &HashMap<String, String>,
+    index: usize,
+) -> Result<DataPackageServer, std::io::Error> {
+    // Per connection entries take precedence over the application wide ones
+    let preference = |key: &str| {
+        preferences
+            .get(&format!("{key}{index}"))
+            .or(preferences.get(key))
+            .map(|value| value.as_str())
+    };
+
+    let connect_string = preference("connectString").ok_or(std::io::Error::other(format!(
+        "Connection {index} is missing its connect string"
+    )))?;
+    let Destination::TakServer { url, tls } = Destination::from_connect_string(connect_string)?
+    else {
+        return Err(std::io::Error::other(format!(
+            "Connection {index} is not a TAK server connection: {connect_string}"
+        )));
+    };
+
+    let client_credentials = match preference("certificateLocation") {
+        Some(location) => {
+            let password = preference("clientPassword").unwrap_or(DEFAULT_CERTIFICATE_PASSWORD);
+            let credentials = Credentials::from_pkcs12(find_file(files, location)?, password)
+                .map_err(|e| {
+                    std::io::Error::other(format!(
+                        "Failed to load client certificate {location}: {e}"
+                    ))
+                })?;
+            Some(credentials)
+        }
+        None => None,
+    };
+
+    let root_cert = match preference("caLocation") {
+        Some(location) => {
+            let password = preference("caPassword").unwrap_or(DEFAULT_CERTIFICATE_PASSWORD);
+            let pem =
+                crate::keys::pkcs12_certificates_to_string(find_file(files, location)?, password)
+                    .map_err(|e| {
+                    std::io::Error::other(format!("Failed to load truststore {location}: {e}"))
+                })?;
+            Some(Source::String(pem))
+        }
+        None => None,
+    };
+
+    Ok(DataPackageServer {
+        description: preference("description")
+            .unwrap_or(connect_string)
+            .to_owned(),
+        enabled: preference("enabled") != Some("false"),
+        url,
+        settings: TakServerSetting {
+            tls,
+            client_credentials,
+            root_cert,
+            ..Default::default()
+        },
+    })
+}
+
+/// Finds a file referenced by the manifest or preferences, matching the full path first and
+/// falling back to the file name as ATAK stores absolute device paths in the preferences
+fn find_file<'a>(
+    files: &'a HashMap<String, Vec<u8>>,
+    location: &str,
+) -> Result<&'a Vec<u8>, std::io::Error> {
+    let file_name = |path: &str| path.rsplit(['/', '\\']).next().unwrap_or(path).to_owned();
+
+    files
+        .get(location)
+        .or_else(|| {
+            files
+                .iter()
+                .find(|(name, _)| file_name(name) == file_name(location))
+                .map(|(_, content)| content)
+        })
+        .ok_or(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Data package does not contain {location}"),
+        ))
+}
+
+/// Parses the zip entries listed in the manifest
+fn parse_manifest(manifest: &str) -> Result<Vec<String>, std::io::Error> {
+    let mut reader = quick_xml::Reader::from_str(manifest);
+    let mut entries = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element) | Event::Empty(element))
+                if element.local_name().as_ref() == b"Content" =>
+            {
+                if let Some(entry) = element
+                    .try_get_attribute("zipEntry")
+                    .map_err(|e| std::io::Error::other(format!("Invalid manifest: {e}")))?
+                {
+                    let entry = entry
+                        .unescape_value()
+                        .map_err(|e| std::io::Error::other(format!("Invalid manifest: {e}")))?;
+                    entries.push(entry.into_owned());
+                }
+            }
+            Ok(Event::Eof) => return Ok(entries),
+            Ok(_) => {}
+            Err(e) => return Err(std::io::Error::other(format!("Invalid manifest: {e}"))),
+        }
+    }
+}
+
+/// Parses the `<entry key="...">value</entry>` elements of an ATAK preference file
+fn parse_preferences(preferences: &str) -> Result<HashMap<String, String>, std::io::Error> {
+    let mut reader = quick_xml::Reader::from_str(preferences);
+    let mut entries = HashMap::new();
+    let mut key = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) if element.local_name().as_ref() == b"entry" => {
+                key = element
+                    .try_get_attribute("key")
+                    .map_err(|e| std::io::Error::other(format!("Invalid preferences: {e}")))?
+                    .map(|attribute| String::from_utf8_lossy(&attribute.value).into_owned());
+            }
+            Ok(Event::Text(text)) => {
+                if let Some(key) = key.take() {
+                    let value = text
+                        .unescape()
+                        .map_err(|e| std::io::Error::other(format!("Invalid preferences: {e}")))?;
+                    entries.insert(key, value.trim().to_owned());
+                }
+            }
+            Ok(Event::End(_)) => key = None,
+            Ok(Event::Eof) => return Ok(entries),
+            Ok(_) => {}
+            Err(e) => return Err(std::io::Error::other(format!("Invalid preferences: {e}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestCa;
+    use openssl::pkcs12::Pkcs12;
+    use openssl::stack::Stack;
+    use openssl::x509::X509;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    const MANIFEST: &str = r#"<MissionPackageManifest version="2">
+   <Configuration>
+      <Parameter name="uid" value="2d1f0c7e-5a55-4b8e-9a0f-6c1b7f3e9d21"/>
+      <Parameter name="name" value="takserver.zip"/>
+   </Configuration>
+   <Contents>
+      <Content ignore="false" zipEntry="certs/server.pref"/>
+      <Content ignore="false" zipEntry="certs/truststore.p12"/>
+      <Content ignore="false" zipEntry="certs/client.p12"/>
+   </Contents>
+</MissionPackageManifest>"#;
+
+    const PREFERENCES: &str = r#"<?xml version='1.0' standalone='yes'?>
+<preferences>
+  <preference version="1" name="cot_streams">
+    <entry key="count" class="class java.lang.Integer">2</entry>
+    <entry key="description0" class="class java.lang.String">Primary</entry>
+    <entry key="enabled0" class="class java.lang.Boolean">true</entry>
+    <entry key="connectString0" class="class java.lang.String">takserver.example.com:8089:ssl</entry>
+    <entry key="description1" class="class java.lang.String">Backup</entry>
+    <entry key="enabled1" class="class java.lang.Boolean">false</entry>
+    <entry key="connectString1" class="class java.lang.String">10.0.0.5:8087:tcp</entry>
+  </preference>
+  <preference version="1" name="com.atakmap.app_preferences">
+    <entry key="caLocation" class="class java.lang.String">/storage/emulated/0/atak/cert/truststore.p12</entry>
+    <entry key="caPassword" class="class java.lang.String">trust&amp;pass</entry>
+    <entry key="certificateLocation" class="class java.lang.String">cert/client.p12</entry>
+    <entry key="clientPassword" class="class java.lang.String">clientpass</entry>
+  </preference>
+</preferences>"#;
+
+    /// Builds a zip in memory from its entries
+    fn zip(entries: &[(&str, &[u8])], options: SimpleFileOptions) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        let mut zip = writer.finish().unwrap();
+        zip.set_position(0);
+        zip
+    }
+
+    fn ca_stack(ca: &TestCa) -> Stack<X509> {
+        let mut stack = Stack::new().unwrap();
+        stack.push(ca.cert.clone()).unwrap();
+        stack
+    }
+
+    #[test]
+    fn load_servers_from_data_package() {
+        let ca = TestCa::new("TAK CA");
+        let client = ca.issue("client");
+        let client_p12 = Pkcs12::builder()
+            .name("client")
+            .pkey(&client.key)
+            .cert(&client.cert)
+            .ca(ca_stack(&ca))
+            .build2("clientpass")
+            .unwrap()
+            .to_der()
+            .unwrap();
+        let truststore_p12 = Pkcs12::builder()
+            .ca(ca_stack(&ca))
+            .build2("trust&pass")
+            .unwrap()
+            .to_der()
+            .unwrap();
+
+        let package = zip(
+            &[
+                (MANIFEST_PATH, MANIFEST.as_bytes()),
+                ("certs/server.pref", PREFERENCES.as_bytes()),
+                ("certs/truststore.p12", &truststore_p12),
+                ("certs/client.p12", &client_p12),
+            ],
+            SimpleFileOptions::default(),
+        );
+        let servers = load_data_package_from_reader(package).unwrap();
+
+        let connections: Vec<(&str, bool, &str, bool)> = servers
+            .iter()
+            .map(|server| {
+                (
+                    server.description.as_str(),
+                    server.enabled,
+                    server.url.as_str(),
+                    server.settings.tls,
+                )
+            })
+            .collect();
+        assert_eq!(
+            connections,
+            [
+                ("Primary", true, "ssl://takserver.example.com:8089", true),
+                ("Backup", false, "tcp://10.0.0.5:8087", false),
+            ]
+        );
+
+        for server in &servers {
+            let credentials = server.settings.client_credentials.as_ref().unwrap();
+            assert_eq!(credentials.certificate, client.cert_der());
+            assert_eq!(credentials.root_cert, Some(vec![ca.cert_der()]));
+            let root_cert = server.settings.root_cert.as_ref().unwrap().load().unwrap();
+            assert_eq!(root_cert, ca.cert_pem());
+        }
+    }
+
+    #[test]
+    fn missing_certificate_file_is_reported() {
+        let package = zip(
+            &[("server.pref", PREFERENCES.as_bytes())],
+            SimpleFileOptions::default(),
+        );
+        let error = load_data_package_from_reader(package).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn oversized_entry_is_rejected() {
+        let content = vec![0u8; MAX_ENTRY_SIZE as usize + 1];
+        let package = zip(
+            &[
+                ("server.pref", PREFERENCES.as_bytes()),
+                ("map.bin", &content),
+            ],
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored),
+        );
+        let error = load_data_package_from_reader(package).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
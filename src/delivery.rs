@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module confirms the delivery of messages to a TAK server.
+//!
+//! TAK servers do not acknowledge individual messages, but answer a ping (`t-x-c-t`) with a pong
+//! (`t-x-c-t-r`). The server processes the messages of a connection in order, so the pong
+//! answering a ping written after a message confirms the server has received the message.
+//!
+//! The XML events of the protocol negotiation precede the first framed message, after which the
+//! server only sends framed messages.
+
+use prost::Message;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{CursorOnTarget, PublishError, tak_proto};
+
+/// COT type of the ping sent to the TAK server
+const PING_TYPE: &str = "t-x-c-t";
+/// COT type of the TAK server's answer to a ping
+const PONG_TYPE: &str = "t-x-c-t-r";
+
+/// Reads the messages sent by the TAK server and keeps track of the pings not yet answered
+#[derive(Default)]
+pub(crate) struct ConfirmationReader {
+    /// Received data which does not yet form a complete message
+    buffer: Vec<u8>,
+    /// Number of pings written which have not been answered
+    outstanding: usize,
+    /// Whether the first framed message has been received, ending the protocol negotiation
+    negotiated: bool,
+}
+
+impl ConfirmationReader {
+    /// Encodes the ping following a message to be confirmed
+    ///
+    /// # Arguments
+    ///
+    /// * `uid` - UID of the publishing entity, the ping is sent as `<uid>-ping`
+    ///
+    pub(crate) fn ping(uid: &str) -> Vec<u8> {
+        let mut ping = CursorOnTarget::default();
+        ping.uid = format!("{uid}-ping");
+        ping.r#type = PING_TYPE.into();
+        ping.how = "h-g-i-g-o".into();
+        ping.stale_time_ms = 20 * 1000;
+
+        crate::rpc_from_cot(&ping).encode_to_vec()
+    }
+
+    /// Records a ping written to the TAK server
+    pub(crate) fn ping_sent(&mut self) {
+        self.outstanding += 1;
+    }
+
+    /// Reads from the connection once and processes the complete messages, the read can be
+    /// cancelled without losing data
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Connection to the TAK server
+    ///
+    pub(crate) async fn read<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+    ) -> Result<(), PublishError> {
+        let read = stream.read_buf(&mut self.buffer).await.map_err(|e| {
+            PublishError::ConnectionError(format!("Failed reading from TAK server: {e}"))
+        })?;
+
+        if read == 0 {
+            return Err(PublishError::ConnectionError(
+                "TAK server closed the connection".into(),
+            ));
+        }
+
+        self.process_messages()
+    }
+
+    /// Reads from the connection until every ping written has been answered
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Connection to the TAK server
+    ///
+    pub(crate) async fn wait_confirmed<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+    ) -> Result<(), PublishError> {
+        while self.outstanding > 0 {
+            self.read(stream).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the complete messages from the buffer, counting the pongs among them
+    fn process_messages(&mut self) -> Result<(), PublishError> {
+        while !self.negotiated {
+            // Skip the XML events of the protocol negotiation, such as the response to the
+            // request switching to the TAK protocol
+            let start = self
+                .buffer
+                .iter()
+                .position(|byte| !byte.is_ascii_whitespace())
+                .unwrap_or(self.buffer.len());
+            self.buffer.drain(..start);
+
+            match self.buffer.first() {
+                None => return Ok(()),
+                Some(&byte) if byte == crate::TCP_MAGIC[0] => self.negotiated = true,
+                Some(_) => {
+                    let Some(end) = self
+                        .buffer
+                        .windows(crate::XML_EVENT_END.len())
+                        .position(|window| window == crate::XML_EVENT_END)
+                    else {
+                        return Ok(());
+                    };
+                    self.buffer.drain(..end + crate::XML_EVENT_END.len());
+                }
+            }
+        }
+
+        while let Some(payload) = crate::framing::decode_frame(&self.buffer).map_err(|e| {
+            PublishError::ConnectionError(format!("Invalid message from TAK server: {e}"))
+        })? {
+            let is_pong = tak_proto::TakMessage::decode(&self.buffer[payload.clone()])
+                .ok()
+                .and_then(|message| message.cot_event)
+                .is_some_and(|event| event.r#type == PONG_TYPE);
+            if is_pong {
+                self.outstanding = self.outstanding.saturating_sub(1);
+            }
+
+            self.buffer.drain(..payload.end);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestTakServer;
+    use crate::{CotPublisher, DeliveryPath, TakServerSetting, TimeoutSettings};
+    use std::time::Duration;
+
+    /// Frames a TAK protocol message holding an event of the COT type
+    fn message(r#type: &str) -> Vec<u8> {
+        let mut cot = CursorOnTarget::default();
+        cot.uid = "takserver".into();
+        cot.r#type = r#type.into();
+        crate::framing::frame(&crate::rpc_from_cot(&cot).encode_to_vec())
+    }
+
+    /// Reads all of the data into the confirmation reader
+    async fn feed(reader: &mut ConfirmationReader, mut data: &[u8]) -> Result<(), PublishError> {
+        while !data.is_empty() {
+            reader.read(&mut data).await?;
+        }
+        Ok(())
+    }
+
+    /// Confirmation reader with pings outstanding
+    fn reader(outstanding: usize) -> ConfirmationReader {
+        let mut reader = ConfirmationReader::default();
+        for _ in 0..outstanding {
+            reader.ping_sent();
+        }
+        reader
+    }
+
+    #[tokio::test]
+    async fn pongs_answer_outstanding_pings() {
+        let mut reader = reader(2);
+        let data = [message(PONG_TYPE), message("a-f-G-U-C")].concat();
+        feed(&mut reader, &data).await.unwrap();
+        assert_eq!(reader.outstanding, 1);
+
+        // Pongs which were not asked for are ignored
+        let data = [message(PONG_TYPE), message(PONG_TYPE)].concat();
+        feed(&mut reader, &data).await.unwrap();
+        assert_eq!(reader.outstanding, 0);
+    }
+
+    #[tokio::test]
+    async fn partial_messages_are_kept_until_complete() {
+        let mut reader = reader(1);
+        let data = message(PONG_TYPE);
+        for (index, byte) in data.iter().enumerate() {
+            assert_eq!(reader.outstanding, 1, "{index} bytes");
+            feed(&mut reader, std::slice::from_ref(byte)).await.unwrap();
+        }
+        assert_eq!(reader.outstanding, 0);
+        assert!(reader.buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn negotiation_response_before_the_first_message_is_skipped() {
+        let mut reader = reader(1);
+
+        // A magic byte, followed by a large length, in the negotiation response is not taken
+        // for a message
+        let response = "<?xml version='1.0'?><event type='t-x-takp-r'><detail>\
+            <TakControl><TakResponse status='true'/></TakControl><remarks>¿\u{ff}\u{ff}</remarks>\
+            </detail></event>\n";
+        let data = [response.as_bytes(), &message(PONG_TYPE)].concat();
+        let (first, second) = data.split_at(response.len() / 2);
+        feed(&mut reader, first).await.unwrap();
+        feed(&mut reader, second).await.unwrap();
+        assert_eq!(reader.outstanding, 0);
+    }
+
+    #[tokio::test]
+    async fn data_between_messages_breaks_the_connection() {
+        let mut reader = reader(1);
+        let data = [message("a-f-G-U-C").as_slice(), b"<event/>"].concat();
+        let error = feed(&mut reader, &data).await.unwrap_err();
+        assert!(matches!(error, PublishError::ConnectionError(_)), "{error}");
+    }
+
+    #[tokio::test]
+    async fn wait_confirmed_fails_when_the_server_closes_the_connection() {
+        let mut reader = reader(1);
+        let data = message("a-f-G-U-C");
+        let error = reader
+            .wait_confirmed(&mut data.as_slice())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, PublishError::ConnectionError(_)), "{error}");
+    }
+
+    /// Publisher to a test server confirming the delivery of messages
+    fn confirming_publisher(server: &TestTakServer) -> CotPublisher {
+        let settings = TakServerSetting {
+            tls: false,
+            confirm_delivery: true,
+            timeouts: TimeoutSettings {
+                confirmation: None,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        CotPublisher::new_takserver(server.url(), settings)
+    }
+
+    #[tokio::test]
+    async fn delivery_is_confirmed_by_the_server() {
+        let mut server = TestTakServer::start().await;
+        let publisher = confirming_publisher(&server);
+
+        let cot = publisher.create_cot("confirmed", "a-f-G-U-C").unwrap();
+        let path = cot.publish_checked_timeout(Duration::from_secs(10)).await;
+        assert_eq!(path.unwrap(), DeliveryPath::TakServer);
+        assert_eq!(server.next_event().await.event.uid, "confirmed");
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_delivery_times_out() {
+        let server = TestTakServer::start_on("127.0.0.1:0".parse().unwrap(), None, None).await;
+        let publisher = confirming_publisher(&server);
+
+        let timeout = Duration::from_millis(1500);
+        let cot = publisher.create_cot("unconfirmed", "a-f-G-U-C").unwrap();
+        let error = cot.publish_checked_timeout(timeout).await.unwrap_err();
+        assert!(
+            matches!(error, PublishError::DeliveryTimeout(t) if t == timeout),
+            "{error}"
+        );
+    }
+}
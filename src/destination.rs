@@ -0,0 +1,419 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module parses publisher destinations given as URLs or ATAK connect strings.
+//!
+//! The following forms are accepted, the port defaults to the standard port of the scheme when
+//! it is not given:
+//!
+//! * `ssl://host:8089` - TAK server over TLS
+//! * `tcp://host:8087` - TAK server over TCP
+//! * `quic://host:8090` - TAK server over QUIC, requires the `quic` feature
+//! * `wss://host/takproto/1` - TAK server WebSocket streaming endpoint over TLS, `ws://` without
+//!   TLS, the path defaults to `/takproto/1` and the port to 443 or 80, any query string is kept
+//! * `udp://host:8087` - UDP unicast
+//! * `mcast://239.2.3.1:6969?iface=eth0&ttl=4` - UDP multicast, optionally sent from the
+//!   interface given by name or address and with the given time to live
+//! * `host:port:proto` - ATAK connect string, where `proto` is one of the schemes above
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use url::Url;
+
+/// Default port of TAK server TLS connections
+const DEFAULT_SSL_PORT: u16 = 8089;
+/// Default port of TAK server TCP connections
+const DEFAULT_TCP_PORT: u16 = 8087;
+/// Default port of TAK server QUIC connections
+pub(crate) const DEFAULT_QUIC_PORT: u16 = 8090;
+/// Path of the TAK server WebSocket streaming endpoint
+const DEFAULT_WEBSOCKET_PATH: &str = "/takproto/1";
+/// Default port of UDP unicast destinations
+const DEFAULT_UDP_PORT: u16 = 8087;
+/// Default port of the ATAK situational awareness multicast group
+const DEFAULT_MULTICAST_PORT: u16 = 6969;
+
+/// Destination COT messages are published to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// TAK server connection
+    TakServer {
+        /// URL of the TAK server, always including the port
+        url: Url,
+        /// Use TLS for the connection
+        tls: bool,
+    },
+    /// UDP unicast to a host
+    Udp {
+        /// Host name or IP address of the receiver
+        host: String,
+        /// Port of the receiver
+        port: u16,
+    },
+    /// UDP multicast to a group
+    Multicast {
+        /// Multicast group address
+        address: IpAddr,
+        /// Port of the multicast group
+        port: u16,
+        /// Name or IP address of the interface to send from
+        interface: Option<String>,
+        /// Time to live of the multicast packets
+        ttl: Option<u32>,
+    },
+}
+
+impl Destination {
+    /// Parses a destination URL or ATAK connect string
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Destination, e.g. ssl://takserver.example.com:8089 or
+    ///   takserver.example.com:8089:ssl
+    ///
+    pub fn parse(destination: &str) -> Result<Self, std::io::Error> {
+        if destination.contains("://") {
+            let url = Url::parse(destination).map_err(|e| {
+                invalid_destination(format!("Invalid destination {destination}: {e}"))
+            })?;
+            Self::from_url(&url)
+        } else {
+            Self::from_connect_string(destination)
+        }
+    }
+
+    /// Parses an ATAK `host:port:proto` connect string
+    ///
+    /// # Arguments
+    ///
+    /// * `connect_string` - Connect string, e.g. takserver.example.com:8089:ssl
+    ///
+    pub fn from_connect_string(connect_string: &str) -> Result<Self, std::io::Error> {
+        let mut parts = connect_string.rsplitn(3, ':');
+        let (Some(protocol), Some(port), Some(host)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid_destination(format!(
+                "Invalid connect string {connect_string}, expected host:port:protocol"
+            )));
+        };
+
+        // IPv6 addresses have to be bracketed to form a URL
+        let host = if host.contains(':') && !host.starts_with('[') {
+            format!("[{host}]")
+        } else {
+            host.to_owned()
+        };
+
+        let url = Url::parse(&format!("{protocol}://{host}:{port}")).map_err(|e| {
+            invalid_destination(format!("Invalid connect string {connect_string}: {e}"))
+        })?;
+        Self::from_url(&url)
+    }
+
+    /// Converts a destination URL, filling in the default port of the scheme
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Destination URL, e.g. mcast://239.2.3.1:6969?ttl=4
+    ///
+    pub fn from_url(url: &Url) -> Result<Self, std::io::Error> {
+        let host = url.host_str().ok_or(invalid_destination(format!(
+            "Destination {url} has no host"
+        )))?;
+        let mut options: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+        let destination = match url.scheme() {
+            scheme @ ("ssl" | "tls" | "tcp") => {
+                let tls = scheme != "tcp";
+                let port = url.port().unwrap_or(if tls {
+                    DEFAULT_SSL_PORT
+                } else {
+                    DEFAULT_TCP_PORT
+                });
+                let url = Url::parse(&format!("{scheme}://{host}:{port}"))
+                    .map_err(|e| invalid_destination(format!("Invalid destination {url}: {e}")))?;
+                Destination::TakServer { url, tls }
+            }
+            "quic" => {
+                let port = url.port().unwrap_or(DEFAULT_QUIC_PORT);
+                let url = Url::parse(&format!("quic://{host}:{port}"))
+                    .map_err(|e| invalid_destination(format!("Invalid destination {url}: {e}")))?;
+                Destination::TakServer { url, tls: true }
+            }
+            scheme @ ("ws" | "wss") => {
+                // The query string belongs to the endpoint and is sent to the server
+                options.clear();
+                let mut url = url.clone();
+                if url.path() == "/" {
+                    url.set_path(DEFAULT_WEBSOCKET_PATH);
+                }
+                Destination::TakServer {
+                    url,
+                    tls: scheme == "wss",
+                }
+            }
+            "udp" => Destination::Udp {
+                host: unbracket(host).to_owned(),
+                port: url.port().unwrap_or(DEFAULT_UDP_PORT),
+            },
+            "mcast" | "multicast" => {
+                let address = unbracket(host).parse::<IpAddr>().map_err(|e| {
+                    invalid_destination(format!("Invalid multicast address {host}: {e}"))
+                })?;
+                if !address.is_multicast() {
+                    return Err(invalid_destination(format!(
+                        "{address} is not a multicast address"
+                    )));
+                }
+                let interface = take_option(&mut options, "iface");
+                let ttl = take_option(&mut options, "ttl")
+                    .map(|ttl| {
+                        ttl.parse::<u32>().map_err(|e| {
+                            invalid_destination(format!("Invalid multicast ttl {ttl}: {e}"))
+                        })
+                    })
+                    .transpose()?;
+                Destination::Multicast {
+                    address,
+                    port: url.port().unwrap_or(DEFAULT_MULTICAST_PORT),
+                    interface,
+                    ttl,
+                }
+            }
+            scheme => {
+                return Err(invalid_destination(format!(
+                    "Unsupported destination scheme {scheme}, expected ssl, tcp, quic, ws, wss, udp or mcast"
+                )));
+            }
+        };
+
+        if let Some((name, _)) = options.first() {
+            return Err(invalid_destination(format!(
+                "Unsupported option {name} for destination {url}"
+            )));
+        }
+
+        Ok(destination)
+    }
+}
+
+impl FromStr for Destination {
+    type Err = std::io::Error;
+
+    fn from_str(destination: &str) -> Result<Self, Self::Err> {
+        Self::parse(destination)
+    }
+}
+
+/// Finds the local address to send multicast packets from for an interface given by name or
+/// address
+///
+/// # Arguments
+///
+/// * `interface` - Interface name, e.g. eth0, or a local IP address
+/// * `ipv6` - Find an IPv6 address rather than an IPv4 address
+///
+pub(crate) fn interface_address(interface: &str, ipv6: bool) -> Result<IpAddr, std::io::Error> {
+    if let Ok(address) = interface.parse::<IpAddr>() {
+        return Ok(address);
+    }
+
+    if_addrs::get_if_addrs()?
+        .into_iter()
+        .find(|candidate| candidate.name == interface && candidate.ip().is_ipv6() == ipv6)
+        .map(|candidate| candidate.ip())
+        .ok_or(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "Interface {interface} has no {} address",
+                if ipv6 { "IPv6" } else { "IPv4" }
+            ),
+        ))
+}
+
+/// Removes and returns the value of a URL query option
+fn take_option(options: &mut Vec<(String, String)>, name: &str) -> Option<String> {
+    let index = options.iter().position(|(key, _)| key == name)?;
+    Some(options.remove(index).1)
+}
+
+/// Strips the brackets from an IPv6 URL host
+fn unbracket(host: &str) -> &str {
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Creates the error returned for destinations which cannot be parsed
+fn invalid_destination(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tak_server(url: &str, tls: bool) -> Destination {
+        Destination::TakServer {
+            url: Url::parse(url).unwrap(),
+            tls,
+        }
+    }
+
+    fn parse(destination: &str) -> Destination {
+        Destination::parse(destination).unwrap()
+    }
+
+    fn parse_error(destination: &str) -> String {
+        let error = Destination::parse(destination).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        error.to_string()
+    }
+
+    #[test]
+    fn tak_server_urls() {
+        let cases = [
+            (
+                "ssl://tak.example.com:8443",
+                "ssl://tak.example.com:8443",
+                true,
+            ),
+            ("ssl://tak.example.com", "ssl://tak.example.com:8089", true),
+            ("tls://tak.example.com", "tls://tak.example.com:8089", true),
+            ("tcp://tak.example.com", "tcp://tak.example.com:8087", false),
+            ("tcp://[fd00::1]:9000", "tcp://[fd00::1]:9000", false),
+            (
+                "quic://tak.example.com",
+                "quic://tak.example.com:8090",
+                true,
+            ),
+        ];
+        for (destination, url, tls) in cases {
+            assert_eq!(parse(destination), tak_server(url, tls), "{destination}");
+        }
+    }
+
+    #[test]
+    fn websocket_urls() {
+        let cases = [
+            (
+                "ws://tak.example.com",
+                "ws://tak.example.com/takproto/1",
+                false,
+            ),
+            (
+                "wss://tak.example.com:8446",
+                "wss://tak.example.com:8446/takproto/1",
+                true,
+            ),
+            (
+                "wss://tak.example.com/stream",
+                "wss://tak.example.com/stream",
+                true,
+            ),
+            (
+                "wss://tak.example.com/takproto/1?token=abc&mode=1",
+                "wss://tak.example.com/takproto/1?token=abc&mode=1",
+                true,
+            ),
+        ];
+        for (destination, url, tls) in cases {
+            assert_eq!(parse(destination), tak_server(url, tls), "{destination}");
+        }
+    }
+
+    #[test]
+    fn udp_urls() {
+        let udp = |host: &str, port| Destination::Udp {
+            host: host.into(),
+            port,
+        };
+        assert_eq!(parse("udp://10.0.0.2:4242"), udp("10.0.0.2", 4242));
+        assert_eq!(parse("udp://receiver.local"), udp("receiver.local", 8087));
+        assert_eq!(parse("udp://[fd00::2]:4242"), udp("fd00::2", 4242));
+    }
+
+    #[test]
+    fn multicast_urls() {
+        assert_eq!(
+            parse("mcast://239.2.3.1"),
+            Destination::Multicast {
+                address: "239.2.3.1".parse().unwrap(),
+                port: 6969,
+                interface: None,
+                ttl: None,
+            }
+        );
+        assert_eq!(
+            parse("multicast://[ff05::1]:7171?iface=eth0&ttl=4"),
+            Destination::Multicast {
+                address: "ff05::1".parse().unwrap(),
+                port: 7171,
+                interface: Some("eth0".into()),
+                ttl: Some(4),
+            }
+        );
+
+        assert!(parse_error("mcast://10.0.0.1:6969").contains("not a multicast address"));
+        assert!(parse_error("mcast://[fd00::1]:6969").contains("not a multicast address"));
+        assert!(parse_error("mcast://group.local").contains("Invalid multicast address"));
+        assert!(parse_error("mcast://239.2.3.1?ttl=-1").contains("Invalid multicast ttl"));
+    }
+
+    #[test]
+    fn connect_strings() {
+        let cases = [
+            (
+                "tak.example.com:8089:ssl",
+                tak_server("ssl://tak.example.com:8089", true),
+            ),
+            (
+                "10.0.0.5:8087:tcp",
+                tak_server("tcp://10.0.0.5:8087", false),
+            ),
+            (
+                "fd00::1:8087:tcp",
+                tak_server("tcp://[fd00::1]:8087", false),
+            ),
+            (
+                "tak.example.com:8090:quic",
+                tak_server("quic://tak.example.com:8090", true),
+            ),
+            (
+                "10.0.0.2:4242:udp",
+                Destination::Udp {
+                    host: "10.0.0.2".into(),
+                    port: 4242,
+                },
+            ),
+            (
+                "239.2.3.1:6969:mcast",
+                Destination::Multicast {
+                    address: "239.2.3.1".parse().unwrap(),
+                    port: 6969,
+                    interface: None,
+                    ttl: None,
+                },
+            ),
+        ];
+        for (connect_string, destination) in cases {
+            assert_eq!(parse(connect_string), destination, "{connect_string}");
+            assert_eq!(
+                connect_string.parse::<Destination>().unwrap(),
+                destination,
+                "{connect_string}"
+            );
+        }
+
+        assert!(parse_error("tak.example.com:8089").contains("expected host:port:protocol"));
+        assert!(parse_error("tak.example.com:port:ssl").contains("Invalid connect string"));
+    }
+
+    #[test]
+    fn invalid_destinations() {
+        assert!(parse_error("http://tak.example.com").contains("Unsupported destination scheme"));
+        assert!(parse_error("ssl://tak.example.com?ttl=4").contains("Unsupported option ttl"));
+        assert!(parse_error("udp://10.0.0.2?iface=eth0").contains("Unsupported option iface"));
+        assert!(parse_error("ssl:///path").contains("has no host"));
+        assert!(parse_error("tcp://tak example").contains("Invalid destination"));
+    }
+}
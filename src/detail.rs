@@ -0,0 +1,520 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module builds the detail of TAK protocol messages, promoting elements of the XML detail
+//! block into the strongly typed messages as required by detail.proto.
+//!
+//! Senders must move whole `<contact>`, `<__group>`, `<precisionlocation>`, `<status>`,
+//! `<takv>` and `<track>` elements into their typed messages and remove them from the XML
+//! detail. An element is only promoted when it appears once and maps cleanly, with exactly the
+//! attributes of the message and no text or children, otherwise it is left in the XML detail.
+//! Receivers ignore a typed message when the XML detail contains the same element, so when an
+//! element is both set on the [`CursorOnTarget`] and present in the XML detail the XML element
+//! is kept, the typed message is omitted and the conflict is reported. The same applies to the
+//! `__milsym` element written for [`CursorOnTarget::milsym`].
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::{CursorOnTarget, DetailElement, Track, tak_proto};
+
+/// Elements of the detail block which have a strongly typed message
+const TYPED_ELEMENTS: [&str; 6] = [
+    "contact",
+    "__group",
+    "precisionlocation",
+    "status",
+    "takv",
+    "track",
+];
+/// Element of the detail block written for [`CursorOnTarget::milsym`]
+const MILSYM_ELEMENT: &str = "__milsym";
+
+/// Element set both as a typed field of a [`CursorOnTarget`] and in its XML detail block
+///
+/// Receivers use the element of the XML detail, so the typed field is not sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DetailConflict {
+    /// Name of the element, e.g. `contact`
+    pub element: &'static str,
+}
+
+impl fmt::Display for DetailConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<{}> is set as a typed field and in the XML detail, the XML detail is used",
+            self.element
+        )
+    }
+}
+
+/// Element at the top level of an XML detail block
+struct TopLevelElement {
+    /// Name of the element
+    name: String,
+    /// Byte range of the element in the XML detail block, from its start to its end tag
+    range: Range<usize>,
+}
+
+/// Builds the detail of a TAK protocol message, promoting the typed elements of the XML detail
+/// block into their messages
+///
+/// Returns the detail, without the `__milsym` element, and the conflicts between typed fields
+/// and the XML detail. The promoted elements are cut out of the XML detail, which is otherwise
+/// kept as it was written. An XML detail block which is not well-formed is sent as it is.
+///
+/// # Arguments
+///
+/// * `cot` - Reference to the CursorOnTarget struct to convert
+///
+pub(crate) fn detail_from_cot(cot: &CursorOnTarget) -> (tak_proto::Detail, Vec<DetailConflict>) {
+    let mut detail = typed_detail(cot);
+    let xml_detail = cot.xml_detail.as_deref().unwrap_or("");
+    let Some(elements) = top_level_elements(xml_detail) else {
+        detail.xml_detail = xml_detail.into();
+        return (detail, Vec::new());
+    };
+
+    let mut conflicts = Vec::new();
+    let mut promoted = Vec::new();
+    for name in TYPED_ELEMENTS {
+        let mut occurrences = elements.iter().filter(|element| element.name == name);
+        let (first, second) = (occurrences.next(), occurrences.next());
+
+        if first.is_some() && is_typed_set(&detail, name) {
+            conflicts.push(DetailConflict { element: name });
+            clear_typed(&mut detail, name);
+        } else if let (Some(element), None) = (first, second) {
+            let parsed = DetailElement::parse(&xml_detail[element.range.clone()]);
+            if let Ok([parsed]) = parsed.as_deref() {
+                if promote(parsed, &mut detail) {
+                    promoted.push(element.range.clone());
+                }
+            }
+        }
+    }
+    if cot.milsym.is_some()
+        && elements
+            .iter()
+            .any(|element| element.name == MILSYM_ELEMENT)
+    {
+        conflicts.push(DetailConflict {
+            element: MILSYM_ELEMENT,
+        });
+    }
+
+    // Cut the promoted elements out, keeping the text between them as it was written
+    promoted.sort_by_key(|range| range.start);
+    let mut remaining = String::with_capacity(xml_detail.len());
+    let mut kept_from = 0;
+    for range in promoted {
+        remaining.push_str(&xml_detail[kept_from..range.start]);
+        kept_from = range.end;
+    }
+    remaining.push_str(&xml_detail[kept_from..]);
+    detail.xml_detail = remaining;
+    (detail, conflicts)
+}
+
+/// Finds the elements set both as a typed field and in the XML detail block
+///
+/// # Arguments
+///
+/// * `cot` - Reference to the CursorOnTarget struct to check
+///
+pub(crate) fn detail_conflicts(cot: &CursorOnTarget) -> Vec<DetailConflict> {
+    let Some(elements) = top_level_elements(cot.xml_detail.as_deref().unwrap_or("")) else {
+        return Vec::new();
+    };
+
+    let detail = typed_detail(cot);
+    TYPED_ELEMENTS
+        .into_iter()
+        .filter(|name| is_typed_set(&detail, name))
+        .chain(cot.milsym.as_ref().map(|_| MILSYM_ELEMENT))
+        .filter(|name| elements.iter().any(|element| element.name == *name))
+        .map(|element| DetailConflict { element })
+        .collect()
+}
+
+/// Finds the elements at the top level of an XML detail block and their byte ranges
+///
+/// Returns `None` when the block is not well-formed, such as when tags are not balanced or
+/// there is text outside of the elements.
+///
+/// # Arguments
+///
+/// * `xml` - XML detail block, without the enclosing `<detail>` element
+///
+fn top_level_elements(xml: &str) -> Option<Vec<TopLevelElement>> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut elements = Vec::new();
+    let mut depth = 0usize;
+    let mut open = None;
+
+    loop {
+        // The position before an event is the start of its markup
+        let start = reader.buffer_position() as usize;
+        match reader.read_event().ok()? {
+            Event::Start(element) => {
+                if depth == 0 {
+                    let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+                    open = Some((name, start));
+                }
+                depth += 1;
+            }
+            Event::Empty(element) if depth == 0 => elements.push(TopLevelElement {
+                name: String::from_utf8_lossy(element.name().as_ref()).into_owned(),
+                range: start..reader.buffer_position() as usize,
+            }),
+            Event::End(_) => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    let (name, start) = open.take()?;
+                    elements.push(TopLevelElement {
+                        name,
+                        range: start..reader.buffer_position() as usize,
+                    });
+                }
+            }
+            Event::Text(text) if depth == 0 && !text.iter().all(u8::is_ascii_whitespace) => {
+                return None;
+            }
+            Event::CData(_) if depth == 0 => return None,
+            Event::Eof => return (depth == 0).then_some(elements),
+            _ => {}
+        }
+    }
+}
+
+/// Converts the typed fields of a CursorOnTarget struct to their messages
+fn typed_detail(cot: &CursorOnTarget) -> tak_proto::Detail {
+    tak_proto::Detail {
+        xml_detail: String::new(),
+        contact: cot.contact.as_ref().map(|c| tak_proto::Contact {
+            endpoint: c.endpoint.to_owned(),
+            callsign: c.callsign.to_owned(),
+        }),
+        group: cot.group.as_ref().map(|g| tak_proto::Group {
+            name: g.name.to_string(),
+            role: g.role.to_string(),
+        }),
+        precision_location: cot
+            .precision_location
+            .as_ref()
+            .map(|p| tak_proto::PrecisionLocation {
+                geopointsrc: p.geopointsrc.to_owned(),
+                altsrc: p.altsrc.to_owned(),
+            }),
+        status: cot.status.map(|s| tak_proto::Status { battery: s.battery }),
+        takv: cot.takv.as_ref().map(|t| tak_proto::Takv {
+            device: t.device.to_owned(),
+            platform: t.platform.to_owned(),
+            os: t.os.to_owned(),
+            version: t.version.to_owned(),
+        }),
+        track: cot
+            .track
+            .filter(Track::is_finite)
+            .map(|t| tak_proto::Track {
+                speed: t.speed,
+                course: t.course,
+            }),
+    }
+}
+
+/// Whether the typed message of an element is set
+fn is_typed_set(detail: &tak_proto::Detail, name: &str) -> bool {
+    match name {
+        "contact" => detail.contact.is_some(),
+        "__group" => detail.group.is_some(),
+        "precisionlocation" => detail.precision_location.is_some(),
+        "status" => detail.status.is_some(),
+        "takv" => detail.takv.is_some(),
+        "track" => detail.track.is_some(),
+        _ => false,
+    }
+}
+
+/// Clears the typed message of an element
+fn clear_typed(detail: &mut tak_proto::Detail, name: &str) {
+    match name {
+        "contact" => detail.contact = None,
+        "__group" => detail.group = None,
+        "precisionlocation" => detail.precision_location = None,
+        "status" => detail.status = None,
+        "takv" => detail.takv = None,
+        "track" => detail.track = None,
+        _ => {}
+    }
+}
+
+/// Sets the typed message of an element when it maps cleanly, returning whether it did
+fn promote(element: &DetailElement, detail: &mut tak_proto::Detail) -> bool {
+    let promoted = match element.name.as_str() {
+        "contact" => attributes(element, &["callsign"], &["endpoint"]).map(|values| {
+            detail.contact = Some(tak_proto::Contact {
+                callsign: values[0].into(),
+                endpoint: values[1].into(),
+            });
+        }),
+        "__group" => attributes(element, &["name", "role"], &[]).map(|values| {
+            detail.group = Some(tak_proto::Group {
+                name: values[0].into(),
+                role: values[1].into(),
+            });
+        }),
+        "precisionlocation" => attributes(element, &["geopointsrc", "altsrc"], &[]).map(|values| {
+            detail.precision_location = Some(tak_proto::PrecisionLocation {
+                geopointsrc: values[0].into(),
+                altsrc: values[1].into(),
+            });
+        }),
+        "status" => attributes(element, &["battery"], &[])
+            .and_then(|values| values[0].parse().ok())
+            .map(|battery| detail.status = Some(tak_proto::Status { battery })),
+        "takv" => {
+            attributes(element, &["device", "platform", "os", "version"], &[]).map(|values| {
+                detail.takv = Some(tak_proto::Takv {
+                    device: values[0].into(),
+                    platform: values[1].into(),
+                    os: values[2].into(),
+                    version: values[3].into(),
+                });
+            })
+        }
+        "track" => attributes(element, &["speed", "course"], &[])
+            .and_then(|values| Some((parse_finite(values[0])?, parse_finite(values[1])?)))
+            .map(|(speed, course)| detail.track = Some(tak_proto::Track { speed, course })),
+        _ => None,
+    };
+    promoted.is_some()
+}
+
+/// Values of the required and optional attributes of an element, in order with missing
+/// optional attributes empty
+///
+/// Returns `None` when a required attribute is missing, or the element has other attributes,
+/// text or children which its message cannot hold.
+fn attributes<'a>(
+    element: &'a DetailElement,
+    required: &[&str],
+    optional: &[&str],
+) -> Option<Vec<&'a str>> {
+    let known =
+        |name: &String| required.contains(&name.as_str()) || optional.contains(&name.as_str());
+    if !element.text.is_empty()
+        || !element.children.is_empty()
+        || !element.attributes.iter().all(|(name, _)| known(name))
+    {
+        return None;
+    }
+
+    let mut values = Vec::with_capacity(required.len() + optional.len());
+    for name in required {
+        values.push(element.attribute(name)?);
+    }
+    for name in optional {
+        values.push(element.attribute(name).unwrap_or(""));
+    }
+    Some(values)
+}
+
+/// Parses a finite floating point attribute value
+fn parse_finite(value: &str) -> Option<f64> {
+    value.parse().ok().filter(|value: &f64| value.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CursorOnTarget with the given XML detail and no typed fields
+    fn cot(xml_detail: &str) -> CursorOnTarget {
+        let mut cot = CursorOnTarget::default();
+        cot.set_xml_detail(Some(xml_detail));
+        cot
+    }
+
+    #[test]
+    fn typed_elements_promoted() {
+        let (detail, conflicts) =
+            detail_from_cot(&cot("<contact callsign=\"Alpha\" endpoint=\"*:-1:stcp\"/>\
+             <__group name=\"Cyan\" role=\"Team Member\"/>\
+             <precisionlocation geopointsrc=\"GPS\" altsrc=\"DTED0\"/>\
+             <status battery=\"87\"/>\
+             <takv device=\"Pixel\" platform=\"ATAK-CIV\" os=\"34\" version=\"5.2\"/>\
+             <track speed=\"1.5\" course=\"270\"/>\
+             <remarks>Patrol</remarks>"));
+
+        assert!(conflicts.is_empty());
+        assert_eq!(detail.xml_detail, "<remarks>Patrol</remarks>");
+        assert_eq!(
+            detail.contact,
+            Some(tak_proto::Contact {
+                endpoint: "*:-1:stcp".into(),
+                callsign: "Alpha".into(),
+            })
+        );
+        assert_eq!(
+            detail.group,
+            Some(tak_proto::Group {
+                name: "Cyan".into(),
+                role: "Team Member".into(),
+            })
+        );
+        assert_eq!(
+            detail.precision_location,
+            Some(tak_proto::PrecisionLocation {
+                geopointsrc: "GPS".into(),
+                altsrc: "DTED0".into(),
+            })
+        );
+        assert_eq!(detail.status, Some(tak_proto::Status { battery: 87 }));
+        assert_eq!(
+            detail.takv,
+            Some(tak_proto::Takv {
+                device: "Pixel".into(),
+                platform: "ATAK-CIV".into(),
+                os: "34".into(),
+                version: "5.2".into(),
+            })
+        );
+        assert_eq!(
+            detail.track,
+            Some(tak_proto::Track {
+                speed: 1.5,
+                course: 270.0,
+            })
+        );
+    }
+
+    #[test]
+    fn optional_attribute_defaults_to_empty() {
+        let (detail, _) = detail_from_cot(&cot("<contact callsign=\"Alpha\"/>"));
+        assert_eq!(detail.xml_detail, "");
+        assert_eq!(detail.contact.unwrap().endpoint, "");
+    }
+
+    #[test]
+    fn duplicates_left_in_xml_detail() {
+        let xml = "<contact callsign=\"Alpha\"/><remarks/><contact callsign=\"Bravo\"/>";
+        let (detail, conflicts) = detail_from_cot(&cot(xml));
+        assert!(conflicts.is_empty());
+        assert_eq!(detail.contact, None);
+        assert_eq!(detail.xml_detail, xml);
+    }
+
+    #[test]
+    fn unmapped_content_blocks_promotion() {
+        for xml in [
+            // Unknown attribute
+            "<contact callsign=\"Alpha\" phone=\"555\"/>",
+            // Missing required attribute
+            "<__group name=\"Cyan\"/>",
+            // Child element
+            "<takv device=\"Pixel\" platform=\"ATAK-CIV\" os=\"34\" version=\"5.2\"><plugin/></takv>",
+            // Text
+            "<precisionlocation geopointsrc=\"GPS\" altsrc=\"DTED0\">user</precisionlocation>",
+            // Unparsable value
+            "<status battery=\"full\"/>",
+        ] {
+            let (detail, conflicts) = detail_from_cot(&cot(xml));
+            assert!(conflicts.is_empty(), "{xml}");
+            assert_eq!(detail.xml_detail, xml);
+            assert_eq!(
+                (
+                    detail.contact,
+                    detail.group,
+                    detail.precision_location,
+                    detail.status,
+                    detail.takv
+                ),
+                (None, None, None, None, None),
+                "{xml}"
+            );
+        }
+    }
+
+    #[test]
+    fn non_finite_track_not_promoted() {
+        for xml in [
+            "<track speed=\"NaN\" course=\"90\"/>",
+            "<track speed=\"1.5\" course=\"inf\"/>",
+            "<track speed=\"-infinity\" course=\"90\"/>",
+        ] {
+            let (detail, _) = detail_from_cot(&cot(xml));
+            assert_eq!(detail.track, None, "{xml}");
+            assert_eq!(detail.xml_detail, xml);
+        }
+    }
+
+    #[test]
+    fn remaining_xml_detail_kept_as_written() {
+        let remarks = "<remarks source=\"Alpha\">Contact <b>east</b> of hill<!-- 0900 -->\
+                       <![CDATA[ range < 2km ]]></remarks>";
+        let xml = format!("<contact callsign=\"Alpha\"/>\n  {remarks}\n");
+
+        let (detail, conflicts) = detail_from_cot(&cot(&xml));
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            detail.contact,
+            Some(tak_proto::Contact {
+                endpoint: String::new(),
+                callsign: "Alpha".into(),
+            })
+        );
+        assert_eq!(detail.xml_detail, format!("\n  {remarks}\n"));
+    }
+
+    #[test]
+    fn conflict_uses_xml_detail() {
+        let xml = "<contact callsign=\"Bravo\"/><track speed=\"2\" course=\"90\"/>";
+        let mut cot = cot(xml);
+        cot.set_contact(Some("Alpha"), None);
+
+        let expected = vec![DetailConflict { element: "contact" }];
+        assert_eq!(detail_conflicts(&cot), expected);
+
+        let (detail, conflicts) = detail_from_cot(&cot);
+        assert_eq!(conflicts, expected);
+        assert_eq!(detail.contact, None);
+        // The track is still promoted, the conflicting contact is kept in the XML detail
+        assert_eq!(detail.xml_detail, "<contact callsign=\"Bravo\"/>");
+        assert!(detail.track.is_some());
+        assert_eq!(
+            expected[0].to_string(),
+            "<contact> is set as a typed field and in the XML detail, the XML detail is used"
+        );
+    }
+
+    #[test]
+    fn typed_fields_without_xml_detail() {
+        let mut cot = CursorOnTarget::default();
+        cot.set_contact(Some("Alpha"), None);
+        cot.set_battery(Some(50));
+
+        assert!(detail_conflicts(&cot).is_empty());
+        let (detail, conflicts) = detail_from_cot(&cot);
+        assert!(conflicts.is_empty());
+        assert_eq!(detail.xml_detail, "");
+        assert_eq!(detail.contact.unwrap().callsign, "Alpha");
+        assert_eq!(detail.status, Some(tak_proto::Status { battery: 50 }));
+    }
+
+    #[test]
+    fn malformed_xml_detail_passed_through() {
+        let xml = "<contact callsign=\"Alpha\"/><remarks>unclosed";
+        let mut cot = cot(xml);
+        cot.set_contact(Some("Bravo"), None);
+
+        assert!(detail_conflicts(&cot).is_empty());
+        let (detail, conflicts) = detail_from_cot(&cot);
+        assert!(conflicts.is_empty());
+        assert_eq!(detail.xml_detail, xml);
+        assert_eq!(detail.contact.unwrap().callsign, "Bravo");
+    }
+}
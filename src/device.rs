@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides the device details (`takv`) and status (`status`) of a COT entity,
+//! which ATAK shows in the contact list, and a collector for the battery level on Linux.
+
+use std::path::Path;
+
+/// Directory listing the power supplies of a Linux system
+#[cfg(target_os = "linux")]
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+/// Software and device of a COT entity
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Takv {
+    /// Device model
+    pub device: String,
+    /// Name of the publishing software
+    pub platform: String,
+    /// Operating system
+    pub os: String,
+    /// Version of the publishing software
+    pub version: String,
+}
+
+impl Default for Takv {
+    /// Identifies this crate as the platform, running on the host operating system and
+    /// architecture
+    fn default() -> Self {
+        Self {
+            device: std::env::consts::ARCH.into(),
+            platform: env!("CARGO_PKG_NAME").into(),
+            os: std::env::consts::OS.into(),
+            version: env!("CARGO_PKG_VERSION").into(),
+        }
+    }
+}
+
+/// Status of a COT entity
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Battery level in percent, 0 to 100
+    pub battery: u32,
+}
+
+/// Reads the battery level of the system from `/sys/class/power_supply`
+///
+/// Returns `None` when the system has no battery. See [`read_battery_level_from`].
+///
+#[cfg(target_os = "linux")]
+pub fn read_battery_level() -> Result<Option<u32>, std::io::Error> {
+    read_battery_level_from(Path::new(POWER_SUPPLY_PATH))
+}
+
+/// Reads the battery level from a directory laid out like `/sys/class/power_supply`, such as
+/// a copy of the directory used for testing
+///
+/// Only supplies of type `Battery` powering the system are used, batteries of peripherals are
+/// ignored. The level of each battery is taken from `capacity`, or calculated from
+/// `energy_now` / `energy_full` or `charge_now` / `charge_full`, and averaged over all
+/// batteries. Returns `None` when the directory does not exist or lists no battery.
+///
+/// # Arguments
+///
+/// * `power_supply` - Directory containing a subdirectory for each power supply
+///
+pub fn read_battery_level_from(power_supply: &Path) -> Result<Option<u32>, std::io::Error> {
+    let entries = match std::fs::read_dir(power_supply) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut levels = Vec::new();
+    for entry in entries {
+        let supply = entry?.path();
+        if read_value(&supply, "type").as_deref() != Some("Battery")
+            || read_value(&supply, "scope").as_deref() == Some("Device")
+        {
+            continue;
+        }
+
+        let ratio = |now: &str, full: &str| {
+            let now = read_number(&supply, now)?;
+            let full = read_number(&supply, full).filter(|full| *full > 0.0)?;
+            Some(now / full * 100.0)
+        };
+        let level = read_number(&supply, "capacity")
+            .or_else(|| ratio("energy_now", "energy_full"))
+            .or_else(|| ratio("charge_now", "charge_full"));
+
+        if let Some(level) = level {
+            levels.push(level.clamp(0.0, 100.0));
+        }
+    }
+
+    if levels.is_empty() {
+        return Ok(None);
+    }
+    let level = levels.iter().sum::<f64>() / levels.len() as f64;
+    Ok(Some(level.round() as u32))
+}
+
+/// Reads a sysfs attribute of a power supply, trimmed of the trailing newline
+fn read_value(supply: &Path, attribute: &str) -> Option<String> {
+    std::fs::read_to_string(supply.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_owned())
+}
+
+/// Reads a numeric sysfs attribute of a power supply
+fn read_number(supply: &Path, attribute: &str) -> Option<f64> {
+    read_value(supply, attribute)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a power supply with the given sysfs attributes
+    fn supply(power_supply: &Path, name: &str, attributes: &[(&str, &str)]) {
+        let supply = power_supply.join(name);
+        std::fs::create_dir(&supply).unwrap();
+        for (attribute, value) in attributes {
+            std::fs::write(supply.join(attribute), format!("{value}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn battery_capacity() {
+        let power_supply = tempfile::tempdir().unwrap();
+        supply(
+            power_supply.path(),
+            "BAT0",
+            &[("type", "Battery"), ("scope", "System"), ("capacity", "87")],
+        );
+        assert_eq!(
+            read_battery_level_from(power_supply.path()).unwrap(),
+            Some(87)
+        );
+
+        // Batteries are averaged, with levels calculated from energy or charge
+        supply(
+            power_supply.path(),
+            "BAT1",
+            &[
+                ("type", "Battery"),
+                ("energy_now", "30000"),
+                ("energy_full", "60000"),
+            ],
+        );
+        supply(
+            power_supply.path(),
+            "BAT2",
+            &[
+                ("type", "Battery"),
+                ("charge_now", "4000"),
+                ("charge_full", "4000"),
+            ],
+        );
+        assert_eq!(
+            read_battery_level_from(power_supply.path()).unwrap(),
+            Some(79)
+        );
+    }
+
+    #[test]
+    fn non_battery_supplies_ignored() {
+        let power_supply = tempfile::tempdir().unwrap();
+        supply(
+            power_supply.path(),
+            "AC",
+            &[("type", "Mains"), ("online", "1")],
+        );
+        supply(
+            power_supply.path(),
+            "hidpp_battery_0",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "12")],
+        );
+        assert_eq!(read_battery_level_from(power_supply.path()).unwrap(), None);
+
+        supply(
+            power_supply.path(),
+            "BAT0",
+            &[("type", "Battery"), ("capacity", "64")],
+        );
+        assert_eq!(
+            read_battery_level_from(power_supply.path()).unwrap(),
+            Some(64)
+        );
+    }
+
+    #[test]
+    fn no_batteries() {
+        let power_supply = tempfile::tempdir().unwrap();
+        assert_eq!(read_battery_level_from(power_supply.path()).unwrap(), None);
+        assert_eq!(
+            read_battery_level_from(&power_supply.path().join("missing")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn unparsable_capacity() {
+        let power_supply = tempfile::tempdir().unwrap();
+        supply(
+            power_supply.path(),
+            "BAT0",
+            &[("type", "Battery"), ("capacity", "unknown")],
+        );
+        assert_eq!(read_battery_level_from(power_supply.path()).unwrap(), None);
+
+        // The level falls back to the energy of the battery
+        supply(
+            power_supply.path(),
+            "BAT1",
+            &[
+                ("type", "Battery"),
+                ("capacity", "full"),
+                ("energy_now", "45000"),
+                ("energy_full", "50000"),
+            ],
+        );
+        assert_eq!(
+            read_battery_level_from(power_supply.path()).unwrap(),
+            Some(90)
+        );
+    }
+}
@@ -0,0 +1,464 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides client certificate enrollment against a TAK server.
+//!
+//! A new key pair is generated locally and a certificate signing request (CSR) is submitted to
+//! the server's enrollment endpoint using the user's username and password. The signed
+//! certificate and CA chain returned by the server are combined with the key into
+//! [`Credentials`].
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::{X509NameBuilder, X509ReqBuilder};
+use quick_xml::events::Event;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use url::Url;
+
+use crate::PublishError;
+use crate::connection::{self, TakServerSetting, http};
+use crate::keys::Credentials;
+
+/// Port of the TAK server certificate enrollment endpoint
+const DEFAULT_ENROLLMENT_PORT: u16 = 8446;
+/// Endpoint returning the subject name entries required by the server
+const CONFIG_PATH: &str = "/Marti/api/tls/config";
+/// Endpoint signing a client CSR
+const SIGN_CLIENT_PATH: &str = "/Marti/api/tls/signClient/v2";
+
+/// Settings for enrolling a client certificate with a TAK server
+pub struct EnrollmentSettings {
+    /// Username of the TAK server account
+    pub username: String,
+    /// Password of the TAK server account
+    pub password: String,
+    /// Unique identifier of this client, reported to the server
+    pub client_uid: String,
+    /// Client version string, reported to the server
+    pub version: String,
+    /// Size of the generated RSA key
+    pub key_bits: u32,
+    /// TLS settings used to validate the enrollment endpoint, client credentials are not needed
+    pub tls: TakServerSetting<'static>,
+}
+
+impl EnrollmentSettings {
+    /// Creates enrollment settings with default client details
+    ///
+    /// # Arguments
+    ///
+    /// * `username` - Username of the TAK server account
+    /// * `password` - Password of the TAK server account
+    ///
+    pub fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+            client_uid: format!("{}-{username}", env!("CARGO_PKG_NAME")),
+            version: format!("{}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            key_bits: 2048,
+            tls: TakServerSetting::default(),
+        }
+    }
+}
+
+/// Enrolls a new client certificate with the TAK server
+///
+/// # Arguments
+///
+/// * `url` - URL of the TAK server enrollment endpoint, e.g. `https://takserver.example.com:8446`,
+///   the port defaults to 8446 when not given
+/// * `settings` - Account and TLS settings for the enrollment
+///
+pub async fn enroll(
+    url: &Url,
+    settings: &EnrollmentSettings,
+) -> Result<Credentials<'static>, std::io::Error> {
+    let mut url = url.clone();
+    if url.port().is_none() {
+        url.set_port(Some(DEFAULT_ENROLLMENT_PORT))
+            .map_err(|_| std::io::Error::other("Enrollment URL cannot have a port"))?;
+    }
+
+    let authorization = format!(
+        "Basic {}",
+        openssl::base64::encode_block(
+            format!("{}:{}", settings.username, settings.password).as_bytes()
+        )
+    );
+
+    // Fetch the subject name entries the server expects in the CSR
+    let config_url = url
+        .join(CONFIG_PATH)
+        .map_err(|e| std::io::Error::other(format!("Invalid enrollment URL: {e}")))?;
+    let config = send_request(&config_url, settings, "GET", &authorization, &[]).await?;
+    let name_entries = parse_name_entries(&config)?;
+
+    let private_key = generate_private_key(settings.key_bits)?;
+    let csr = build_csr(&private_key, &settings.username, &name_entries)?;
+
+    let mut sign_url = url
+        .join(SIGN_CLIENT_PATH)
+        .map_err(|e| std::io::Error::other(format!("Invalid enrollment URL: {e}")))?;
+    sign_url
+        .query_pairs_mut()
+        .append_pair("clientUid", &settings.client_uid)
+        .append_pair("version", &settings.version);
+    let enrollment =
+        send_request(&sign_url, settings, "POST", &authorization, csr.as_bytes()).await?;
+    let (certificate, ca_chain) = parse_enrollment(&enrollment)?;
+
+    Ok(Credentials {
+        certificate,
+        private_key: PrivateKeyDer::Pkcs8(private_key.private_key_to_pkcs8()?.into()),
+        root_cert: Some(ca_chain),
+    })
+}
+
+/// Sends a request to the enrollment endpoint and returns the body of a successful response
+async fn send_request(
+    url: &Url,
+    settings: &EnrollmentSettings,
+    method: &str,
+    authorization: &str,
+    body: &[u8],
+) -> Result<String, std::io::Error> {
+    let mut stream = connection::create_connection(url, &settings.tls)
+        .await
+        .map_err(|e| match e {
+            PublishError::ConnectTimeout(_) | PublishError::HandshakeTimeout(_) => {
+                std::io::Error::new(std::io::ErrorKind::TimedOut, e)
+            }
+            e => std::io::Error::other(e),
+        })?;
+    let headers = [
+        ("Authorization", authorization.to_owned()),
+        ("Accept", "application/xml".to_owned()),
+        ("Content-Type", "text/plain".to_owned()),
+    ];
+    let response = http::request(&mut stream, method, url, &headers, body).await?;
+
+    match response.status {
+        200..=299 => String::from_utf8(response.body)
+            .map_err(|e| std::io::Error::other(format!("Invalid enrollment response: {e}"))),
+        401 | 403 => Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!(
+                "Enrollment rejected for user {} (HTTP {})",
+                settings.username, response.status
+            ),
+        )),
+        status => Err(std::io::Error::other(format!(
+            "Enrollment request to {} failed with HTTP {status}: {}",
+            url.path(),
+            String::from_utf8_lossy(&response.body)
+        ))),
+    }
+}
+
+/// Parses the `nameEntry` elements of the server's certificate configuration
+fn parse_name_entries(config: &str) -> Result<Vec<(String, String)>, std::io::Error> {
+    let mut reader = quick_xml::Reader::from_str(config);
+    let mut entries = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element) | Event::Empty(element))
+                if element.local_name().as_ref() == b"nameEntry" =>
+            {
+                let mut name = None;
+                let mut value = None;
+                for attribute in element.attributes().flatten() {
+                    let content = attribute
+                        .unescape_value()
+                        .map_err(|e| std::io::Error::other(format!("Invalid name entry: {e}")))?
+                        .into_owned();
+                    match attribute.key.local_name().as_ref() {
+                        b"name" => name = Some(content),
+                        b"value" => value = Some(content),
+                        _ => {}
+                    }
+                }
+                if let (Some(name), Some(value)) = (name, value) {
+                    entries.push((name, value));
+                }
+            }
+            Ok(Event::Eof) => return Ok(entries),
+            Ok(_) => {}
+            Err(e) => {
+                return Err(std::io::Error::other(format!(
+                    "Invalid certificate configuration: {e}"
+                )));
+            }
+        }
+    }
+}
+
+/// Generates the RSA key pair for the client certificate
+fn generate_private_key(bits: u32) -> Result<PKey<Private>, std::io::Error> {
+    Ok(PKey::from_rsa(Rsa::generate(bits)?)?)
+}
+
+/// Builds the CSR for the key, returned as base64 DER without PEM armour as expected by the
+/// enrollment endpoint
+///
+/// # Arguments
+///
+/// * `private_key` - Key pair to request a certificate for
+/// * `common_name` - Common name of the certificate, the TAK server expects the username
+/// * `name_entries` - Additional subject name entries required by the server
+///
+fn build_csr(
+    private_key: &PKey<Private>,
+    common_name: &str,
+    name_entries: &[(String, String)],
+) -> Result<String, std::io::Error> {
+    let mut subject = X509NameBuilder::new()?;
+    for (name, value) in name_entries {
+        subject.append_entry_by_text(name, value)?;
+    }
+    subject.append_entry_by_text("CN", common_name)?;
+
+    let mut request = X509ReqBuilder::new()?;
+    request.set_pubkey(private_key)?;
+    request.set_subject_name(&subject.build())?;
+    request.sign(private_key, MessageDigest::sha256())?;
+
+    Ok(openssl::base64::encode_block(&request.build().to_der()?))
+}
+
+/// Parses the signed certificate and CA chain from the enrollment response
+fn parse_enrollment(
+    enrollment: &str,
+) -> Result<(CertificateDer<'static>, Vec<CertificateDer<'static>>), std::io::Error> {
+    let mut reader = quick_xml::Reader::from_str(enrollment);
+    let mut element = Vec::new();
+    let mut certificate = None;
+    let mut ca_chain = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(start)) => element = start.local_name().as_ref().to_vec(),
+            Ok(Event::End(_)) => element.clear(),
+            Ok(Event::Text(text)) if !element.is_empty() => {
+                let text = text
+                    .unescape()
+                    .map_err(|e| std::io::Error::other(format!("Invalid enrollment: {e}")))?;
+                if element == b"signedCert" {
+                    certificate = Some(decode_certificate(&text)?);
+                } else if is_ca_element(&element) {
+                    ca_chain.push(decode_certificate(&text)?);
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                return Err(std::io::Error::other(format!(
+                    "Invalid enrollment response: {e}"
+                )));
+            }
+        }
+    }
+
+    let certificate = certificate.ok_or(std::io::Error::other(
+        "Enrollment response did not contain a signed certificate",
+    ))?;
+    Ok((certificate, ca_chain))
+}
+
+/// Whether an element of the enrollment response holds a certificate of the CA chain, these are
+/// numbered from `ca0`
+fn is_ca_element(element: &[u8]) -> bool {
+    element
+        .strip_prefix(b"ca")
+        .is_some_and(|index| !index.is_empty() && index.iter().all(u8::is_ascii_digit))
+}
+
+/// Decodes a base64 DER certificate, with or without PEM armour
+fn decode_certificate(encoded: &str) -> Result<CertificateDer<'static>, std::io::Error> {
+    let base64: String = encoded
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .flat_map(|line| line.chars())
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let der = openssl::base64::decode_block(&base64)
+        .map_err(|e| std::io::Error::other(format!("Invalid certificate encoding: {e}")))?;
+    Ok(CertificateDer::from(der))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::Source;
+    use crate::test_util::{TestCa, server_config};
+    use openssl::x509::{X509, X509Req};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const USERNAME: &str = "operator";
+    const PASSWORD: &str = "s3cret:pass";
+    const CONFIG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<certificateConfig>
+    <nameEntries>
+        <nameEntry name="O" value="TAK"/>
+        <nameEntry name="OU" value="Field &amp; Ops"/>
+    </nameEntries>
+</certificateConfig>"#;
+
+    /// Starts an enrollment endpoint on 127.0.0.1 which signs CSRs with the CA, returning its
+    /// URL
+    async fn start_server(ca: Arc<TestCa>, chunked: bool) -> Url {
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config(
+            &ca.issue("takserver"),
+            None,
+            Vec::new(),
+        )));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("https://{}", listener.local_addr().unwrap())).unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut stream = acceptor.accept(stream).await.unwrap();
+                let (head, body) = read_request(&mut stream).await;
+                let response = if !head.contains(&expected_authorization()) {
+                    "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n".into()
+                } else if chunked {
+                    chunk(respond(&ca, &head, &body))
+                } else {
+                    let response = respond(&ca, &head, &body);
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{response}",
+                        response.len()
+                    )
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        url
+    }
+
+    fn expected_authorization() -> String {
+        let credentials = format!("{USERNAME}:{PASSWORD}");
+        format!(
+            "Authorization: Basic {}\r\n",
+            openssl::base64::encode_block(credentials.as_bytes())
+        )
+    }
+
+    /// Reads the request head and its body of Content-Length bytes
+    async fn read_request<S: tokio::io::AsyncRead + Unpin>(stream: &mut S) -> (String, Vec<u8>) {
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+        loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8(request[..end].to_vec()).unwrap();
+            let length: usize = head
+                .lines()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .map_or(0, |length| length.parse().unwrap());
+            if request.len() >= end + 4 + length {
+                return (head + "\r\n", request[end + 4..end + 4 + length].to_vec());
+            }
+        }
+    }
+
+    /// Answers the certificate configuration and signClient requests
+    fn respond(ca: &TestCa, head: &str, body: &[u8]) -> String {
+        let request_line = head.lines().next().unwrap();
+        if request_line.starts_with(&format!("GET {CONFIG_PATH} ")) {
+            return CONFIG.into();
+        }
+
+        assert!(request_line.starts_with(&format!(
+            "POST {SIGN_CLIENT_PATH}?clientUid=cot_publisher-{USERNAME}&version="
+        )));
+        let csr = openssl::base64::decode_block(std::str::from_utf8(body).unwrap()).unwrap();
+        let csr = X509Req::from_der(&csr).unwrap();
+        let subject: Vec<String> = csr
+            .subject_name()
+            .entries()
+            .map(|entry| entry.data().to_string().unwrap())
+            .collect();
+        assert_eq!(subject, ["TAK", "Field & Ops", USERNAME]);
+
+        let signed = ca.sign_request(&csr);
+        // The CA certificate is sent with its PEM armour, followed by an element which only
+        // starts like a CA chain element
+        format!(
+            "<enrollment><signedCert>{}</signedCert><ca0>{}</ca0>\
+             <caStore>not a certificate</caStore></enrollment>",
+            openssl::base64::encode_block(&signed.to_der().unwrap()),
+            ca.cert_pem()
+        )
+    }
+
+    /// Encodes a response body with chunked transfer encoding
+    fn chunk(body: String) -> String {
+        let mut response = String::from("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n");
+        for (index, chunk) in body.as_bytes().chunks(100).enumerate() {
+            let extension = if index == 0 { ";name=value" } else { "" };
+            response.push_str(&format!("{:x}{extension}\r\n", chunk.len()));
+            response.push_str(std::str::from_utf8(chunk).unwrap());
+            response.push_str("\r\n");
+        }
+        response + "0\r\n\r\n"
+    }
+
+    fn settings(ca: &TestCa, password: &str) -> EnrollmentSettings {
+        let mut settings = EnrollmentSettings::new(USERNAME, password);
+        settings.tls.root_cert = Some(Source::String(ca.cert_pem()));
+        settings
+    }
+
+    async fn assert_enrolls(chunked: bool) {
+        let ca = Arc::new(TestCa::new("TAK CA"));
+        let url = start_server(ca.clone(), chunked).await;
+
+        let credentials = enroll(&url, &settings(&ca, PASSWORD)).await.unwrap();
+
+        let certificate = X509::from_der(&credentials.certificate).unwrap();
+        assert!(certificate.verify(&ca.cert.public_key().unwrap()).unwrap());
+        let PrivateKeyDer::Pkcs8(private_key) = &credentials.private_key else {
+            panic!("Private key is not PKCS#8");
+        };
+        let private_key = PKey::private_key_from_pkcs8(private_key.secret_pkcs8_der()).unwrap();
+        assert!(certificate.public_key().unwrap().public_eq(&private_key));
+        assert_eq!(credentials.root_cert, Some(vec![ca.cert_der()]));
+    }
+
+    #[tokio::test]
+    async fn enroll_with_basic_authentication() {
+        assert_enrolls(false).await;
+    }
+
+    #[tokio::test]
+    async fn enroll_with_chunked_responses() {
+        assert_enrolls(true).await;
+    }
+
+    #[tokio::test]
+    async fn enroll_rejected_with_wrong_password() {
+        let ca = Arc::new(TestCa::new("TAK CA"));
+        let url = start_server(ca.clone(), false).await;
+
+        let error = enroll(&url, &settings(&ca, "wrong")).await.err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn ca_elements() {
+        assert!(is_ca_element(b"ca0"));
+        assert!(is_ca_element(b"ca12"));
+        assert!(!is_ca_element(b"ca"));
+        assert!(!is_ca_element(b"caStore"));
+        assert!(!is_ca_element(b"cacert"));
+    }
+}
@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides the settings for publishing to an ordered list of TAK servers, where
+//! the first server is the primary and the following servers are backups, and for falling back
+//! to a multicast group while no TAK server is connected.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use url::Url;
+
+/// Failover settings for a TAK server publisher with backup servers
+#[derive(Debug, Clone, Default)]
+pub struct FailoverSetting {
+    /// Hold-down period after failing over to a backup server, after which reconnecting to the
+    /// primary server is attempted while publishing continues through the backup. Failed
+    /// attempts are repeated after each period. When `None` the publisher stays on the backup
+    /// until its connection is lost
+    pub return_to_primary: Option<Duration>,
+    /// Optional multicast group messages are published to while no TAK server is connected,
+    /// when `None` messages wait for the connection to be established
+    pub multicast_fallback: Option<MulticastFallback>,
+}
+
+/// Multicast group used as the fallback transport of a TAK server publisher, such as the local
+/// SA mesh
+#[derive(Debug, Clone)]
+pub struct MulticastFallback {
+    /// Multicast group address and port, usually 239.2.3.1:6969
+    pub address: SocketAddr,
+    /// Optional name or IP address of the interface to send from
+    pub interface: Option<String>,
+    /// Optional time to live of the multicast packets
+    pub ttl: Option<u32>,
+}
+
+/// TAK server a publisher is connected to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveServer {
+    /// Position of the server in the list, the primary server is 0
+    pub index: usize,
+    /// URL of the server
+    pub url: Url,
+}
@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module frames TAK protocol messages on streaming connections.
+//!
+//! Each message on a TCP, TLS, WebSocket or QUIC stream is preceded by the magic byte and the
+//! length of the message as a varint.
+
+use std::io;
+use std::ops::Range;
+
+use crate::TCP_MAGIC;
+
+/// Maximum length of the varint encoded message length, message lengths are 32 bit
+const MAX_LENGTH_SIZE: usize = 5;
+
+/// Frames a TAK protocol message for a stream
+///
+/// # Arguments
+///
+/// * `payload` - Encoded TAK protocol message
+///
+pub(crate) fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut length = payload.len() as u32;
+    let mut framed = Vec::with_capacity(1 + MAX_LENGTH_SIZE + payload.len());
+    framed.extend(TCP_MAGIC);
+    while length >= 0x80 {
+        framed.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    framed.push(length as u8);
+    framed.extend_from_slice(payload);
+    framed
+}
+
+/// Decodes the framed message at the start of the buffer, returning the range of its payload
+/// or `None` when the buffer does not hold the complete message yet
+///
+/// The end of the payload range is the length of the whole framed message.
+///
+/// # Arguments
+///
+/// * `buffer` - Data read from the stream, starting at a message
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` with kind `InvalidData` if the buffer does not start with the
+/// magic byte or the message length is not a valid 32 bit varint
+///
+pub(crate) fn decode_frame(buffer: &[u8]) -> Result<Option<Range<usize>>, io::Error> {
+    let Some(&magic) = buffer.first() else {
+        return Ok(None);
+    };
+    if magic != TCP_MAGIC[0] {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "TAK protocol message does not start with the magic byte",
+        ));
+    }
+
+    let mut length: u64 = 0;
+    for (index, byte) in buffer[1..].iter().take(MAX_LENGTH_SIZE).enumerate() {
+        length |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 != 0 {
+            continue;
+        }
+
+        let length = u32::try_from(length).map_err(|_| invalid_length())? as usize;
+        let start = 2 + index;
+        return Ok((buffer.len() >= start + length).then_some(start..start + length));
+    }
+
+    if buffer.len() > MAX_LENGTH_SIZE {
+        return Err(invalid_length());
+    }
+    Ok(None)
+}
+
+/// Error for a message length which is not a valid 32 bit varint
+fn invalid_length() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Invalid TAK protocol message length",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_round_trip() {
+        for length in [0, 1, 127, 128, 300, 16_383, 16_384, 100_000] {
+            let payload = vec![0x5a; length];
+            let framed = frame(&payload);
+            let range = decode_frame(&framed).unwrap().unwrap();
+            assert_eq!(range.end, framed.len());
+            assert_eq!(&framed[range], payload.as_slice());
+        }
+
+        // 300 is 0b10_0101100, sent least significant group first
+        assert_eq!(frame(&[0; 300])[..3], [0xbf, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn incomplete_messages_are_not_decoded() {
+        let framed = frame(&[0x5a; 300]);
+        for end in 0..framed.len() {
+            assert_eq!(decode_frame(&framed[..end]).unwrap(), None, "{end} bytes");
+        }
+    }
+
+    #[test]
+    fn trailing_data_is_left_for_the_next_message() {
+        let mut stream = frame(b"first");
+        stream.extend(frame(b"second"));
+
+        let first = decode_frame(&stream).unwrap().unwrap();
+        assert_eq!(&stream[first.clone()], b"first");
+        let second = decode_frame(&stream[first.end..]).unwrap().unwrap();
+        assert_eq!(&stream[first.end..][second], b"second");
+    }
+
+    #[test]
+    fn invalid_frames_are_rejected() {
+        let error = decode_frame(b"<event/>").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // Six length bytes, and five bytes encoding more than 32 bits
+        for length in [&[0xff; 6][..], &[0xff, 0xff, 0xff, 0xff, 0x7f]] {
+            let framed = [&TCP_MAGIC[..], length].concat();
+            let error = decode_frame(&framed).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides the team (`__group`) of a COT entity, which ATAK shows as the team
+//! colour and role of a contact.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Team membership of a COT entity
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Group {
+    /// Team colour, sent as the group name
+    pub name: TeamColor,
+    /// Role within the team
+    pub role: TeamRole,
+}
+
+/// Standard ATAK team colours
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TeamColor {
+    White,
+    Yellow,
+    Orange,
+    Magenta,
+    Red,
+    Maroon,
+    Purple,
+    DarkBlue,
+    Blue,
+    #[default]
+    Cyan,
+    Teal,
+    Green,
+    DarkGreen,
+    Brown,
+}
+
+/// Standard ATAK team roles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TeamRole {
+    #[default]
+    TeamMember,
+    TeamLead,
+    Hq,
+    Sniper,
+    Medic,
+    ForwardObserver,
+    Rto,
+    K9,
+}
+
+impl TeamColor {
+    /// All team colours in the order ATAK lists them
+    pub const ALL: [TeamColor; 14] = [
+        TeamColor::White,
+        TeamColor::Yellow,
+        TeamColor::Orange,
+        TeamColor::Magenta,
+        TeamColor::Red,
+        TeamColor::Maroon,
+        TeamColor::Purple,
+        TeamColor::DarkBlue,
+        TeamColor::Blue,
+        TeamColor::Cyan,
+        TeamColor::Teal,
+        TeamColor::Green,
+        TeamColor::DarkGreen,
+        TeamColor::Brown,
+    ];
+
+    /// Name of the team colour as used in COT messages, e.g. `Dark Blue`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TeamColor::White => "White",
+            TeamColor::Yellow => "Yellow",
+            TeamColor::Orange => "Orange",
+            TeamColor::Magenta => "Magenta",
+            TeamColor::Red => "Red",
+            TeamColor::Maroon => "Maroon",
+            TeamColor::Purple => "Purple",
+            TeamColor::DarkBlue => "Dark Blue",
+            TeamColor::Blue => "Blue",
+            TeamColor::Cyan => "Cyan",
+            TeamColor::Teal => "Teal",
+            TeamColor::Green => "Green",
+            TeamColor::DarkGreen => "Dark Green",
+            TeamColor::Brown => "Brown",
+        }
+    }
+}
+
+impl TeamRole {
+    /// All team roles in the order ATAK lists them
+    pub const ALL: [TeamRole; 8] = [
+        TeamRole::TeamMember,
+        TeamRole::TeamLead,
+        TeamRole::Hq,
+        TeamRole::Sniper,
+        TeamRole::Medic,
+        TeamRole::ForwardObserver,
+        TeamRole::Rto,
+        TeamRole::K9,
+    ];
+
+    /// Name of the role as used in COT messages, e.g. `Team Lead`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TeamRole::TeamMember => "Team Member",
+            TeamRole::TeamLead => "Team Lead",
+            TeamRole::Hq => "HQ",
+            TeamRole::Sniper => "Sniper",
+            TeamRole::Medic => "Medic",
+            TeamRole::ForwardObserver => "Forward Observer",
+            TeamRole::Rto => "RTO",
+            TeamRole::K9 => "K9",
+        }
+    }
+}
+
+impl fmt::Display for TeamColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for TeamRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TeamColor {
+    type Err = std::io::Error;
+
+    /// Parses a team colour by its COT name, ignoring case
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        TeamColor::ALL
+            .into_iter()
+            .find(|color| color.as_str().eq_ignore_ascii_case(name.trim()))
+            .ok_or(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unknown team colour {name}"),
+            ))
+    }
+}
+
+impl FromStr for TeamRole {
+    type Err = std::io::Error;
+
+    /// Parses a team role by its COT name, ignoring case
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        TeamRole::ALL
+            .into_iter()
+            .find(|role| role.as_str().eq_ignore_ascii_case(name.trim()))
+            .ok_or(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unknown team role {name}"),
+            ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn team_colors_round_trip() {
+        for color in TeamColor::ALL {
+            assert_eq!(color.as_str().parse::<TeamColor>().unwrap(), color);
+            assert_eq!(color.to_string(), color.as_str());
+        }
+    }
+
+    #[test]
+    fn team_roles_round_trip() {
+        for role in TeamRole::ALL {
+            assert_eq!(role.as_str().parse::<TeamRole>().unwrap(), role);
+            assert_eq!(role.to_string(), role.as_str());
+        }
+    }
+
+    #[test]
+    fn parse_ignores_case_and_whitespace() {
+        assert_eq!(
+            " dark blue ".parse::<TeamColor>().unwrap(),
+            TeamColor::DarkBlue
+        );
+        assert_eq!("CYAN".parse::<TeamColor>().unwrap(), TeamColor::Cyan);
+        assert_eq!("team lead".parse::<TeamRole>().unwrap(), TeamRole::TeamLead);
+        assert_eq!("hq\n".parse::<TeamRole>().unwrap(), TeamRole::Hq);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_names() {
+        assert!("DarkBlue".parse::<TeamColor>().is_err());
+        assert!("Black".parse::<TeamColor>().is_err());
+        assert!("Pilot".parse::<TeamRole>().is_err());
+        assert!("".parse::<TeamRole>().is_err());
+    }
+}
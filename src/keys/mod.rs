@@ -4,16 +4,12 @@
 //! This module provides an interface for handling PEM-encoded keys and certificates.
 
 use pkcs8::{DecodePrivateKey, Error, PrivateKeyInfo, der::Encode};
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer};
 
-use openssl::pkcs12::Pkcs12;
+use openssl::pkcs12::{ParsedPkcs12_2, Pkcs12};
 use openssl::pkey::PKey;
-use openssl::pkey::Private;
-use openssl::stack::Stack;
 use openssl::x509::X509;
-use std::fs::File;
-use std::io::Read;
-
+use std::time::{Duration, SystemTime};
 
 /// Source for PEM file data
 pub enum Source {
@@ -37,6 +33,14 @@ impl Source {
             Source::String(content) => Ok(content.clone()),
         }
     }
+
+    // Loads raw content from the provided source, used for data which may be DER encoded
+    pub fn load_bytes(&self) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Source::File(path) => std::fs::read(path),
+            _ => Ok(self.load()?.into_bytes()),
+        }
+    }
 }
 
 /// MockKey is a simple wrapper around a Vec<u8> to represent a private key.
@@ -66,6 +70,27 @@ pub struct Credentials<'a> {
     pub root_cert: Option<Vec<CertificateDer<'a>>>,
 }
 
+impl Clone for Credentials<'_> {
+    fn clone(&self) -> Self {
+        Self {
+            certificate: self.certificate.clone(),
+            private_key: self.private_key.clone_key(),
+            root_cert: self.root_cert.clone(),
+        }
+    }
+}
+
+/// Validity state of a client certificate relative to the current time
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CertificateExpiry {
+    /// Certificate is valid for longer than the warning period
+    Valid { remaining: Duration },
+    /// Certificate expires within the warning period
+    ExpiringSoon { remaining: Duration },
+    /// Certificate has already expired
+    Expired { since: Duration },
+}
+
 impl<'a> Credentials<'a> {
     /// Creates Credentials from unencrypted PEM strings or files
     ///
@@ -152,7 +177,8 @@ impl<'a> Credentials<'a> {
             .ok_or("No certificate found")?;
 
         let key_pem = private_key.load()?;
-        let decrypted_key = MockKey::from_pkcs8_encrypted_pem(&key_pem, password).unwrap();
+        let decrypted_key = MockKey::from_pkcs8_encrypted_pem(&key_pem, password)
+            .map_err(|e| format!("Failed to decrypt private key: {e}"))?;
         let private_key = PrivateKeyDer::try_from(decrypted_key.as_ref().to_owned())?;
         Ok(Self {
             certificate,
@@ -160,6 +186,183 @@ impl<'a> Credentials<'a> {
             root_cert: None,
         })
     }
+
+    /// Creates Credentials from a DER-encoded PKCS#12 archive, the CA chain in the archive is
+    /// used as the root certificate
+    ///
+    /// # Arguments
+    ///
+    /// * `pkcs12_data` - DER-encoded PKCS#12 archive holding the certificate and private key
+    /// * `password` - Password protecting the archive
+    ///
+    pub fn from_pkcs12(
+        pkcs12_data: &[u8],
+        password: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let parsed = parse_pkcs12(pkcs12_data, password)?;
+        let mut credentials = Self::from_unencrypted_pem(
+            Source::String(pkcs12_cert_to_string(&parsed)?),
+            Source::String(pkcs12_key_to_string(&parsed)?),
+        )?;
+
+        let ca_pem = pkcs12_ca_root_to_string(&parsed)?;
+        if !ca_pem.is_empty() {
+            credentials.root_cert = Some(parse_certificates(ca_pem)?);
+        }
+
+        Ok(credentials)
+    }
+
+    /// Saves the credentials as PEM files, the private key is written as unencrypted PKCS#8
+    /// and is only readable by the owner on Unix systems
+    ///
+    /// # Arguments
+    ///
+    /// * `certificate` - Path to write the client certificate to
+    /// * `private_key` - Path to write the private key to
+    /// * `root_cert` - Optional path to write the root certificate chain to
+    ///
+    pub fn save_pem(
+        &self,
+        certificate: &str,
+        private_key: &str,
+        root_cert: Option<&str>,
+    ) -> Result<(), std::io::Error> {
+        std::fs::write(certificate, X509::from_der(&self.certificate)?.to_pem()?)?;
+
+        let key_pem = PKey::private_key_from_der(self.private_key.secret_der())?
+            .private_key_to_pem_pkcs8()?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(private_key)?, &key_pem)?;
+
+        if let (Some(path), Some(root_certs)) = (root_cert, &self.root_cert) {
+            let mut pem = Vec::new();
+            for cert in root_certs {
+                pem.append(&mut X509::from_der(cert)?.to_pem()?);
+            }
+            std::fs::write(path, pem)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the private key belongs to the public key of the client certificate
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` with kind `InvalidData` if the key does not match the
+    /// certificate, or if either cannot be parsed
+    ///
+    pub fn check_key_matches(&self) -> Result<(), std::io::Error> {
+        let public_key = X509::from_der(&self.certificate)?.public_key()?;
+        let private_key = PKey::private_key_from_der(self.private_key.secret_der())?;
+        if !public_key.public_eq(&private_key) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Private key does not match the client certificate",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the time at which the client certificate expires (X.509 notAfter)
+    pub fn not_after(&self) -> Result<SystemTime, std::io::Error> {
+        let certificate = X509::from_der(&self.certificate)?;
+        let epoch = openssl::asn1::Asn1Time::from_unix(0)?;
+        let diff = epoch.diff(certificate.not_after())?;
+        let seconds = diff.days as i64 * 86400 + diff.secs as i64;
+        if seconds < 0 {
+            return Err(std::io::Error::other(
+                "Certificate expiry is before the epoch",
+            ));
+        }
+        Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds as u64))
+    }
+
+    /// Checks the client certificate validity against the current time
+    ///
+    /// # Arguments
+    ///
+    /// * `warning_days` - Number of days before expiry at which the certificate is reported as
+    ///   expiring soon
+    ///
+    pub fn check_expiry(&self, warning_days: u32) -> Result<CertificateExpiry, std::io::Error> {
+        let not_after = self.not_after()?;
+        match not_after.duration_since(SystemTime::now()) {
+            Ok(remaining) if remaining <= Duration::from_secs(warning_days as u64 * 86400) => {
+                Ok(CertificateExpiry::ExpiringSoon { remaining })
+            }
+            Ok(remaining) => Ok(CertificateExpiry::Valid { remaining }),
+            Err(e) => Ok(CertificateExpiry::Expired {
+                since: e.duration(),
+            }),
+        }
+    }
+}
+
+/// Credential files which are reloaded when they change on disk
+pub struct CredentialFiles {
+    /// Path to the PEM-encoded client certificate
+    pub certificate: String,
+    /// Path to the PEM-encoded private key
+    pub private_key: String,
+    /// Password for an encrypted private key, `None` for an unencrypted key
+    pub password: Option<String>,
+    last_modified: Option<SystemTime>,
+}
+
+impl CredentialFiles {
+    /// Creates a new set of credential files to watch
+    ///
+    /// # Arguments
+    ///
+    /// * `certificate` - Path to the PEM-encoded client certificate
+    /// * `private_key` - Path to the PEM-encoded private key
+    /// * `password` - Password for an encrypted private key
+    ///
+    pub fn new(certificate: &str, private_key: &str, password: Option<&str>) -> Self {
+        Self {
+            certificate: certificate.into(),
+            private_key: private_key.into(),
+            password: password.map(|p| p.into()),
+            last_modified: None,
+        }
+    }
+
+    /// Loads the credentials if either file has been modified since the last successful load
+    ///
+    /// Returns `Ok(None)` when the files are unchanged. Credentials whose private key does not
+    /// match the certificate, such as while only one of the files has been replaced, are
+    /// rejected and loaded again on the next call.
+    pub fn reload_if_modified(&mut self) -> Result<Option<Credentials<'static>>, std::io::Error> {
+        let modified = std::fs::metadata(&self.certificate)?
+            .modified()?
+            .max(std::fs::metadata(&self.private_key)?.modified()?);
+        if self.last_modified == Some(modified) {
+            return Ok(None);
+        }
+
+        let certificate = Source::File(self.certificate.clone());
+        let private_key = Source::File(self.private_key.clone());
+        let credentials = match &self.password {
+            Some(password) => Credentials::from_encrypted_pem(certificate, private_key, password),
+            None => Credentials::from_unencrypted_pem(certificate, private_key),
+        }
+        .map_err(|e| std::io::Error::other(e.to_string()))
+        .and_then(|credentials| credentials.check_key_matches().map(|_| credentials))
+        .map_err(|e| {
+            std::io::Error::other(format!(
+                "Failed to load credentials from {}: {e}",
+                self.certificate
+            ))
+        })?;
+
+        self.last_modified = Some(modified);
+        Ok(Some(credentials))
+    }
 }
 
 /// Parses PEM-encoded certificates from a string
@@ -177,108 +380,279 @@ pub fn parse_certificates<'a>(cert_pem: String) -> Result<Vec<CertificateDer<'a>
     Ok(certs)
 }
 
-// -----
-
-pub fn read_pkcs12_cert_to_string(
-    pkcs12_filename: &str,
-    pkcs12_passwd: &str,
-) -> Result<String, std::io::Error> {
+/// Parses certificate revocation lists, PEM-encoded lists are detected by their header and
+/// anything else is treated as a single DER-encoded list
+///
+/// # Arguments
+///
+/// * `crl_data` - PEM or DER encoded CRL data
+///
+pub fn parse_crls(
+    crl_data: Vec<u8>,
+) -> Result<Vec<CertificateRevocationListDer<'static>>, std::io::Error> {
+    const PEM_HEADER: &[u8] = b"-----BEGIN X509 CRL-----";
+    if !crl_data.windows(PEM_HEADER.len()).any(|w| w == PEM_HEADER) {
+        return Ok(vec![CertificateRevocationListDer::from(crl_data)]);
+    }
 
-    // Load the PKCS#12 file
-    let mut file = File::open(&pkcs12_filename)
-        .expect(format!("Failed to open PKCS12 file: {}", pkcs12_filename).as_str());
-
-    let mut pkcs12_data = Vec::new();
-    file.read_to_end(&mut pkcs12_data)
-        .expect("Failed to read PKCS12 file");
-
-    // Load the identity using the PKCS#12 file and password
-    let parsed = Pkcs12::from_der(&pkcs12_data)?
-        .parse2(&pkcs12_passwd)
-        .expect(format!("Failed to load identity: {}", pkcs12_filename).as_str());
-
-    // These are accessible:
-    let leaf_cert:Option<X509> = parsed.cert; // openssl::x509::X509
-    let cert_pem_string:String;
-        
-    if let Some(cert) = leaf_cert {
-        let cert_pem_bytes = cert.to_pem()?;
-        cert_pem_string = String::from_utf8(cert_pem_bytes)
-            .expect("failed to convert to utf8");
-    } else {
-        cert_pem_string = "".to_string();
+    let mut crl_reader = std::io::BufReader::new(crl_data.as_slice());
+    let mut crls = Vec::new();
+    for crl_result in rustls_pemfile::crls(&mut crl_reader) {
+        crls.push(crl_result?);
     }
+    Ok(crls)
+}
 
-    Ok(cert_pem_string)
+// -----
+
+/// Parses a DER-encoded PKCS#12 archive
+///
+/// # Arguments
+///
+/// * `pkcs12_data` - DER-encoded PKCS#12 archive
+/// * `pkcs12_passwd` - Password protecting the archive
+///
+pub fn parse_pkcs12(
+    pkcs12_data: &[u8],
+    pkcs12_passwd: &str,
+) -> Result<ParsedPkcs12_2, std::io::Error> {
+    Pkcs12::from_der(pkcs12_data)?
+        .parse2(pkcs12_passwd)
+        .map_err(|e| std::io::Error::other(format!("Failed to load PKCS12 identity: {e}")))
 }
 
-pub fn read_pkcs12_key_to_string(
+/// Loads and parses a PKCS#12 file
+fn read_pkcs12_file(
     pkcs12_filename: &str,
     pkcs12_passwd: &str,
-) -> Result<String, std::io::Error> {
-
-    // Load the PKCS#12 file
-    let mut file = File::open(&pkcs12_filename)
-        .expect(format!("Failed to open PKCS12 file: {}", pkcs12_filename).as_str());
-
-    let mut pkcs12_data = Vec::new();
-    file.read_to_end(&mut pkcs12_data)
-        .expect("Failed to read PKCS12 file");
+) -> Result<ParsedPkcs12_2, std::io::Error> {
+    let pkcs12_data = std::fs::read(pkcs12_filename).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("Failed to open PKCS12 file {pkcs12_filename}: {e}"),
+        )
+    })?;
+
+    parse_pkcs12(&pkcs12_data, pkcs12_passwd).map_err(|e| {
+        std::io::Error::other(format!("Failed to load identity {pkcs12_filename}: {e}"))
+    })
+}
 
-    // Load the identity using the PKCS#12 file and password
-    let parsed = Pkcs12::from_der(&pkcs12_data)?
-        .parse2(&pkcs12_passwd)
-        .expect(format!("Failed to load identity: {}", pkcs12_filename).as_str());
+/// Converts PEM bytes produced by OpenSSL to a string
+fn pem_to_string(pem: Vec<u8>) -> Result<String, std::io::Error> {
+    String::from_utf8(pem).map_err(|e| std::io::Error::other(format!("Invalid PEM data: {e}")))
+}
 
-    let private_key:Option<PKey<Private>> = parsed.pkey; // openssl::pkey::PKey
+/// Returns the leaf certificate of a parsed PKCS#12 archive as PEM, or an empty string
+pub fn pkcs12_cert_to_string(parsed: &ParsedPkcs12_2) -> Result<String, std::io::Error> {
+    match &parsed.cert {
+        Some(cert) => pem_to_string(cert.to_pem()?),
+        None => Ok(String::new()),
+    }
+}
 
-    let pkey_pem_string:String;
+/// Returns the private key of a parsed PKCS#12 archive as PKCS#8 PEM, or an empty string
+pub fn pkcs12_key_to_string(parsed: &ParsedPkcs12_2) -> Result<String, std::io::Error> {
+    match &parsed.pkey {
+        Some(pkey) => pem_to_string(pkey.private_key_to_pem_pkcs8()?),
+        None => Ok(String::new()),
+    }
+}
 
-    if let Some(pkey) = private_key {
-        let pkey_pem_bytes = pkey.private_key_to_pem_pkcs8()?;
-        pkey_pem_string = String::from_utf8(pkey_pem_bytes)
-            .expect("failed to convert to utf8");
-    } else {
-        pkey_pem_string = "".to_string();
+/// Returns the CA chain of a parsed PKCS#12 archive as concatenated PEM, or an empty string
+pub fn pkcs12_ca_root_to_string(parsed: &ParsedPkcs12_2) -> Result<String, std::io::Error> {
+    let mut ca_cert_pem_string = String::new();
+    if let Some(ca) = &parsed.ca {
+        for cert in ca {
+            ca_cert_pem_string.push_str(&pem_to_string(cert.to_pem()?)?);
+        }
     }
+    Ok(ca_cert_pem_string)
+}
 
-    Ok(pkey_pem_string)
-        
+pub fn read_pkcs12_cert_to_string(
+    pkcs12_filename: &str,
+    pkcs12_passwd: &str,
+) -> Result<String, std::io::Error> {
+    pkcs12_cert_to_string(&read_pkcs12_file(pkcs12_filename, pkcs12_passwd)?)
+}
+
+pub fn read_pkcs12_key_to_string(
+    pkcs12_filename: &str,
+    pkcs12_passwd: &str,
+) -> Result<String, std::io::Error> {
+    pkcs12_key_to_string(&read_pkcs12_file(pkcs12_filename, pkcs12_passwd)?)
 }
 
 pub fn read_pkcs12_ca_root_to_string(
     pkcs12_filename: &str,
     pkcs12_passwd: &str,
 ) -> Result<String, std::io::Error> {
+    pkcs12_ca_root_to_string(&read_pkcs12_file(pkcs12_filename, pkcs12_passwd)?)
+}
+
+/// Returns every certificate in a PKCS#12 archive as concatenated PEM, used for truststores
+/// which hold only CA certificates
+///
+/// # Arguments
+///
+/// * `pkcs12_data` - DER-encoded PKCS#12 archive
+/// * `pkcs12_passwd` - Password protecting the archive
+///
+pub fn pkcs12_certificates_to_string(
+    pkcs12_data: &[u8],
+    pkcs12_passwd: &str,
+) -> Result<String, std::io::Error> {
+    let parsed = parse_pkcs12(pkcs12_data, pkcs12_passwd)?;
+    let mut pem = pkcs12_cert_to_string(&parsed)?;
+    pem.push_str(&pkcs12_ca_root_to_string(&parsed)?);
+    Ok(pem)
+}
 
-    // Load the PKCS#12 file
-    let mut file = File::open(&pkcs12_filename)
-        .expect(format!("Failed to open PKCS12 file: {}", pkcs12_filename).as_str());
-
-    let mut pkcs12_data = Vec::new();
-    file.read_to_end(&mut pkcs12_data)
-        .expect("Failed to read PKCS12 file");
-
-    // Load the identity using the PKCS#12 file and password
-    let parsed = Pkcs12::from_der(&pkcs12_data)?
-        .parse2(&pkcs12_passwd)
-        .expect(format!("Failed to load identity: {}", pkcs12_filename).as_str());
-
-    let mut ca_cert_pem_string:String;
-    let ca_chain:Option<Stack<X509>> = parsed.ca; // Option<Vec<openssl::x509::X509>>
-        
-    if let Some(ca) = ca_chain {
-        ca_cert_pem_string = "".to_string();
-        for cert in ca.into_iter() {
-            let ca_cert_pem_bytes = cert.to_pem()?;
-            ca_cert_pem_string = String::from_utf8(ca_cert_pem_bytes)
-                .expect("failed to convert to utf8");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TestCa, TestIdentity};
+    use openssl::x509::X509Crl;
+    use std::path::Path;
+
+    /// Writes the certificate of one identity and the key of another as PEM files, setting
+    /// their modification time
+    fn write_files(
+        directory: &Path,
+        certificate: &TestIdentity,
+        key: &TestIdentity,
+        modified: SystemTime,
+    ) -> CredentialFiles {
+        let certificate_path = directory.join("client.pem");
+        let key_path = directory.join("client.key");
+        for (path, pem) in [
+            (&certificate_path, certificate.cert_pem()),
+            (&key_path, key.key_pem()),
+        ] {
+            std::fs::write(path, pem).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
         }
-    } else {
-        ca_cert_pem_string = "".to_string();
+        CredentialFiles::new(
+            certificate_path.to_str().unwrap(),
+            key_path.to_str().unwrap(),
+            None,
+        )
     }
 
-    Ok(ca_cert_pem_string)
+    #[test]
+    fn parse_crls_loads_der_and_pem() {
+        let first = TestCa::new("First CA").crl(&[]);
+        let second = TestCa::new("Second CA").crl(&[]);
+
+        let crls = parse_crls(first.clone()).unwrap();
+        assert_eq!(
+            crls,
+            vec![CertificateRevocationListDer::from(first.clone())]
+        );
+
+        let mut pem = X509Crl::from_der(&first).unwrap().to_pem().unwrap();
+        pem.extend(X509Crl::from_der(&second).unwrap().to_pem().unwrap());
+        let crls = parse_crls(pem).unwrap();
+        assert_eq!(
+            crls,
+            vec![
+                CertificateRevocationListDer::from(first),
+                CertificateRevocationListDer::from(second)
+            ]
+        );
+    }
 
-}
+    #[test]
+    fn credential_files_are_reloaded_when_modified() {
+        let ca = TestCa::new("TAK CA");
+        let (first, second) = (ca.issue("first"), ca.issue("second"));
+        let directory = tempfile::tempdir().unwrap();
+        let start = SystemTime::now() - Duration::from_secs(3600);
+
+        let mut files = write_files(directory.path(), &first, &first, start);
+        let loaded = files.reload_if_modified().unwrap().unwrap();
+        assert_eq!(loaded.certificate, first.cert_der());
+        assert!(files.reload_if_modified().unwrap().is_none());
+
+        // Only the modification time is compared, not the content
+        write_files(directory.path(), &second, &second, start);
+        assert!(files.reload_if_modified().unwrap().is_none());
+
+        write_files(
+            directory.path(),
+            &second,
+            &second,
+            start + Duration::from_secs(60),
+        );
+        let loaded = files.reload_if_modified().unwrap().unwrap();
+        assert_eq!(loaded.certificate, second.cert_der());
+        assert_eq!(
+            loaded.private_key.secret_der(),
+            second.key_der().secret_der()
+        );
+        assert!(files.reload_if_modified().unwrap().is_none());
+    }
+
+    #[test]
+    fn mismatched_credential_files_are_loaded_again() {
+        let ca = TestCa::new("TAK CA");
+        let (first, second) = (ca.issue("first"), ca.issue("second"));
+        let directory = tempfile::tempdir().unwrap();
+        let start = SystemTime::now() - Duration::from_secs(3600);
+
+        let mut files = write_files(directory.path(), &first, &first, start);
+        files.reload_if_modified().unwrap().unwrap();
+
+        // The certificate has been replaced before the key
+        let modified = start + Duration::from_secs(60);
+        write_files(directory.path(), &second, &first, modified);
+        assert!(files.reload_if_modified().is_err());
+        assert!(files.reload_if_modified().is_err());
+
+        write_files(directory.path(), &second, &second, modified);
+        let loaded = files.reload_if_modified().unwrap().unwrap();
+        assert_eq!(loaded.certificate, second.cert_der());
+    }
 
+    #[test]
+    fn key_must_match_the_certificate() {
+        let ca = TestCa::new("TAK CA");
+        let (first, second) = (ca.issue("first"), ca.issue("second"));
+        first.credentials().check_key_matches().unwrap();
+
+        let mismatched = Credentials {
+            private_key: second.key_der(),
+            ..first.credentials()
+        };
+        let error = mismatched.check_key_matches().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn expiry_is_reported_within_the_warning_period() {
+        let ca = TestCa::new("TAK CA");
+        let day = Duration::from_secs(86_400);
+
+        let credentials = ca.issue_expiring("client", 10 * 86_400).credentials();
+        let CertificateExpiry::Valid { remaining } = credentials.check_expiry(9).unwrap() else {
+            panic!("Certificate expiring in 10 days is not valid with a 9 day warning");
+        };
+        assert!(remaining > 9 * day && remaining <= 10 * day);
+        assert!(matches!(
+            credentials.check_expiry(10).unwrap(),
+            CertificateExpiry::ExpiringSoon { remaining } if remaining > 9 * day
+        ));
+
+        let credentials = ca.issue_expiring("client", -2 * 86_400).credentials();
+        let CertificateExpiry::Expired { since } = credentials.check_expiry(30).unwrap() else {
+            panic!("Certificate which expired 2 days ago is not expired");
+        };
+        assert!(since >= 2 * day && since < 3 * day);
+    }
+}
@@ -2,17 +2,22 @@
 // Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
 
 //! This crate provides an interface for publishing Cursor on Target (COT) messages
-//! to multicast addresses or TAK servers over TCP/TLS.
+//! to multicast addresses, UDP hosts or TAK servers over TCP/TLS.
 //!
-//! There are two features available:
+//! The following features are available:
 //!
 //! * `blocking` - Provides a blocking interface for use in non-async applications
-//! * `emit_errors` - Enables error logging using the `log` crate
+//! * `emit_errors` - Enables error and warning logging using the `log` crate
+//! * `aws_lc_rs` (default) - Uses the aws-lc-rs crypto provider for TLS connections
+//! * `ring` - Makes the ring crypto provider available, it is used for TLS connections when
+//!   `aws_lc_rs` is disabled
+//! * `quic` - Enables `quic://` TAK server connections, requires `aws_lc_rs` or `ring`
 //!
 //! The blocking implementation runs a Tokio runtime in a separate thread to handle
 //! async operations.
 //!
-//! The emit_errors feature allows the library to log errors using the `log` crate.
+//! The emit_errors feature allows the library to log errors and warnings (such as client
+//! certificates nearing expiry) using the `log` crate.
 //! This is disabled by default to avoid unnecessary dependencies in applications.
 //!
 //! # Examples
@@ -41,27 +46,77 @@
 //! cot.set_contact(Some("CALLSIGN"), None);
 //! cot.blocking_publish().unwrap();
 //! ```
+//!
+//! ## Destination URLs
+//!
+//! ```
+//! use cot_publisher::{CotPublisher, CursorOnTarget};
+//!
+//! async fn example() {
+//!     // Also accepts ssl://, tcp://, udp:// and ATAK host:port:proto connect strings
+//!     let publisher = CotPublisher::connect("mcast://239.2.3.1:6969?ttl=4").unwrap();
+//!     let mut cot = publisher.create_cot("unique-uid", "a-f-G-U").unwrap();
+//!     cot.set_position(51.5074, -0.1278);
+//!     cot.publish().await.unwrap();
+//! }
+//! ```
 
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 use prost::Message;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use url::Url;
-use varint_rs::VarintWriter;
 
 #[cfg(feature = "blocking")]
 pub mod blocking;
 mod connection;
+mod cot_type;
 mod cursor_on_target;
+mod data_package;
+mod delivery;
+mod destination;
+mod detail;
+mod device;
+mod enrollment;
+mod failover;
+mod framing;
+mod group;
 mod keys;
+mod marker;
+mod sidc;
+#[cfg(test)]
+mod test_util;
+mod track;
+mod udp;
 
 // Re-export modules for library users
-pub use crate::connection::TakServerSetting;
+pub use crate::connection::{
+    CertificatePin, ClientConfigHook, ProxyProtocol, ProxySettings, RevocationSettings,
+    SocketOptions, TakServerSetting, TcpKeepalive, TimeoutSettings, TlsOptions, TlsVersion,
+};
+pub use cot_type::{Affiliation, CotType, Dimension};
 pub use cursor_on_target::*;
-pub use keys::{Credentials, Source};
+pub use data_package::{DataPackageServer, load_data_package, load_data_package_from_reader};
+pub use destination::Destination;
+pub use detail::DetailConflict;
+#[cfg(target_os = "linux")]
+pub use device::read_battery_level;
+pub use device::{Status, Takv, read_battery_level_from};
+pub use enrollment::{EnrollmentSettings, enroll};
+pub use failover::{ActiveServer, FailoverSetting, MulticastFallback};
+pub use group::{Group, TeamColor, TeamRole};
+pub use keys::{CertificateExpiry, CredentialFiles, Credentials, Source};
+pub use marker::{Color, Marker};
+pub use sidc::{Sidc, SymbolStatus};
+pub use track::Track;
+pub use udp::{PayloadFormat, UdpSetting};
+// Re-exported so custom TLS configurations use the same rustls version as this crate
+pub use rustls;
 
 const UDP_MAGIC: [u8; 3] = [0xbf, 0x01, 0xbf]; // Magic bytes for UDP TAK_PROTO
 const TCP_MAGIC: [u8; 1] = [0xbf]; // Magic byte for TCP TAK_PROTO
+const XML_EVENT_END: &[u8] = b"</event>"; // End of the XML events of the protocol negotiation
+const MAX_XML_EVENT_LENGTH: usize = 64 * 1024; // Longest XML event read while negotiating
 pub(crate) const BROADCAST_CHANNEL_SIZE: usize = 1000; // Size of the broadcast channel buffer
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -70,18 +125,54 @@ pub enum PublishError {
     SendError(String),
     #[error("Connection error: {0}")]
     ConnectionError(String),
+    #[error("Connecting to TAK server timed out after {0:?}")]
+    ConnectTimeout(std::time::Duration),
+    #[error("Handshake with TAK server timed out after {0:?}")]
+    HandshakeTimeout(std::time::Duration),
+    #[error("Switching to the TAK protocol timed out after {0:?}")]
+    NegotiationTimeout(std::time::Duration),
+    #[error("Writing COT message timed out after {0:?}")]
+    WriteTimeout(std::time::Duration),
+    #[error("TAK server did not confirm delivery within {0:?}")]
+    ConfirmationTimeout(std::time::Duration),
+    #[error("COT message was not delivered within {0:?}")]
+    DeliveryTimeout(std::time::Duration),
+}
+
+/// Transport a published COT message was delivered through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryPath {
+    /// Written to the TAK server connection
+    TakServer,
+    /// Sent to a multicast group, either by a multicast publisher or as the fallback of a TAK
+    /// server publisher
+    Multicast,
+    /// Sent to a UDP unicast host
+    Udp,
 }
 
 /// Type alias for the complex channel sender type
 pub(crate) type CotSender = (
     CursorOnTarget,
-    Option<tokio::sync::oneshot::Sender<Result<(), PublishError>>>,
+    Option<tokio::sync::oneshot::Sender<Result<DeliveryPath, PublishError>>>,
 );
 
+/// Type alias for the channel used to replace the credentials of a running TAK server publisher
+pub(crate) type CredentialSender = tokio::sync::watch::Sender<Option<Credentials<'static>>>;
+
+/// Type alias for the channel reporting the expiry state of the credentials in use
+pub(crate) type ExpiryReceiver = tokio::sync::watch::Receiver<Option<CertificateExpiry>>;
+
+/// Type alias for the channel reporting the TAK server a publisher is connected to
+pub(crate) type ActiveReceiver = tokio::sync::watch::Receiver<Option<ActiveServer>>;
+
 // Publishes COT messages to multicast or TCP targets
 pub struct CotPublisher {
     broadcast_sender: Option<tokio::sync::mpsc::Sender<CotSender>>,
     publish_task: Option<tokio::task::JoinHandle<Result<(), PublishError>>>,
+    credential_sender: Option<CredentialSender>,
+    expiry_receiver: Option<ExpiryReceiver>,
+    active_receiver: Option<ActiveReceiver>,
 }
 
 const PROTOCOL_CHANGE: &str = r"<event version='2.0' uid='protouid' type='t-x-takp-q' time='TIME' start='TIME' stale='TIME' how='m-g'>
@@ -133,16 +224,12 @@ impl CotPublisher {
     /// * `bind_address` - Local IP address for interface to bind to
     ///
     pub fn new_multicast_bind(address: IpAddr, port: u16, bind_address: IpAddr) -> Self {
-        let (sender, receiver) = tokio::sync::mpsc::channel::<CotSender>(BROADCAST_CHANNEL_SIZE);
-        Self {
-            broadcast_sender: Some(sender),
-            publish_task: Some(tokio::task::spawn(multicast_publisher_task(
-                address,
-                port,
-                bind_address,
-                receiver,
-            ))),
-        }
+        CotPublisher::new_multicast_bind_custom_channel_capacity(
+            address,
+            port,
+            bind_address,
+            BROADCAST_CHANNEL_SIZE,
+        )
     }
 
     /// Create a new publisher using multicast with defined bind target, this can be used to
@@ -162,16 +249,138 @@ impl CotPublisher {
         port: u16,
         bind_address: IpAddr,
         channel_capacity: usize,
+    ) -> Self {
+        CotPublisher::new_multicast_task(
+            SocketAddr::new(address, port),
+            bind_address,
+            None,
+            channel_capacity,
+        )
+    }
+
+    /// Create a new publisher sending UDP datagrams to a unicast host, such as the UDP input
+    /// port of a TAK server
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - Host name or IP address of the receiver
+    /// * `port` - Port to address packets to, usually 8087
+    /// * `settings` - Settings for the UDP publisher, including the payload format
+    ///
+    pub fn new_udp(host: &str, port: u16, settings: UdpSetting) -> Self {
+        CotPublisher::new_udp_custom_channel_capacity(host, port, settings, BROADCAST_CHANNEL_SIZE)
+    }
+
+    /// Create a new publisher sending UDP datagrams to a unicast host, such as the UDP input
+    /// port of a TAK server
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - Host name or IP address of the receiver
+    /// * `port` - Port to address packets to, usually 8087
+    /// * `settings` - Settings for the UDP publisher, including the payload format
+    /// * `channel_capacity` - Size of the broadcast channel buffer
+    ///
+    pub fn new_udp_custom_channel_capacity(
+        host: &str,
+        port: u16,
+        settings: UdpSetting,
+        channel_capacity: usize,
     ) -> Self {
         let (sender, receiver) = tokio::sync::mpsc::channel::<CotSender>(channel_capacity);
         Self {
             broadcast_sender: Some(sender),
-            publish_task: Some(tokio::task::spawn(multicast_publisher_task(
+            publish_task: Some(tokio::task::spawn(udp_publisher_task(
+                host.to_owned(),
+                port,
+                settings,
+                receiver,
+            ))),
+            credential_sender: None,
+            expiry_receiver: None,
+            active_receiver: None,
+        }
+    }
+
+    /// Create a new publisher from a destination URL or ATAK connect string
+    ///
+    /// The destination selects the transport, `ssl://` and `tcp://` connect to a TAK server,
+    /// `udp://` sends to a unicast host and `mcast://` to a multicast group. A missing port
+    /// defaults to the standard port of the scheme. TAK server connections use the default
+    /// [`TakServerSetting`].
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Destination, e.g. ssl://takserver.example.com:8089,
+    ///   mcast://239.2.3.1:6969?iface=eth0&ttl=4 or takserver.example.com:8087:tcp
+    ///
+    pub fn connect(destination: &str) -> Result<Self, std::io::Error> {
+        CotPublisher::connect_with_settings(destination, TakServerSetting::default())
+    }
+
+    /// Create a new publisher from a destination URL or ATAK connect string, using the given
+    /// settings for TAK server connections
+    ///
+    /// The `tls` flag of the settings is replaced by the one implied by the destination scheme.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Destination, e.g. ssl://takserver.example.com:8089
+    /// * `settings` - Settings for TAK server connections, including credentials
+    ///
+    pub fn connect_with_settings(
+        destination: &str,
+        mut settings: TakServerSetting<'static>,
+    ) -> Result<Self, std::io::Error> {
+        Ok(match Destination::parse(destination)? {
+            Destination::TakServer { url, tls } => {
+                settings.tls = tls;
+                CotPublisher::new_takserver(url, settings)
+            }
+            Destination::Udp { host, port } => {
+                CotPublisher::new_udp(&host, port, UdpSetting::default())
+            }
+            Destination::Multicast {
                 address,
                 port,
+                interface,
+                ttl,
+            } => CotPublisher::new_multicast_task(
+                SocketAddr::new(address, port),
+                multicast_bind_address(address, interface.as_deref())?,
+                ttl,
+                BROADCAST_CHANNEL_SIZE,
+            ),
+        })
+    }
+
+    /// Create a new publisher using multicast
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Multicast group address and port
+    /// * `bind_address` - Local IP address for interface to bind to
+    /// * `ttl` - Optional time to live of multicast packets
+    /// * `channel_capacity` - Size of the broadcast channel buffer
+    ///
+    fn new_multicast_task(
+        destination: SocketAddr,
+        bind_address: IpAddr,
+        ttl: Option<u32>,
+        channel_capacity: usize,
+    ) -> Self {
+        let (sender, receiver) = tokio::sync::mpsc::channel::<CotSender>(channel_capacity);
+        Self {
+            broadcast_sender: Some(sender),
+            publish_task: Some(tokio::task::spawn(multicast_publisher_task(
+                destination,
                 bind_address,
+                ttl,
                 receiver,
             ))),
+            credential_sender: None,
+            expiry_receiver: None,
+            active_receiver: None,
         }
     }
 
@@ -183,13 +392,7 @@ impl CotPublisher {
     /// * `settings` - Settings for the TAK server connection, including credentials
     ///
     pub fn new_takserver(url: Url, settings: TakServerSetting<'static>) -> Self {
-        let (sender, receiver) = tokio::sync::mpsc::channel::<CotSender>(BROADCAST_CHANNEL_SIZE);
-        Self {
-            broadcast_sender: Some(sender),
-            publish_task: Some(tokio::task::spawn(takserver_publisher_task(
-                url, settings, receiver,
-            ))),
-        }
+        CotPublisher::new_takserver_custom_channel_capacity(url, settings, BROADCAST_CHANNEL_SIZE)
     }
 
     /// Create a new publisher using TAK server over TCP/TLS
@@ -204,16 +407,150 @@ impl CotPublisher {
         url: Url,
         settings: TakServerSetting<'static>,
         channel_capacity: usize,
+    ) -> Self {
+        CotPublisher::new_takserver_task(
+            vec![(url, settings)],
+            FailoverSetting::default(),
+            channel_capacity,
+        )
+    }
+
+    /// Create a new publisher using a list of TAK servers, connecting to the first server
+    /// which is reachable
+    ///
+    /// The first server is the primary, the following servers are backups in order of
+    /// preference. When the connection is lost the publisher fails over to the next server in
+    /// the list. Once every server has failed in turn the reconnect settings of the primary
+    /// server apply. [`CotPublisher::active_server`] reports the server in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `servers` - URLs of the TAK servers with the settings for each connection
+    /// * `failover` - Failover settings, including returning to the primary server
+    ///
+    pub fn new_takserver_failover(
+        servers: Vec<(Url, TakServerSetting<'static>)>,
+        failover: FailoverSetting,
+    ) -> Result<Self, std::io::Error> {
+        CotPublisher::new_takserver_failover_custom_channel_capacity(
+            servers,
+            failover,
+            BROADCAST_CHANNEL_SIZE,
+        )
+    }
+
+    /// Create a new publisher using a list of TAK servers, connecting to the first server
+    /// which is reachable
+    ///
+    /// # Arguments
+    ///
+    /// * `servers` - URLs of the TAK servers with the settings for each connection
+    /// * `failover` - Failover settings, including returning to the primary server
+    /// * `channel_capacity` - Size of the broadcast channel buffer
+    ///
+    pub fn new_takserver_failover_custom_channel_capacity(
+        servers: Vec<(Url, TakServerSetting<'static>)>,
+        failover: FailoverSetting,
+        channel_capacity: usize,
+    ) -> Result<Self, std::io::Error> {
+        if servers.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "At least one TAK server is required",
+            ));
+        }
+        Ok(CotPublisher::new_takserver_task(
+            servers,
+            failover,
+            channel_capacity,
+        ))
+    }
+
+    /// Create a new publisher using TAK servers
+    ///
+    /// # Arguments
+    ///
+    /// * `servers` - URLs of the TAK servers with the settings for each connection, not empty
+    /// * `failover` - Failover settings, including returning to the primary server
+    /// * `channel_capacity` - Size of the broadcast channel buffer
+    ///
+    fn new_takserver_task(
+        servers: Vec<(Url, TakServerSetting<'static>)>,
+        failover: FailoverSetting,
+        channel_capacity: usize,
     ) -> Self {
         let (sender, receiver) = tokio::sync::mpsc::channel::<CotSender>(channel_capacity);
+        let (credential_sender, credential_receiver) = tokio::sync::watch::channel(None);
+        let (expiry_sender, expiry_receiver) = tokio::sync::watch::channel(None);
+        let (active_sender, active_receiver) = tokio::sync::watch::channel(None);
         Self {
             broadcast_sender: Some(sender),
             publish_task: Some(tokio::task::spawn(takserver_publisher_task(
-                url, settings, receiver,
+                servers,
+                failover,
+                receiver,
+                credential_receiver,
+                expiry_sender,
+                active_sender,
             ))),
+            credential_sender: Some(credential_sender),
+            expiry_receiver: Some(expiry_receiver),
+            active_receiver: Some(active_receiver),
         }
     }
 
+    /// Replace the client credentials used by a TAK server publisher, the new credentials are
+    /// used from the next reconnect
+    ///
+    /// Credentials whose private key does not match the certificate are rejected, and the
+    /// previous credentials stay in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `credentials` - New client credentials
+    ///
+    pub fn update_credentials(
+        &self,
+        credentials: Credentials<'static>,
+    ) -> Result<(), PublishError> {
+        let credential_sender =
+            self.credential_sender
+                .as_ref()
+                .ok_or(PublishError::ConnectionError(
+                    "Credentials can only be updated for TAK server publishers".into(),
+                ))?;
+
+        credentials.check_key_matches().map_err(|e| {
+            PublishError::ConnectionError(format!("Invalid client credentials: {e}"))
+        })?;
+
+        credential_sender
+            .send(Some(credentials))
+            .map_err(|_| PublishError::ConnectionError("Publish task has stopped".into()))
+    }
+
+    /// Expiry state of the client certificate used for the most recent TAK server connection
+    ///
+    /// Returns `None` for multicast publishers, before the first connection attempt or when no
+    /// client certificate is used.
+    ///
+    pub fn certificate_expiry(&self) -> Option<CertificateExpiry> {
+        self.expiry_receiver
+            .as_ref()
+            .and_then(|receiver| receiver.borrow().clone())
+    }
+
+    /// TAK server the publisher is currently connected to
+    ///
+    /// Returns `None` for multicast and UDP publishers, and while no TAK server connection is
+    /// established.
+    ///
+    pub fn active_server(&self) -> Option<ActiveServer> {
+        self.active_receiver
+            .as_ref()
+            .and_then(|receiver| receiver.borrow().clone())
+    }
+
     /// Check if the publisher is still connected and the task is running
     ///
     /// This should be called periodically to ensure the connection is still alive    
@@ -284,22 +621,82 @@ impl CotPublisher {
     }
 }
 
-/// Source represents either a file path or a direct string input for PEM data
+/// Task to publish COT messages to a multicast group
 ///
 /// # Arguments
 ///
-/// * `address` - IP Address destination for, usually 239.2.3.1
-/// * `port` - Port to address packets to, usually 6969
+/// * `destination` - Multicast group address and port
 /// * `bind_address` - Local IP address for interface to bind to
+/// * `ttl` - Optional time to live of multicast packets
 /// * `receiver` - Mpsc receiver for COT messages to publish
 ///
 pub(crate) async fn multicast_publisher_task(
-    address: IpAddr,
-    port: u16,
+    destination: SocketAddr,
     bind_address: IpAddr,
+    ttl: Option<u32>,
     mut receiver: tokio::sync::mpsc::Receiver<CotSender>,
 ) -> Result<(), PublishError> {
-    let socket = tokio::net::UdpSocket::bind(format!("{bind_address}:0"))
+    let socket = bind_multicast_socket(bind_address, ttl).await?;
+
+    while let Some((cot, response_sender)) = receiver.recv().await {
+        let message = rpc_from_cot(&cot);
+
+        let mut message_buffer = Vec::with_capacity(message.encoded_len());
+
+        let conversion_result = message
+            .encode(&mut message_buffer)
+            .map_err(|e| {
+                std::io::Error::other(format!("Failed encoding COT message to protobuf: {e}"))
+            })
+            .inspect_err(|e| {
+                handle_error(e.to_string().as_str());
+            });
+
+        // Ignore this message if we can't encode it
+        if conversion_result.is_err() {
+            continue;
+        }
+
+        // If this Socket IO fails, we assume the connection is broken and exit the task
+        let mut buffer = UDP_MAGIC.to_vec(); // Magic
+        buffer.append(&mut message_buffer);
+        let result = socket
+            .send_to(&buffer, destination)
+            .await
+            .map_err(|e| std::io::Error::other(format!("Failed to send COT message data: {e}")))
+            .inspect_err(|e| {
+                handle_error(e.to_string().as_str());
+            });
+
+        if let Some(sender) = response_sender {
+            match result {
+                Ok(_) => {
+                    sender.send(Ok(DeliveryPath::Multicast)).ok();
+                }
+                Err(e) => {
+                    sender
+                        .send(Err(PublishError::SendError(e.to_string())))
+                        .ok();
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Binds the socket used to send to a multicast group
+///
+/// # Arguments
+///
+/// * `bind_address` - Local IP address for interface to bind to
+/// * `ttl` - Optional time to live of multicast packets
+///
+async fn bind_multicast_socket(
+    bind_address: IpAddr,
+    ttl: Option<u32>,
+) -> Result<tokio::net::UdpSocket, PublishError> {
+    let socket = tokio::net::UdpSocket::bind(SocketAddr::new(bind_address, 0))
         .await
         .map_err(|e| PublishError::SendError(format!("Binding to {bind_address}: {e}")))
         .inspect_err(|e| handle_error(e.to_string().as_str()))?;
@@ -311,184 +708,918 @@ pub(crate) async fn multicast_publisher_task(
         })
         .inspect_err(|e| handle_error(e.to_string().as_str()))?;
 
-    let destination = format!("{address}:{port}");
-
-    loop {
-        if let Some((cot, response_sender)) = receiver.recv().await {
-            let message = rpc_from_cot(&cot);
-
-            let mut message_buffer = Vec::with_capacity(message.encoded_len());
-
-            let conversion_result = message
-                .encode(&mut message_buffer)
-                .map_err(|e| {
-                    std::io::Error::other(format!("Failed encoding COT message to protobuf: {e}"))
-                })
-                .inspect_err(|e| {
-                    handle_error(e.to_string().as_str());
-                });
-
-            // Ignore this message if we can't encode it
-            if conversion_result.is_err() {
-                continue;
-            }
+    if let Some(ttl) = ttl {
+        let result = if bind_address.is_ipv6() {
+            socket2::SockRef::from(&socket).set_multicast_hops_v6(ttl)
+        } else {
+            socket.set_multicast_ttl_v4(ttl)
+        };
+        result
+            .map_err(|e| {
+                PublishError::SendError(format!("Failed setting multicast ttl {ttl}: {e}"))
+            })
+            .inspect_err(|e| handle_error(e.to_string().as_str()))?;
+    }
 
-            // If this Socket IO fails, we assume the connection is broken and exit the task
-            let mut buffer = UDP_MAGIC.to_vec(); // Magic
-            buffer.append(&mut message_buffer);
-            let result = socket
-                .send_to(&buffer, &destination)
-                .await
-                .map_err(|e| std::io::Error::other(format!("Failed to send COT message data: {e}")))
-                .inspect_err(|e| {
-                    handle_error(e.to_string().as_str());
-                });
+    Ok(socket)
+}
 
-            if let Some(sender) = response_sender {
-                match result {
-                    Ok(_) => {
-                        sender.send(Ok(())).ok();
-                    }
-                    Err(e) => {
-                        sender
-                            .send(Err(PublishError::SendError(e.to_string())))
-                            .ok();
-                    }
-                }
-            }
-        }
+/// Local address to bind a multicast publisher to, the address of the given interface or the
+/// unspecified address of the group's address family
+///
+/// # Arguments
+///
+/// * `address` - Multicast group address
+/// * `interface` - Optional interface name or local IP address to send from
+///
+pub(crate) fn multicast_bind_address(
+    address: IpAddr,
+    interface: Option<&str>,
+) -> Result<IpAddr, std::io::Error> {
+    match (interface, address) {
+        (Some(interface), _) => destination::interface_address(interface, address.is_ipv6()),
+        (None, IpAddr::V4(_)) => Ok(IpAddr::from(Ipv4Addr::UNSPECIFIED)),
+        (None, IpAddr::V6(_)) => Ok(IpAddr::from(Ipv6Addr::UNSPECIFIED)),
     }
 }
 
-/// Task to manage connection to TAK server and publish COT messages
+/// Task to publish COT messages to a UDP unicast host
+///
+/// The host is resolved before the first message is sent, again once the resolve interval of
+/// the settings has elapsed and after a failed resolution, so a receiver which is not yet
+/// resolvable does not stop the task. Failures to bind the socket are reported for the message
+/// being sent and retried on the next one.
 ///
 /// # Arguments
 ///
-/// * `url` - URL of the TAK server, e.g. takserver.example.com:8080
-/// * `settings` - Settings for the TAK server connection, including credentials
+/// * `host` - Host name or IP address of the receiver
+/// * `port` - Port to address packets to
+/// * `settings` - Settings for the UDP publisher, including the payload format
 /// * `receiver` - Mpsc receiver for COT messages to publish
 ///
-pub(crate) async fn takserver_publisher_task(
-    url: Url,
-    settings: TakServerSetting<'static>,
-    mut receiver: tokio::sync::mpsc::Receiver<CotSender>,
+pub(crate) async fn udp_publisher_task(
+    host: String,
+    port: u16,
+    settings: UdpSetting,
+    receiver: tokio::sync::mpsc::Receiver<CotSender>,
 ) -> Result<(), PublishError> {
-    let mut stream = connection::create_connection(url, settings)
-        .await
-        .map_err(|e| PublishError::SendError(format!("Creating connection to TAK server: {e}")))
-        .inspect_err(|e| handle_error(e.to_string().as_str()))?;
-
-    stream
-        .write_all(PROTOCOL_CHANGE.as_bytes())
-        .await
-        .map_err(|e| {
-            PublishError::SendError(format!("Failed to send protocol change COT message: {e}"))
-        })
-        .inspect_err(|e| handle_error(e.to_string().as_str()))?;
+    publish_udp(&settings, receiver, || {
+        resolve_udp_target(&host, port, &settings)
+    })
+    .await
+}
 
-    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+/// Publishes COT messages to the UDP receiver found by a resolver
+///
+/// # Arguments
+///
+/// * `settings` - Settings for the UDP publisher, including the payload format
+/// * `receiver` - Mpsc receiver for COT messages to publish
+/// * `resolve` - Resolves the address of the receiver
+///
+async fn publish_udp<F, R>(
+    settings: &UdpSetting,
+    mut receiver: tokio::sync::mpsc::Receiver<CotSender>,
+    mut resolve: R,
+) -> Result<(), PublishError>
+where
+    R: FnMut() -> F,
+    F: std::future::Future<Output = Result<SocketAddr, PublishError>>,
+{
+    let mut target: Option<(tokio::net::UdpSocket, SocketAddr)> = None;
+    let mut resolved_at = std::time::Instant::now();
 
-    loop {
-        if let Some((cot, response_sender)) = receiver.recv().await {
-            let message = rpc_from_cot(&cot);
-            let mut message_buffer = Vec::with_capacity(message.encoded_len());
-            let conversion_result = message
-                .encode(&mut message_buffer)
-                .map_err(|e| {
-                    std::io::Error::other(format!("Failed encoding COT message to protobuf: {e}"))
-                })
-                .inspect_err(|e| {
-                    handle_error(e.to_string().as_str());
-                });
-
-            // Ignore this message if we can't encode it
-            if conversion_result.is_err() {
-                continue;
-            }
+    while let Some((cot, response_sender)) = receiver.recv().await {
+        let expired = settings
+            .resolve_interval
+            .is_some_and(|interval| resolved_at.elapsed() >= interval);
 
-            // If this Socket IO fails, we assume the connection is broken and exit the task
-            let result = stream
-                .write_all(&TCP_MAGIC)
-                .await
-                .map_err(|e| {
-                    PublishError::SendError(format!("Failed to send COT message magic byte: {e}"))
-                })
-                .inspect_err(|e| {
-                    handle_error(e.to_string().as_str());
-                });
-
-            if let Err(e) = result {
-                if let Some(sender) = response_sender {
-                    sender
-                        .send(Err(PublishError::SendError(e.to_string())))
-                        .ok();
-                    return Err(e);
+        if target.is_none() || expired {
+            match resolve().await {
+                Ok(resolved) => {
+                    // Keep the existing socket when the address family has not changed
+                    match target.as_mut() {
+                        Some((_, previous)) if previous.is_ipv6() == resolved.is_ipv6() => {
+                            *previous = resolved;
+                        }
+                        _ => match bind_udp_socket(resolved, settings).await {
+                            Ok(socket) => target = Some((socket, resolved)),
+                            // Keep the previous socket and address, binding again on the next
+                            // message
+                            Err(e) => {
+                                if let Some(sender) = response_sender {
+                                    sender.send(Err(e)).ok();
+                                }
+                                continue;
+                            }
+                        },
+                    }
+                    resolved_at = std::time::Instant::now();
                 }
-            }
-
-            let result = stream
-                .write_all(&get_varint(message_buffer.len() as u32))
-                .await
-                .map_err(|e| {
-                    PublishError::SendError(format!("Failed to send COT message size: {e}"))
-                })
-                .inspect_err(|e| {
-                    handle_error(e.to_string().as_str());
-                });
-
-            if let Err(e) = result {
-                if let Some(sender) = response_sender {
-                    sender
-                        .send(Err(PublishError::SendError(e.to_string())))
-                        .ok();
-                    return Err(e);
+                // Carry on with the previous address when re-resolution fails
+                Err(e) if target.is_some() => handle_warning(&e.to_string()),
+                Err(e) => {
+                    handle_error(&e.to_string());
+                    if let Some(sender) = response_sender {
+                        sender.send(Err(e)).ok();
+                    }
+                    continue;
                 }
             }
+        }
 
-            let result = stream
-                .write_all(&message_buffer)
-                .await
-                .map_err(|e| {
-                    PublishError::SendError(format!("Failed to send COT message data: {e}"))
-                })
-                .inspect_err(|e| {
-                    handle_error(e.to_string().as_str());
-                });
-
-            if let Err(e) = result {
+        let Some((socket, address)) = &target else {
+            continue;
+        };
+
+        let buffer = match encode_udp_payload(&cot, settings.payload) {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                handle_error(&e.to_string());
                 if let Some(sender) = response_sender {
-                    sender
-                        .send(Err(PublishError::SendError(e.to_string())))
-                        .ok();
-                    return Err(e);
+                    sender.send(Err(e)).ok();
                 }
+                continue;
             }
+        };
+
+        let result = socket
+            .send_to(&buffer, address)
+            .await
+            .map_err(|e| PublishError::SendError(format!("Failed to send COT message data: {e}")))
+            .inspect_err(|e| handle_error(e.to_string().as_str()));
+
+        if let Some(sender) = response_sender {
+            sender.send(result.map(|_| DeliveryPath::Udp)).ok();
         }
     }
+
+    Ok(())
 }
 
-/// Converts a CursorOnTarget struct to a tak_proto::TakMessage protobuf message
+/// Resolves the UDP receiver, preferring an address of the bind address family when set
 ///
 /// # Arguments
 ///
-/// * `cot` - Reference to the CursorOnTarget struct to convert
+/// * `host` - Host name or IP address of the receiver
+/// * `port` - Port to address packets to
+/// * `settings` - Settings for the UDP publisher
 ///
-fn rpc_from_cot(cot: &CursorOnTarget) -> tak_proto::TakMessage {
-    let pos = cot.position.as_ref().unwrap_or(&Position {
-        lat: 0.0,
-        lng: 0.0,
-        hae: 0.0,
-        ce: 0.0,
-        le: 0.0,
+async fn resolve_udp_target(
+    host: &str,
+    port: u16,
+    settings: &UdpSetting,
+) -> Result<SocketAddr, PublishError> {
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| PublishError::SendError(format!("Failed resolving {host}: {e}")))?
+        .collect();
+
+    addresses
+        .iter()
+        .find(|address| {
+            settings
+                .bind_address
+                .is_none_or(|bind_address| bind_address.is_ipv6() == address.is_ipv6())
+        })
+        .copied()
+        .ok_or(PublishError::SendError(format!(
+            "{host} did not resolve to a usable address"
+        )))
+}
+
+/// Binds the socket used to send to the UDP receiver
+///
+/// # Arguments
+///
+/// * `address` - Resolved address of the receiver
+/// * `settings` - Settings for the UDP publisher
+///
+async fn bind_udp_socket(
+    address: SocketAddr,
+    settings: &UdpSetting,
+) -> Result<tokio::net::UdpSocket, PublishError> {
+    let bind_address = settings.bind_address.unwrap_or(match address {
+        SocketAddr::V4(_) => IpAddr::from(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::from(Ipv6Addr::UNSPECIFIED),
     });
 
-    let time = get_time();
-    tak_proto::TakMessage {
-        tak_control: Some(tak_proto::TakControl {
-            min_proto_version: 2, // Hard coded as this is the only version supported
-            max_proto_version: 2, // Hard coded as this is the only version supported
-            contact_uid: cot.uid.to_owned(),
-        }),
+    tokio::net::UdpSocket::bind(SocketAddr::new(bind_address, 0))
+        .await
+        .map_err(|e| PublishError::SendError(format!("Binding to {bind_address}: {e}")))
+        .inspect_err(|e| handle_error(e.to_string().as_str()))
+}
+
+/// Encodes a COT message as the payload of a UDP datagram
+///
+/// # Arguments
+///
+/// * `cot` - Reference to the CursorOnTarget to encode
+/// * `format` - Encoding of the payload
+///
+fn encode_udp_payload(
+    cot: &CursorOnTarget,
+    format: PayloadFormat,
+) -> Result<Vec<u8>, PublishError> {
+    match format {
+        PayloadFormat::TakProtocol => {
+            let message = rpc_from_cot(cot);
+            let mut buffer = Vec::with_capacity(UDP_MAGIC.len() + message.encoded_len());
+            buffer.extend_from_slice(&UDP_MAGIC);
+            message.encode(&mut buffer).map_err(|e| {
+                PublishError::SendError(format!("Failed encoding COT message to protobuf: {e}"))
+            })?;
+            Ok(buffer)
+        }
+        PayloadFormat::Xml => Ok(xml_from_cot(cot).into_bytes()),
+    }
+}
+
+/// Task to manage connection to TAK server and publish COT messages
+///
+/// The servers are tried in order, failing over to the next server when a connection cannot be
+/// established or is lost. While no server is connected messages are published to the multicast
+/// fallback when one is configured.
+///
+/// # Arguments
+///
+/// * `servers` - URLs of the TAK servers with the settings for each connection, the first is
+///   the primary server
+/// * `failover` - Failover settings, including returning to the primary server
+/// * `receiver` - Mpsc receiver for COT messages to publish
+/// * `credential_receiver` - Watch receiver for credentials replacing those in the settings
+/// * `expiry_sender` - Watch sender reporting the expiry state of the credentials in use
+/// * `active_sender` - Watch sender reporting the server the publisher is connected to
+///
+pub(crate) async fn takserver_publisher_task(
+    mut servers: Vec<(Url, TakServerSetting<'static>)>,
+    failover: FailoverSetting,
+    mut receiver: tokio::sync::mpsc::Receiver<CotSender>,
+    mut credential_receiver: tokio::sync::watch::Receiver<Option<Credentials<'static>>>,
+    expiry_sender: tokio::sync::watch::Sender<Option<CertificateExpiry>>,
+    active_sender: tokio::sync::watch::Sender<Option<ActiveServer>>,
+) -> Result<(), PublishError> {
+    let mut index = 0;
+    let mut failed_servers = 0;
+    // Connection to the primary server established while publishing through a backup
+    let mut primary_stream = None;
+    let mut fallback = failover
+        .multicast_fallback
+        .map(MulticastFallbackSocket::new);
+
+    loop {
+        let updated_credentials = if credential_receiver.has_changed().unwrap_or(false) {
+            credential_receiver.borrow_and_update().clone()
+        } else {
+            None
+        };
+        for (_, settings) in servers.iter_mut() {
+            reload_credentials(settings, updated_credentials.clone());
+        }
+
+        let (url, settings) = &servers[index];
+        expiry_sender.send_replace(check_credential_expiry(settings));
+
+        let stream = match primary_stream.take() {
+            Some(stream) => {
+                handle_warning(&format!("Returned to primary TAK server {url}"));
+                Ok(stream)
+            }
+            None => {
+                publish_while_disconnected(
+                    connect_takserver(url, settings),
+                    &mut receiver,
+                    fallback.as_mut(),
+                )
+                .await
+            }
+        };
+
+        let result = match stream {
+            Ok(stream) => {
+                failed_servers = 0;
+                active_sender.send_replace(Some(ActiveServer {
+                    index,
+                    url: url.clone(),
+                }));
+                if index > 0 {
+                    handle_warning(&format!("Failed over to TAK server {url}"));
+                }
+
+                let result = match failover.return_to_primary {
+                    Some(hold_down) if index > 0 => {
+                        // Publishing continues through the backup while the primary is
+                        // reconnected, and switches to the primary between messages
+                        let (primary_url, primary_settings) = &servers[0];
+                        let (switch_sender, mut switch_receiver) =
+                            tokio::sync::watch::channel(false);
+                        let reconnect = async {
+                            let stream =
+                                reconnect_primary(primary_url, primary_settings, hold_down).await;
+                            switch_sender.send_replace(true);
+                            stream
+                        };
+                        let publish = publish_to_takserver(
+                            stream,
+                            &mut receiver,
+                            settings,
+                            fallback.as_mut(),
+                            Some(&mut switch_receiver),
+                        );
+                        tokio::pin!(reconnect, publish);
+
+                        loop {
+                            tokio::select! {
+                                result = &mut publish => break result,
+                                stream = &mut reconnect, if primary_stream.is_none() => {
+                                    primary_stream = Some(stream);
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        publish_to_takserver(
+                            stream,
+                            &mut receiver,
+                            settings,
+                            fallback.as_mut(),
+                            None,
+                        )
+                        .await
+                    }
+                };
+
+                active_sender.send_replace(None);
+                result
+            }
+            Err(e) => Err(e),
+        };
+
+        match result {
+            // All senders have been dropped, there is nothing left to publish
+            Ok(()) => return Ok(()),
+            Err(_) if primary_stream.is_some() => index = 0,
+            Err(e) => {
+                index = (index + 1) % servers.len();
+                failed_servers += 1;
+
+                // Every server has failed in turn, the reconnect settings of the primary apply
+                if failed_servers >= servers.len() {
+                    failed_servers = 0;
+                    let (_, settings) = &servers[0];
+                    if !settings.auto_reconnect {
+                        return Err(e);
+                    }
+                    publish_while_disconnected(
+                        tokio::time::sleep(settings.reconnect_interval),
+                        &mut receiver,
+                        fallback.as_mut(),
+                    )
+                    .await;
+                }
+            }
+        }
+    }
+}
+
+/// Attempts to connect to the primary TAK server after each hold-down period until a
+/// connection is established
+///
+/// # Arguments
+///
+/// * `url` - URL of the primary TAK server
+/// * `settings` - Settings for the primary TAK server connection
+/// * `hold_down` - Period between connection attempts
+///
+async fn reconnect_primary(
+    url: &Url,
+    settings: &TakServerSetting<'static>,
+    hold_down: std::time::Duration,
+) -> connection::Connection {
+    loop {
+        tokio::time::sleep(hold_down).await;
+        if let Ok(stream) = connect_takserver(url, settings).await {
+            return stream;
+        }
+    }
+}
+
+/// Multicast socket of the fallback transport, bound when it is first used so an interface
+/// which is not yet available does not disable the fallback
+struct MulticastFallbackSocket {
+    fallback: MulticastFallback,
+    socket: Option<tokio::net::UdpSocket>,
+}
+
+impl MulticastFallbackSocket {
+    fn new(fallback: MulticastFallback) -> Self {
+        Self {
+            fallback,
+            socket: None,
+        }
+    }
+
+    /// Publishes a COT message to the fallback multicast group
+    ///
+    /// # Arguments
+    ///
+    /// * `cot` - Reference to the CursorOnTarget to publish
+    ///
+    async fn publish(&mut self, cot: &CursorOnTarget) -> Result<DeliveryPath, PublishError> {
+        let socket = match self.socket.take() {
+            Some(socket) => socket,
+            None => {
+                let address = self.fallback.address;
+                let bind_address =
+                    multicast_bind_address(address.ip(), self.fallback.interface.as_deref())
+                        .map_err(|e| {
+                            PublishError::SendError(format!(
+                                "Finding multicast fallback interface: {e}"
+                            ))
+                        })?;
+                bind_multicast_socket(bind_address, self.fallback.ttl).await?
+            }
+        };
+
+        let buffer = encode_udp_payload(cot, PayloadFormat::TakProtocol)?;
+        let result = socket
+            .send_to(&buffer, self.fallback.address)
+            .await
+            .map(|_| DeliveryPath::Multicast)
+            .map_err(|e| {
+                PublishError::SendError(format!("Failed to send COT message to fallback: {e}"))
+            });
+
+        // Bind again on the next message if the socket has become unusable
+        if result.is_ok() {
+            self.socket = Some(socket);
+        }
+        result
+    }
+
+    /// Publishes a COT message to the fallback multicast group and reports the result to the
+    /// sender waiting for it
+    ///
+    /// # Arguments
+    ///
+    /// * `cot` - Reference to the CursorOnTarget to publish
+    /// * `response_sender` - Optional oneshot sender for the delivery result
+    ///
+    async fn publish_reporting(
+        &mut self,
+        cot: &CursorOnTarget,
+        response_sender: Option<tokio::sync::oneshot::Sender<Result<DeliveryPath, PublishError>>>,
+    ) {
+        let result = self
+            .publish(cot)
+            .await
+            .inspect_err(|e| handle_error(e.to_string().as_str()));
+
+        if let Some(sender) = response_sender {
+            sender.send(result).ok();
+        }
+    }
+}
+
+/// Waits for a future while no TAK server is connected, publishing received messages to the
+/// multicast fallback in the meantime
+///
+/// Without a fallback the messages stay queued until the connection is established.
+///
+/// # Arguments
+///
+/// * `future` - Future to wait for, such as a connection attempt
+/// * `receiver` - Mpsc receiver for COT messages to publish
+/// * `fallback` - Optional multicast fallback
+///
+async fn publish_while_disconnected<F: std::future::Future>(
+    future: F,
+    receiver: &mut tokio::sync::mpsc::Receiver<CotSender>,
+    fallback: Option<&mut MulticastFallbackSocket>,
+) -> F::Output {
+    let Some(fallback) = fallback else {
+        return future.await;
+    };
+
+    tokio::pin!(future);
+    loop {
+        tokio::select! {
+            output = &mut future => return output,
+            Some((cot, response_sender)) = receiver.recv() => {
+                fallback.publish_reporting(&cot, response_sender).await;
+            }
+        }
+    }
+}
+
+/// Connects to the TAK server and switches the stream to the TAK protocol
+///
+/// # Arguments
+///
+/// * `url` - URL of the TAK server, e.g. takserver.example.com:8080
+/// * `settings` - Settings for the TAK server connection, including credentials
+///
+async fn connect_takserver(
+    url: &Url,
+    settings: &TakServerSetting<'static>,
+) -> Result<connection::Connection, PublishError> {
+    let mut stream = connection::create_connection(url, settings)
+        .await
+        .inspect_err(|e| handle_error(e.to_string().as_str()))?;
+
+    if stream.requires_protocol_negotiation() {
+        let negotiation = negotiate_protocol(&mut stream);
+        match settings.timeouts.negotiation {
+            Some(timeout) => tokio::time::timeout(timeout, negotiation)
+                .await
+                .unwrap_or(Err(PublishError::NegotiationTimeout(timeout))),
+            None => negotiation.await,
+        }
+        .inspect_err(|e| handle_error(e.to_string().as_str()))?;
+    }
+
+    Ok(stream)
+}
+
+/// Requests the switch to the TAK protocol and waits for the TAK server to accept it
+///
+/// XML events sent by the TAK server before its response, such as the protocol versions it
+/// supports, are skipped.
+///
+/// # Arguments
+///
+/// * `stream` - Connection to the TAK server
+///
+async fn negotiate_protocol(stream: &mut connection::Connection) -> Result<(), PublishError> {
+    stream
+        .write_all(PROTOCOL_CHANGE.as_bytes())
+        .await
+        .map_err(|e| {
+            PublishError::SendError(format!("Failed to send protocol change COT message: {e}"))
+        })?;
+    stream.flush().await.map_err(|e| {
+        PublishError::SendError(format!("Failed to send protocol change COT message: {e}"))
+    })?;
+
+    loop {
+        let event = read_xml_event(stream).await?;
+        match protocol_change_status(&event) {
+            Some(true) => return Ok(()),
+            Some(false) => {
+                return Err(PublishError::ConnectionError(
+                    "TAK server rejected the switch to the TAK protocol".into(),
+                ));
+            }
+            None => continue,
+        }
+    }
+}
+
+/// Reads an XML event from the TAK server, a byte at a time so no data following it is consumed
+///
+/// # Arguments
+///
+/// * `stream` - Connection to the TAK server
+///
+async fn read_xml_event(stream: &mut connection::Connection) -> Result<String, PublishError> {
+    let mut event = Vec::new();
+    while !event.ends_with(XML_EVENT_END) {
+        if event.len() >= MAX_XML_EVENT_LENGTH {
+            return Err(PublishError::ConnectionError(
+                "TAK server sent an XML event which is too long".into(),
+            ));
+        }
+        let byte = stream.read_u8().await.map_err(|e| {
+            PublishError::ConnectionError(format!("Failed reading from TAK server: {e}"))
+        })?;
+        event.push(byte);
+    }
+    Ok(String::from_utf8_lossy(&event).into_owned())
+}
+
+/// Returns the status of the TAK server's response to the protocol change request, or `None`
+/// when the event is not the response
+///
+/// # Arguments
+///
+/// * `event` - XML event sent by the TAK server
+///
+fn protocol_change_status(event: &str) -> Option<bool> {
+    let mut reader = quick_xml::Reader::from_str(event);
+    loop {
+        match reader.read_event() {
+            Ok(
+                quick_xml::events::Event::Start(element) | quick_xml::events::Event::Empty(element),
+            ) if element.name().as_ref() == b"TakResponse" => {
+                let status = element.try_get_attribute("status").ok().flatten();
+                return Some(status.is_some_and(|status| status.value.as_ref() == b"true"));
+            }
+            Ok(quick_xml::events::Event::Eof) | Err(_) => return None,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Publishes COT messages over an established TAK server stream
+///
+/// Returns `Ok` once all senders have been dropped, or the error which broke the stream. The
+/// message which could not be written is published to the multicast fallback when one is
+/// configured.
+///
+/// Messages published with a response sender are confirmed once they are written and flushed,
+/// or when delivery confirmation is enabled once the TAK server has answered the ping following
+/// them. While confirmation is enabled the messages sent by the server are read and discarded.
+///
+/// When a switch to another server is requested the message being published is still
+/// delivered and confirmed, and the stream is given up before the next message.
+///
+/// # Arguments
+///
+/// * `stream` - Connection to the TAK server
+/// * `receiver` - Mpsc receiver for COT messages to publish
+/// * `settings` - Settings for the TAK server connection, including the timeouts
+/// * `fallback` - Optional multicast fallback
+/// * `switch` - Optional watch receiver set to `true` to stop publishing through this stream
+///
+async fn publish_to_takserver(
+    mut stream: connection::Connection,
+    receiver: &mut tokio::sync::mpsc::Receiver<CotSender>,
+    settings: &TakServerSetting<'static>,
+    fallback: Option<&mut MulticastFallbackSocket>,
+    mut switch: Option<&mut tokio::sync::watch::Receiver<bool>>,
+) -> Result<(), PublishError> {
+    let mut confirmation = settings
+        .confirm_delivery
+        .then(delivery::ConfirmationReader::default);
+
+    loop {
+        // Only wait for the switch between messages so none is dropped while it is written or
+        // waiting for its confirmation
+        let next = tokio::select! {
+            next = receiver.recv() => next,
+            result = read_confirmations(&mut stream, confirmation.as_mut()) => {
+                result.inspect_err(|e| handle_error(e.to_string().as_str()))?;
+                continue;
+            }
+            () = switch_requested(switch.as_deref_mut()) => {
+                return Err(PublishError::ConnectionError(
+                    "Switching to another TAK server".into(),
+                ));
+            }
+        };
+        let Some((cot, response_sender)) = next else {
+            break;
+        };
+
+        let message = rpc_from_cot(&cot);
+        let mut message_buffer = Vec::with_capacity(message.encoded_len());
+        let conversion_result = message
+            .encode(&mut message_buffer)
+            .map_err(|e| {
+                PublishError::SendError(format!("Failed encoding COT message to protobuf: {e}"))
+            })
+            .inspect_err(|e| {
+                handle_error(e.to_string().as_str());
+            });
+
+        // Ignore this message if we can't encode it
+        if let Err(e) = conversion_result {
+            if let Some(sender) = response_sender {
+                sender.send(Err(e)).ok();
+            }
+            continue;
+        }
+
+        // Follow the message with a ping when its delivery is to be confirmed
+        let ping = match (&confirmation, &response_sender) {
+            (Some(_), Some(_)) => Some(delivery::ConfirmationReader::ping(&cot.uid)),
+            _ => None,
+        };
+
+        // If this Socket IO fails, we assume the connection is broken and exit
+        let write = async {
+            write_takserver_message(&mut stream, &message_buffer).await?;
+            if let Some(ping) = &ping {
+                write_takserver_message(&mut stream, ping).await?;
+            }
+            Ok(())
+        };
+        let result = match settings.timeouts.write {
+            Some(timeout) => tokio::time::timeout(timeout, write)
+                .await
+                .unwrap_or(Err(PublishError::WriteTimeout(timeout))),
+            None => write.await,
+        }
+        .inspect_err(|e| {
+            handle_error(e.to_string().as_str());
+        });
+
+        if let Err(e) = result {
+            match fallback {
+                Some(fallback) => fallback.publish_reporting(&cot, response_sender).await,
+                None => {
+                    if let Some(sender) = response_sender {
+                        sender.send(Err(e.clone())).ok();
+                    }
+                }
+            }
+            return Err(e);
+        }
+
+        let Some(sender) = response_sender else {
+            continue;
+        };
+
+        let Some(confirmation) = confirmation.as_mut() else {
+            sender.send(Ok(DeliveryPath::TakServer)).ok();
+            continue;
+        };
+
+        confirmation.ping_sent();
+        let confirmed = confirmation.wait_confirmed(&mut stream);
+        let result = match settings.timeouts.confirmation {
+            Some(timeout) => tokio::time::timeout(timeout, confirmed)
+                .await
+                .unwrap_or(Err(PublishError::ConfirmationTimeout(timeout))),
+            None => confirmed.await,
+        }
+        .inspect_err(|e| handle_error(e.to_string().as_str()));
+
+        match result {
+            Ok(()) => {
+                sender.send(Ok(DeliveryPath::TakServer)).ok();
+            }
+            // The connection stays usable when the server is only slow to answer
+            Err(e @ PublishError::ConfirmationTimeout(_)) => {
+                sender.send(Err(e)).ok();
+            }
+            Err(e) => {
+                sender.send(Err(e.clone())).ok();
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the messages sent by the TAK server while delivery confirmation is enabled, never
+/// completing when it is disabled
+///
+/// # Arguments
+///
+/// * `stream` - Connection to the TAK server
+/// * `confirmation` - Optional confirmation reader of the connection
+///
+async fn read_confirmations(
+    stream: &mut connection::Connection,
+    confirmation: Option<&mut delivery::ConfirmationReader>,
+) -> Result<(), PublishError> {
+    match confirmation {
+        Some(confirmation) => confirmation.read(stream).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits until a switch to another TAK server is requested, never completing without a switch
+/// receiver
+///
+/// # Arguments
+///
+/// * `switch` - Optional watch receiver set to `true` when the switch is requested
+///
+async fn switch_requested(switch: Option<&mut tokio::sync::watch::Receiver<bool>>) {
+    if let Some(switch) = switch {
+        if switch.wait_for(|requested| *requested).await.is_ok() {
+            return;
+        }
+    }
+    std::future::pending().await
+}
+
+/// Writes a framed TAK protocol message to the TAK server stream and flushes it
+///
+/// # Arguments
+///
+/// * `stream` - Connection to the TAK server
+/// * `message_buffer` - Encoded TAK protocol message
+///
+async fn write_takserver_message(
+    stream: &mut connection::Connection,
+    message_buffer: &[u8],
+) -> Result<(), PublishError> {
+    stream
+        .write_all(&framing::frame(message_buffer))
+        .await
+        .map_err(|e| PublishError::SendError(format!("Failed to send COT message: {e}")))?;
+
+    // Message based transports such as WebSocket only send complete messages on flush
+    stream
+        .flush()
+        .await
+        .map_err(|e| PublishError::SendError(format!("Failed to flush COT message: {e}")))
+}
+
+/// Replaces the client credentials in the settings with credentials supplied through the
+/// publisher or reloaded from modified credential files
+///
+/// # Arguments
+///
+/// * `settings` - Settings for the TAK server connection, including credentials
+/// * `updated_credentials` - Credentials supplied through the publisher since the last call
+///
+fn reload_credentials(
+    settings: &mut TakServerSetting<'static>,
+    updated_credentials: Option<Credentials<'static>>,
+) {
+    let mut credentials = None;
+
+    if let Some(credential_files) = settings.credential_files.as_mut() {
+        match credential_files.reload_if_modified() {
+            Ok(reloaded) => credentials = reloaded,
+            Err(e) => handle_error(&format!("Failed reloading credential files: {e}")),
+        }
+    }
+
+    credentials = updated_credentials.or(credentials);
+
+    if let Some(mut credentials) = credentials {
+        // Keep the root certificate of the previous credentials if the new ones don't carry one
+        if credentials.root_cert.is_none() {
+            credentials.root_cert = settings
+                .client_credentials
+                .as_mut()
+                .and_then(|previous| previous.root_cert.take());
+        }
+        settings.client_credentials = Some(credentials);
+    }
+}
+
+/// Checks the expiry of the client certificate in the settings, emitting a warning when it has
+/// expired or expires within the configured warning period
+///
+/// # Arguments
+///
+/// * `settings` - Settings for the TAK server connection, including credentials
+///
+fn check_credential_expiry(settings: &TakServerSetting<'static>) -> Option<CertificateExpiry> {
+    let expiry = settings
+        .client_credentials
+        .as_ref()?
+        .check_expiry(settings.expiry_warning_days)
+        .inspect_err(|e| handle_error(&format!("Failed checking client certificate expiry: {e}")))
+        .ok()?;
+
+    match &expiry {
+        CertificateExpiry::Valid { .. } => {}
+        CertificateExpiry::ExpiringSoon { remaining } => handle_warning(&format!(
+            "Client certificate expires in {} days",
+            remaining.as_secs() / 86400
+        )),
+        CertificateExpiry::Expired { since } => handle_warning(&format!(
+            "Client certificate expired {} days ago",
+            since.as_secs() / 86400
+        )),
+    }
+
+    Some(expiry)
+}
+
+/// Converts a CursorOnTarget struct to a tak_proto::TakMessage protobuf message
+///
+/// # Arguments
+///
+/// * `cot` - Reference to the CursorOnTarget struct to convert
+///
+fn rpc_from_cot(cot: &CursorOnTarget) -> tak_proto::TakMessage {
+    let pos = cot.position.as_ref().unwrap_or(&Position {
+        lat: 0.0,
+        lng: 0.0,
+        hae: 0.0,
+        ce: 0.0,
+        le: 0.0,
+    });
+
+    let (mut detail, conflicts) = detail::detail_from_cot(cot);
+    for conflict in &conflicts {
+        handle_warning(&format!("COT {}: {conflict}", cot.uid));
+    }
+    // A `__milsym` element already in the XML detail is used instead of the SIDC
+    if let Some(milsym) = cot.milsym.as_ref().filter(|_| {
+        !conflicts
+            .iter()
+            .any(|conflict| conflict.element == "__milsym")
+    }) {
+        detail.xml_detail.insert_str(0, &milsym_detail(milsym));
+    }
+
+    let (time, start, stale) = event_times(cot);
+    tak_proto::TakMessage {
+        tak_control: Some(tak_proto::TakControl {
+            min_proto_version: 2, // Hard coded as this is the only version supported
+            max_proto_version: 2, // Hard coded as this is the only version supported
+            contact_uid: cot.uid.to_owned(),
+        }),
         cot_event: Some(tak_proto::CotEvent {
             r#type: cot.r#type.to_owned(),
             access: cot.access.to_owned(),
@@ -496,35 +1627,135 @@ fn rpc_from_cot(cot: &CursorOnTarget) -> tak_proto::TakMessage {
             opex: cot.opex.to_owned(),
             uid: cot.uid.to_owned(),
             send_time: time,
-            start_time: time,
-            stale_time: time + cot.stale_time_ms,
+            start_time: start,
+            stale_time: stale,
             how: cot.how.to_owned(),
             lat: pos.lat,
             lon: pos.lng,
             hae: pos.hae,
             ce: pos.ce,
             le: pos.le,
-            detail: Some(tak_proto::Detail {
-                xml_detail: cot.xml_detail.to_owned().unwrap_or("".into()),
-                contact: cot.contact.as_ref().map(|c| tak_proto::Contact {
-                    endpoint: c.endpoint.to_owned(),
-                    callsign: c.callsign.to_owned(),
-                }),
-                group: None,
-                precision_location: cot.precision_location.as_ref().map(|p| {
-                    tak_proto::PrecisionLocation {
-                        geopointsrc: p.geopointsrc.to_owned(),
-                        altsrc: p.altsrc.to_owned(),
-                    }
-                }),
-                status: None,
-                takv: None,
-                track: None,
-            }),
+            detail: Some(detail),
         }),
     }
 }
 
+/// Converts a CursorOnTarget struct to a COT XML event
+///
+/// # Arguments
+///
+/// * `cot` - Reference to the CursorOnTarget struct to convert
+///
+fn xml_from_cot(cot: &CursorOnTarget) -> String {
+    use quick_xml::escape::escape;
+
+    let pos = cot.position.as_ref().unwrap_or(&Position {
+        lat: 0.0,
+        lng: 0.0,
+        hae: 0.0,
+        ce: 0.0,
+        le: 0.0,
+    });
+
+    // Typed fields also in the XML detail are omitted, as receivers would ignore them
+    let conflicts = detail::detail_conflicts(cot);
+    for conflict in &conflicts {
+        handle_warning(&format!("COT {}: {conflict}", cot.uid));
+    }
+    let conflicting = |element| conflicts.iter().any(|conflict| conflict.element == element);
+
+    let (time, start, stale) = event_times(cot);
+    let mut event = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+         <event version=\"2.0\" uid=\"{}\" type=\"{}\" time=\"{}\" start=\"{}\" stale=\"{}\" how=\"{}\"",
+        escape(cot.uid.as_str()),
+        escape(cot.r#type.as_str()),
+        format_time(time),
+        format_time(start),
+        format_time(stale),
+        escape(cot.how.as_str()),
+    );
+    for (name, value) in [
+        ("access", &cot.access),
+        ("qos", &cot.qos),
+        ("opex", &cot.opex),
+    ] {
+        if !value.is_empty() {
+            event.push_str(&format!(" {name}=\"{}\"", escape(value.as_str())));
+        }
+    }
+    event.push_str(&format!(
+        "><point lat=\"{}\" lon=\"{}\" hae=\"{}\" ce=\"{}\" le=\"{}\"/><detail>",
+        pos.lat, pos.lng, pos.hae, pos.ce, pos.le
+    ));
+
+    if let Some(contact) = cot.contact.as_ref().filter(|_| !conflicting("contact")) {
+        event.push_str(&format!(
+            "<contact callsign=\"{}\" endpoint=\"{}\"/>",
+            escape(contact.callsign.as_str()),
+            escape(contact.endpoint.as_str())
+        ));
+    }
+    if let Some(group) = cot.group.as_ref().filter(|_| !conflicting("__group")) {
+        event.push_str(&format!(
+            "<__group name=\"{}\" role=\"{}\"/>",
+            group.name, group.role
+        ));
+    }
+    if let Some(precision_location) = cot
+        .precision_location
+        .as_ref()
+        .filter(|_| !conflicting("precisionlocation"))
+    {
+        event.push_str(&format!(
+            "<precisionlocation geopointsrc=\"{}\" altsrc=\"{}\"/>",
+            escape(precision_location.geopointsrc.as_str()),
+            escape(precision_location.altsrc.as_str())
+        ));
+    }
+    if let Some(track) = cot
+        .track
+        .as_ref()
+        .filter(|track| track.is_finite() && !conflicting("track"))
+    {
+        event.push_str(&format!(
+            "<track speed=\"{}\" course=\"{}\"/>",
+            track.speed, track.course
+        ));
+    }
+    if let Some(status) = cot.status.as_ref().filter(|_| !conflicting("status")) {
+        event.push_str(&format!("<status battery=\"{}\"/>", status.battery));
+    }
+    if let Some(takv) = cot.takv.as_ref().filter(|_| !conflicting("takv")) {
+        event.push_str(&format!(
+            "<takv device=\"{}\" platform=\"{}\" os=\"{}\" version=\"{}\"/>",
+            escape(takv.device.as_str()),
+            escape(takv.platform.as_str()),
+            escape(takv.os.as_str()),
+            escape(takv.version.as_str())
+        ));
+    }
+    if let Some(milsym) = cot.milsym.as_ref().filter(|_| !conflicting("__milsym")) {
+        event.push_str(&milsym_detail(milsym));
+    }
+    if let Some(xml_detail) = &cot.xml_detail {
+        event.push_str(xml_detail);
+    }
+
+    event.push_str("</detail></event>");
+    event
+}
+
+/// Formats a 2525 symbol as the `__milsym` detail
+///
+/// # Arguments
+///
+/// * `sidc` - Symbol identification code of the entity
+///
+fn milsym_detail(sidc: &Sidc) -> String {
+    format!("<__milsym id=\"{sidc}\"/>")
+}
+
 /// Get the current time of the system in milliseconds since UNIX epoch
 fn get_time() -> u64 {
     let now = std::time::SystemTime::now();
@@ -534,14 +1765,55 @@ fn get_time() -> u64 {
     since_the_epoch.as_millis() as u64
 }
 
-/// Converts a u32 size to a varint byte vector
-fn get_varint(size: u32) -> Vec<u8> {
-    // Message size
-    let mut size_buffer: std::io::Cursor<Vec<u8>> = std::io::Cursor::new(Vec::with_capacity(4));
-    size_buffer
-        .write_u32_varint(size)
-        .expect("Failed to write varint"); // Should never happen
-    size_buffer.into_inner()
+/// Converts a time to milliseconds since UNIX epoch, times before the epoch are clamped to it
+fn millis_since_epoch(time: std::time::SystemTime) -> u64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since_the_epoch| since_the_epoch.as_millis() as u64)
+}
+
+/// Resolves the time, start and stale of a COT event in milliseconds since UNIX epoch
+///
+/// The time defaults to the current time, the start to the time, and the stale to the start
+/// plus `stale_time_ms`.
+///
+/// # Arguments
+///
+/// * `cot` - Reference to the CursorOnTarget struct to encode
+///
+fn event_times(cot: &CursorOnTarget) -> (u64, u64, u64) {
+    let time = cot.time.map_or_else(get_time, millis_since_epoch);
+    let start = cot.start.map_or(time, millis_since_epoch);
+    let stale = cot
+        .stale
+        .map_or(start + cot.stale_time_ms, millis_since_epoch);
+    (time, start, stale)
+}
+
+/// Formats milliseconds since UNIX epoch as an ISO 8601 UTC timestamp, as used in COT XML
+fn format_time(millis: u64) -> String {
+    let seconds = millis / 1000;
+    let (hour, minute, second) = (seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{:03}Z",
+        millis % 1000
+    )
 }
 
 #[cfg(feature = "emit_errors")]
@@ -553,3 +1825,669 @@ fn handle_error(e: &str) {
 #[cfg(not(feature = "emit_errors"))]
 /// Placeholder when error emission is disabled
 fn handle_error(_: &str) {}
+
+#[cfg(feature = "emit_errors")]
+/// Emit warnings to log when feature is enabled
+fn handle_warning(e: &str) {
+    log::warn!("{}", e);
+}
+
+#[cfg(not(feature = "emit_errors"))]
+/// Placeholder when error emission is disabled
+fn handle_warning(_: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TestCa, TestTakServer, read_xml_event, server_config};
+    use std::time::Duration;
+
+    /// Starts a TAK server on 127.0.0.1 which answers the request switching to the TAK protocol
+    /// with the given XML, returning its URL
+    async fn negotiating_server(answer: &'static str) -> Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("tcp://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_xml_event(&mut stream).await.unwrap();
+            stream.write_all(answer.as_bytes()).await.unwrap();
+            // Keep the connection open until the client closes it
+            let _ = stream.read_to_end(&mut Vec::new()).await;
+        });
+        url
+    }
+
+    /// Settings of a plain TCP connection with a negotiation timeout
+    fn negotiation_settings(timeout: Duration) -> TakServerSetting<'static> {
+        TakServerSetting {
+            tls: false,
+            timeouts: TimeoutSettings {
+                negotiation: Some(timeout),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// CursorOnTarget with a SIDC sent as the `__milsym` detail
+    fn cot_with_milsym(xml_detail: Option<&str>) -> CursorOnTarget {
+        let mut cot = CursorOnTarget::default();
+        cot.set_uid("marker-1");
+        cot.set_sidc(&"SFGPUCI----".parse().unwrap(), true).unwrap();
+        cot.set_xml_detail(xml_detail);
+        cot
+    }
+
+    #[test]
+    fn milsym_sent_once() {
+        let cot = cot_with_milsym(Some("<remarks>Patrol</remarks>"));
+        let detail = rpc_from_cot(&cot).cot_event.unwrap().detail.unwrap();
+        assert_eq!(
+            detail.xml_detail,
+            "<__milsym id=\"SFGPUCI--------\"/><remarks>Patrol</remarks>"
+        );
+        assert_eq!(xml_from_cot(&cot).matches("<__milsym").count(), 1);
+    }
+
+    #[test]
+    fn milsym_of_xml_detail_used() {
+        let cot = cot_with_milsym(Some("<__milsym id=\"SHGPUCI--------\"/>"));
+        assert_eq!(
+            cot.detail_conflicts(),
+            vec![DetailConflict {
+                element: "__milsym"
+            }]
+        );
+
+        let detail = rpc_from_cot(&cot).cot_event.unwrap().detail.unwrap();
+        assert_eq!(detail.xml_detail, "<__milsym id=\"SHGPUCI--------\"/>");
+        let xml = xml_from_cot(&cot);
+        assert_eq!(xml.matches("<__milsym").count(), 1);
+        assert!(xml.contains("SHGPUCI"));
+    }
+
+    #[test]
+    fn group_encoded() {
+        let mut cot = CursorOnTarget::default();
+        cot.set_group(Some(TeamColor::DarkBlue), Some(TeamRole::ForwardObserver));
+
+        let group = rpc_from_cot(&cot)
+            .cot_event
+            .unwrap()
+            .detail
+            .unwrap()
+            .group
+            .unwrap();
+        assert_eq!(group.name, "Dark Blue");
+        assert_eq!(group.role, "Forward Observer");
+        assert!(
+            xml_from_cot(&cot).contains("<__group name=\"Dark Blue\" role=\"Forward Observer\"/>")
+        );
+
+        // A missing role defaults to Team Member, and clearing both removes the group
+        cot.set_group(Some(TeamColor::Red), None);
+        assert!(xml_from_cot(&cot).contains("<__group name=\"Red\" role=\"Team Member\"/>"));
+        cot.set_group(None, None);
+        let detail = rpc_from_cot(&cot).cot_event.unwrap().detail;
+        assert_eq!(detail.and_then(|detail| detail.group), None);
+        assert!(!xml_from_cot(&cot).contains("__group"));
+    }
+
+    #[test]
+    fn track_encoded() {
+        let mut cot = CursorOnTarget::default();
+        cot.set_track_knots(10.0, -90.0);
+
+        let track = rpc_from_cot(&cot)
+            .cot_event
+            .unwrap()
+            .detail
+            .unwrap()
+            .track
+            .unwrap();
+        assert_eq!(track.speed, 10.0 * 1852.0 / 3600.0);
+        assert_eq!(track.course, 270.0);
+
+        cot.set_track(2.5, 45.0);
+        assert!(xml_from_cot(&cot).contains("<track speed=\"2.5\" course=\"45\"/>"));
+
+        // Tracks which are not finite are left out
+        for (speed, course) in [(f64::NAN, 90.0), (1.0, f64::INFINITY)] {
+            cot.set_track(speed, course);
+            let detail = rpc_from_cot(&cot).cot_event.unwrap().detail;
+            assert_eq!(detail.and_then(|detail| detail.track), None);
+            assert!(!xml_from_cot(&cot).contains("<track"));
+        }
+    }
+
+    #[test]
+    fn device_details_encoded() {
+        let mut cot = CursorOnTarget::default();
+        cot.set_takv(None, None, None, None);
+        cot.set_battery(Some(150));
+
+        let detail = rpc_from_cot(&cot).cot_event.unwrap().detail.unwrap();
+        let takv = detail.takv.unwrap();
+        assert_eq!(takv.device, std::env::consts::ARCH);
+        assert_eq!(takv.platform, env!("CARGO_PKG_NAME"));
+        assert_eq!(takv.os, std::env::consts::OS);
+        assert_eq!(takv.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(detail.status.unwrap().battery, 100);
+        assert!(xml_from_cot(&cot).contains("<status battery=\"100\"/>"));
+
+        // Values which are set replace the defaults, and are escaped in the XML
+        cot.set_takv(
+            Some("Pixel 8"),
+            None,
+            Some("Android 14"),
+            Some("5.1 <beta>"),
+        );
+        cot.set_battery(Some(42));
+        let detail = rpc_from_cot(&cot).cot_event.unwrap().detail.unwrap();
+        let takv = detail.takv.unwrap();
+        assert_eq!(takv.device, "Pixel 8");
+        assert_eq!(takv.platform, env!("CARGO_PKG_NAME"));
+        assert_eq!(takv.os, "Android 14");
+        assert_eq!(takv.version, "5.1 <beta>");
+        assert_eq!(detail.status.unwrap().battery, 42);
+
+        let xml = xml_from_cot(&cot);
+        assert!(
+            xml.contains(&format!(
+                "<takv device=\"Pixel 8\" platform=\"{}\" os=\"Android 14\" \
+                 version=\"5.1 &lt;beta&gt;\"/>",
+                env!("CARGO_PKG_NAME")
+            )),
+            "{xml}"
+        );
+        assert!(xml.contains("<status battery=\"42\"/>"), "{xml}");
+
+        cot.set_battery(None);
+        assert!(!xml_from_cot(&cot).contains("<status"));
+    }
+
+    /// Time the given number of milliseconds after UNIX epoch
+    fn epoch_millis(millis: u64) -> std::time::SystemTime {
+        std::time::UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn format_known_times() {
+        assert_eq!(format_time(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_time(946_684_799_999), "1999-12-31T23:59:59.999Z");
+        assert_eq!(format_time(951_782_400_000), "2000-02-29T00:00:00.000Z");
+        assert_eq!(format_time(951_868_800_000), "2000-03-01T00:00:00.000Z");
+        assert_eq!(format_time(1_709_210_096_789), "2024-02-29T12:34:56.789Z");
+        // 2100 is not a leap year
+        assert_eq!(format_time(4_107_542_400_000), "2100-03-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn explicit_times_encoded() {
+        let mut cot = CursorOnTarget::default();
+        cot.set_times(
+            Some(epoch_millis(951_782_400_000)),
+            Some(epoch_millis(951_868_800_000)),
+            Some(epoch_millis(1_709_210_096_789)),
+        );
+
+        let event = rpc_from_cot(&cot).cot_event.unwrap();
+        assert_eq!(event.send_time, 951_782_400_000);
+        assert_eq!(event.start_time, 951_868_800_000);
+        assert_eq!(event.stale_time, 1_709_210_096_789);
+        assert!(xml_from_cot(&cot).contains(
+            "time=\"2000-02-29T00:00:00.000Z\" start=\"2000-03-01T00:00:00.000Z\" \
+             stale=\"2024-02-29T12:34:56.789Z\""
+        ));
+    }
+
+    #[test]
+    fn unset_times_default() {
+        let mut cot = CursorOnTarget::default();
+        cot.stale_time_ms = 30_000;
+
+        // The time defaults to now, the start to the time and the stale to the start
+        let before = get_time();
+        let (time, start, stale) = event_times(&cot);
+        assert!((before..=get_time()).contains(&time));
+        assert_eq!(start, time);
+        assert_eq!(stale, time + 30_000);
+
+        cot.set_times(Some(epoch_millis(1_000_000)), None, None);
+        assert_eq!(event_times(&cot), (1_000_000, 1_000_000, 1_030_000));
+
+        cot.set_times(None, Some(epoch_millis(2_000_000)), None);
+        let before = get_time();
+        let (time, start, stale) = event_times(&cot);
+        assert!((before..=get_time()).contains(&time));
+        assert_eq!((start, stale), (2_000_000, 2_030_000));
+
+        cot.set_times(
+            Some(epoch_millis(1_000_000)),
+            None,
+            Some(epoch_millis(5_000_000)),
+        );
+        assert_eq!(event_times(&cot), (1_000_000, 1_000_000, 5_000_000));
+    }
+
+    #[test]
+    fn credential_expiry_uses_the_warning_period() {
+        let ca = TestCa::new("TAK CA");
+        let mut settings = TakServerSetting {
+            client_credentials: Some(ca.issue_expiring("client", 10 * 86_400).credentials()),
+            expiry_warning_days: 30,
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_credential_expiry(&settings),
+            Some(CertificateExpiry::ExpiringSoon { .. })
+        ));
+
+        settings.expiry_warning_days = 7;
+        assert!(matches!(
+            check_credential_expiry(&settings),
+            Some(CertificateExpiry::Valid { .. })
+        ));
+
+        settings.client_credentials = Some(ca.issue_expiring("client", -86_400).credentials());
+        assert!(matches!(
+            check_credential_expiry(&settings),
+            Some(CertificateExpiry::Expired { .. })
+        ));
+
+        settings.client_credentials = None;
+        assert_eq!(check_credential_expiry(&settings), None);
+    }
+
+    #[test]
+    fn updated_credentials_replace_reloaded_files() {
+        let ca = TestCa::new("TAK CA");
+        let (first, second) = (ca.issue("first"), ca.issue("second"));
+        let directory = tempfile::tempdir().unwrap();
+        let certificate = directory.path().join("client.pem");
+        let key = directory.path().join("client.key");
+        std::fs::write(&certificate, first.cert_pem()).unwrap();
+        std::fs::write(&key, first.key_pem()).unwrap();
+
+        let mut settings = TakServerSetting {
+            client_credentials: Some(Credentials {
+                root_cert: Some(vec![ca.cert_der()]),
+                ..first.credentials()
+            }),
+            credential_files: Some(CredentialFiles::new(
+                certificate.to_str().unwrap(),
+                key.to_str().unwrap(),
+                None,
+            )),
+            ..Default::default()
+        };
+
+        // Credentials supplied through the publisher take precedence over the files, and keep
+        // the root certificate of the previous credentials
+        reload_credentials(&mut settings, Some(second.credentials()));
+        let credentials = settings.client_credentials.as_ref().unwrap();
+        assert_eq!(credentials.certificate, second.cert_der());
+        assert_eq!(credentials.root_cert, Some(vec![ca.cert_der()]));
+
+        // The unchanged files do not replace them again
+        reload_credentials(&mut settings, None);
+        let credentials = settings.client_credentials.as_ref().unwrap();
+        assert_eq!(credentials.certificate, second.cert_der());
+    }
+
+    #[tokio::test]
+    async fn updated_credentials_are_used_after_reconnecting() {
+        let ca = TestCa::new("TAK CA");
+        let (first, second) = (ca.issue("first"), ca.issue("second"));
+        let tls = || Some(server_config(&ca.issue("takserver"), Some(&ca), Vec::new()));
+        let mut server =
+            TestTakServer::start_on("127.0.0.1:0".parse().unwrap(), tls(), Some(Duration::ZERO))
+                .await;
+        let address = server.address;
+
+        let settings = TakServerSetting {
+            client_credentials: Some(first.credentials()),
+            root_cert: Some(Source::String(ca.cert_pem())),
+            reconnect_interval: Duration::from_millis(100),
+            confirm_delivery: true,
+            ..Default::default()
+        };
+        let publisher = CotPublisher::new_takserver(server.url(), settings);
+        let cot = publisher.create_cot("client", "a-f-G-U-C").unwrap();
+        cot.publish_checked().await.unwrap();
+        let received = server.next_event().await;
+        assert_eq!(received.client_certificate, Some(first.cert_der()));
+
+        let mismatched = Credentials {
+            private_key: second.key_der(),
+            ..first.credentials()
+        };
+        assert!(publisher.update_credentials(mismatched).is_err());
+        publisher.update_credentials(second.credentials()).unwrap();
+
+        // The new credentials are presented once the publisher has reconnected
+        server.stop().await;
+        let mut server = TestTakServer::start_on(address, tls(), Some(Duration::ZERO)).await;
+        let mut attempts = 0;
+        while cot
+            .publish_checked_timeout(Duration::from_secs(2))
+            .await
+            .is_err()
+        {
+            attempts += 1;
+            assert!(attempts < 10, "Publisher did not reconnect");
+        }
+        let received = server.next_event().await;
+        assert_eq!(received.client_certificate, Some(second.cert_der()));
+    }
+
+    #[tokio::test]
+    async fn credentials_are_only_updated_for_tak_servers() {
+        let ca = TestCa::new("TAK CA");
+        let publisher = CotPublisher::new_udp("127.0.0.1", 6969, UdpSetting::default());
+        let error = publisher
+            .update_credentials(ca.issue("client").credentials())
+            .unwrap_err();
+        assert!(matches!(error, PublishError::ConnectionError(_)), "{error}");
+    }
+
+    #[tokio::test]
+    async fn negotiation_waits_for_the_response() {
+        // The protocol support announced by the server precedes the response
+        let url = negotiating_server(
+            "<event type='t-x-takp-v'><detail><TakControl><TakProtocolSupport version='1'/>\
+             </TakControl></detail></event>\n\
+             <event type='t-x-takp-r'><detail><TakControl><TakResponse status='true'/>\
+             </TakControl></detail></event>",
+        )
+        .await;
+        let settings = negotiation_settings(Duration::from_secs(10));
+        assert!(connect_takserver(&url, &settings).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejected_negotiation_fails() {
+        let url = negotiating_server(
+            "<event type='t-x-takp-r'><detail><TakControl><TakResponse status='false'/>\
+             </TakControl></detail></event>",
+        )
+        .await;
+        let settings = negotiation_settings(Duration::from_secs(10));
+        let result = connect_takserver(&url, &settings).await;
+        assert!(
+            matches!(result, Err(PublishError::ConnectionError(_))),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn negotiation_times_out_when_the_server_does_not_answer() {
+        let url = negotiating_server("").await;
+        let timeout = Duration::from_millis(300);
+        let result = connect_takserver(&url, &negotiation_settings(timeout)).await;
+        assert!(
+            matches!(result, Err(PublishError::NegotiationTimeout(t)) if t == timeout),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn returning_to_primary_delivers_every_message() {
+        // The primary is down until it is started on this free address
+        let primary_address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        // The backup is slow to confirm, so the switch is requested while messages wait for
+        // their confirmation
+        let mut backup = TestTakServer::start_on(
+            "127.0.0.1:0".parse().unwrap(),
+            None,
+            Some(Duration::from_millis(200)),
+        )
+        .await;
+
+        let settings = || TakServerSetting {
+            tls: false,
+            reconnect_interval: Duration::from_millis(100),
+            confirm_delivery: true,
+            ..Default::default()
+        };
+        let primary_url = Url::parse(&format!("tcp://{primary_address}")).unwrap();
+        let publisher = CotPublisher::new_takserver_failover(
+            vec![(primary_url, settings()), (backup.url(), settings())],
+            FailoverSetting {
+                return_to_primary: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let cot = publisher.create_cot("client", "a-f-G-U-C").unwrap();
+        let timeout = Duration::from_secs(5);
+        cot.publish_checked_timeout(timeout).await.unwrap();
+        assert_eq!(
+            publisher.active_server().map(|server| server.index),
+            Some(1)
+        );
+
+        let mut primary =
+            TestTakServer::start_on(primary_address, None, Some(Duration::ZERO)).await;
+        let mut published = 1;
+        while publisher.active_server().map(|server| server.index) != Some(0) {
+            cot.publish_checked_timeout(timeout).await.unwrap();
+            published += 1;
+            assert!(published < 50, "Publisher did not return to the primary");
+        }
+        cot.publish_checked_timeout(timeout).await.unwrap();
+        published += 1;
+
+        // Every confirmed message was received by one of the servers
+        let received = backup.received_events().len() + primary.received_events().len();
+        assert_eq!(received, published);
+    }
+
+    #[tokio::test]
+    async fn multicast_fallback_until_connected() {
+        // A loopback socket stands in for the multicast group of the mesh
+        let mesh = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        // The TAK server is down until it is started on this free address
+        let server_address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let settings = TakServerSetting {
+            tls: false,
+            reconnect_interval: Duration::from_millis(100),
+            confirm_delivery: true,
+            ..Default::default()
+        };
+        let url = Url::parse(&format!("tcp://{server_address}")).unwrap();
+        let publisher = CotPublisher::new_takserver_failover(
+            vec![(url, settings)],
+            FailoverSetting {
+                multicast_fallback: Some(MulticastFallback {
+                    address: mesh.local_addr().unwrap(),
+                    interface: None,
+                    ttl: None,
+                }),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let cot = publisher.create_cot("client", "a-f-G-U-C").unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            cot.publish_checked_timeout(timeout).await.unwrap(),
+            DeliveryPath::Multicast
+        );
+
+        let mut buffer = [0; 4096];
+        let length = tokio::time::timeout(timeout, mesh.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buffer[..UDP_MAGIC.len()], UDP_MAGIC);
+        let message = tak_proto::TakMessage::decode(&buffer[UDP_MAGIC.len()..length]).unwrap();
+        assert_eq!(message.cot_event.unwrap().uid, "client");
+
+        // Messages are published to the TAK server again once it is connected
+        let mut server = TestTakServer::start_on(server_address, None, Some(Duration::ZERO)).await;
+        let mut attempts = 0;
+        while cot.publish_checked_timeout(timeout).await.unwrap() != DeliveryPath::TakServer {
+            attempts += 1;
+            assert!(attempts < 50, "Publisher did not connect");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(server.next_event().await.event.uid, "client");
+        assert_eq!(
+            publisher.active_server().map(|server| server.index),
+            Some(0)
+        );
+    }
+
+    /// Receives the next datagram sent to a socket
+    async fn receive_datagram(socket: &tokio::net::UdpSocket) -> Vec<u8> {
+        let mut buffer = vec![0; 65_536];
+        let length = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        buffer.truncate(length);
+        buffer
+    }
+
+    #[tokio::test]
+    async fn udp_sends_tak_protocol_datagrams() {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let publisher = CotPublisher::new_udp("127.0.0.1", port, UdpSetting::default());
+        let cot = publisher.create_cot("client", "a-f-G-U-C").unwrap();
+        assert_eq!(cot.publish_checked().await.unwrap(), DeliveryPath::Udp);
+
+        let datagram = receive_datagram(&socket).await;
+        assert_eq!(datagram[..UDP_MAGIC.len()], UDP_MAGIC);
+        let message = tak_proto::TakMessage::decode(&datagram[UDP_MAGIC.len()..]).unwrap();
+        let event = message.cot_event.unwrap();
+        assert_eq!(event.uid, "client");
+        assert_eq!(event.r#type, "a-f-G-U-C");
+    }
+
+    #[tokio::test]
+    async fn udp_sends_xml_datagrams() {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let settings = UdpSetting {
+            payload: PayloadFormat::Xml,
+            ..Default::default()
+        };
+        let publisher = CotPublisher::new_udp("127.0.0.1", port, settings);
+        let cot = publisher.create_cot("client", "a-f-G-U-C").unwrap();
+        assert_eq!(cot.publish_checked().await.unwrap(), DeliveryPath::Udp);
+
+        let datagram = String::from_utf8(receive_datagram(&socket).await).unwrap();
+        assert!(datagram.starts_with("<?xml"), "{datagram}");
+        assert!(
+            datagram.contains("<event version=\"2.0\" uid=\"client\" type=\"a-f-G-U-C\""),
+            "{datagram}"
+        );
+        assert!(datagram.ends_with("</event>"), "{datagram}");
+    }
+
+    #[tokio::test]
+    async fn udp_bind_failure_reported() {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        // An address of the documentation range, which is not assigned to any interface
+        let settings = UdpSetting {
+            bind_address: Some("192.0.2.1".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut publisher = CotPublisher::new_udp("127.0.0.1", port, settings);
+        let cot = publisher.create_cot("client", "a-f-G-U-C").unwrap();
+
+        // Each message reports the failure and the task carries on
+        for _ in 0..2 {
+            let error = cot.publish_checked().await.unwrap_err();
+            assert!(
+                error.to_string().contains("Binding to 192.0.2.1"),
+                "{error}"
+            );
+        }
+        publisher.check_connected().await.unwrap();
+    }
+
+    /// Publishes messages to a UDP receiver with a resolver which fails the first time,
+    /// returning the number of resolutions and the delivery results
+    async fn publish_resolving(
+        resolve_interval: Option<Duration>,
+        address: SocketAddr,
+        messages: usize,
+    ) -> (usize, Vec<Result<DeliveryPath, PublishError>>) {
+        let settings = UdpSetting {
+            resolve_interval,
+            ..Default::default()
+        };
+        let resolutions = std::sync::atomic::AtomicUsize::new(0);
+        let (sender, receiver) = tokio::sync::mpsc::channel(messages);
+        let mut results = Vec::new();
+        for _ in 0..messages {
+            let (response_sender, response_receiver) = tokio::sync::oneshot::channel();
+            sender
+                .send((CursorOnTarget::default(), Some(response_sender)))
+                .await
+                .unwrap();
+            results.push(response_receiver);
+        }
+        drop(sender);
+
+        let resolve = || {
+            let attempt = resolutions.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                match attempt {
+                    0 => Err(PublishError::SendError("Failed resolving".into())),
+                    _ => Ok(address),
+                }
+            }
+        };
+        publish_udp(&settings, receiver, resolve).await.unwrap();
+
+        let mut delivered = Vec::new();
+        for result in results {
+            delivered.push(result.await.unwrap());
+        }
+        (resolutions.into_inner(), delivered)
+    }
+
+    #[tokio::test]
+    async fn udp_resolves_again() {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+
+        // The failed resolution is retried on the next message, and the address is kept
+        let (resolutions, delivered) = publish_resolving(None, address, 3).await;
+        assert_eq!(resolutions, 2);
+        assert!(delivered[0].is_err());
+        assert!(
+            delivered[1..]
+                .iter()
+                .all(|result| matches!(result, Ok(DeliveryPath::Udp)))
+        );
+
+        // The address is resolved again before each message once the interval has elapsed
+        let (resolutions, delivered) = publish_resolving(Some(Duration::ZERO), address, 3).await;
+        assert_eq!(resolutions, 3);
+        assert!(delivered[0].is_err());
+        assert!(
+            delivered[1..]
+                .iter()
+                .all(|result| matches!(result, Ok(DeliveryPath::Udp)))
+        );
+
+        for _ in 0..4 {
+            receive_datagram(&socket).await;
+        }
+    }
+}
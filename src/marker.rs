@@ -0,0 +1,458 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides a builder for map markers, such as spot map markers (`b-m-p-s-m`) or
+//! unknown ground points (`a-u-G`), with the icon, colour, remarks, archive and parent link
+//! details ATAK expects.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{CotPublisher, CursorOnTarget, DetailElement};
+
+/// COT type of spot map markers
+const SPOT_MAP_TYPE: &str = "b-m-p-s-m";
+/// Relation of a marker to the entity which created it
+const PARENT_RELATION: &str = "p-p";
+
+/// Builder of map marker COT messages, with the contact, position and typed details of the
+/// marker
+#[derive(Clone, Debug)]
+pub struct Marker {
+    uid: String,
+    r#type: String,
+    callsign: Option<String>,
+    position: Option<(f64, f64)>,
+    icon: Option<String>,
+    color: Option<Color>,
+    remarks: Option<String>,
+    archive: bool,
+    parent: Option<DetailElement>,
+    details: Vec<DetailElement>,
+}
+
+/// ARGB colour of a marker, as sent in the `argb` attribute of `<color>`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Color {
+    /// Opacity, 255 is opaque
+    pub alpha: u8,
+    /// Red component
+    pub red: u8,
+    /// Green component
+    pub green: u8,
+    /// Blue component
+    pub blue: u8,
+}
+
+impl Marker {
+    /// Creates a marker builder
+    ///
+    /// # Arguments
+    ///
+    /// * `uid` - Unique identifier of the marker
+    /// * `r#type` - COT type of the marker (e.g., "a-u-G")
+    ///
+    pub fn new(uid: &str, r#type: &str) -> Self {
+        Self {
+            uid: uid.into(),
+            r#type: r#type.into(),
+            callsign: None,
+            position: None,
+            icon: None,
+            color: None,
+            remarks: None,
+            archive: false,
+            parent: None,
+            details: Vec::new(),
+        }
+    }
+
+    /// Creates a spot map marker builder, of type `b-m-p-s-m`
+    ///
+    /// Unless an icon is set, spot map markers use the ATAK spot map icon in the marker's
+    /// colour, white by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `uid` - Unique identifier of the marker
+    ///
+    pub fn spot(uid: &str) -> Self {
+        Self::new(uid, SPOT_MAP_TYPE)
+    }
+
+    /// Sets the callsign shown as the marker's label (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `callsign` - Label of the marker
+    ///
+    pub fn with_callsign(mut self, callsign: &str) -> Self {
+        self.callsign = Some(callsign.into());
+        self
+    }
+
+    /// Sets the position of the marker (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `lat` - Latitude in decimal degrees (WGS-84)
+    /// * `lng` - Longitude in decimal degrees (WGS-84)
+    ///
+    pub fn with_position(mut self, lat: f64, lng: f64) -> Self {
+        self.position = Some((lat, lng));
+        self
+    }
+
+    /// Sets the icon of the marker (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `iconsetpath` - Path of the icon in an icon set, `<iconset uid>/<group>/<icon file>`
+    ///
+    pub fn with_icon(mut self, iconsetpath: &str) -> Self {
+        self.icon = Some(iconsetpath.into());
+        self
+    }
+
+    /// Sets the colour of the marker (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - Colour of the marker
+    ///
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Sets the remarks of the marker (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `remarks` - Free text remarks, escaped when serialised
+    ///
+    pub fn with_remarks(mut self, remarks: &str) -> Self {
+        self.remarks = Some(remarks.into());
+        self
+    }
+
+    /// Sets whether receivers keep the marker after it goes stale or they restart (builder
+    /// pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `archive` - Whether to send the `<archive/>` detail
+    ///
+    pub fn with_archive(mut self, archive: bool) -> Self {
+        self.archive = archive;
+        self
+    }
+
+    /// Links the marker to the entity which created it (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `uid` - UID of the creator
+    /// * `r#type` - Optional COT type of the creator
+    /// * `callsign` - Optional callsign of the creator
+    ///
+    pub fn with_parent(mut self, uid: &str, r#type: Option<&str>, callsign: Option<&str>) -> Self {
+        let mut link = DetailElement::new("link")
+            .with_attribute("uid", uid)
+            .with_attribute("relation", PARENT_RELATION);
+        if let Some(r#type) = r#type {
+            link.set_attribute("type", r#type);
+        }
+        if let Some(callsign) = callsign {
+            link.set_attribute("parent_callsign", callsign);
+        }
+        self.parent = Some(link);
+        self
+    }
+
+    /// Appends a further detail element (builder pattern)
+    ///
+    /// # Arguments
+    ///
+    /// * `element` - Detail element written after the marker's details
+    ///
+    pub fn with_detail(mut self, element: DetailElement) -> Self {
+        self.details.push(element);
+        self
+    }
+
+    /// Builds the marker as a CursorOnTarget of the publisher, ready to publish
+    ///
+    /// # Arguments
+    ///
+    /// * `publisher` - Publisher the marker is published with
+    ///
+    pub fn build(&self, publisher: &CotPublisher) -> Result<CursorOnTarget, std::io::Error> {
+        let mut cot = publisher.create_cot(self.uid.as_str(), self.r#type.as_str())?;
+        self.apply(&mut cot);
+        Ok(cot)
+    }
+
+    /// Writes the marker's contact, position and details to a CursorOnTarget, such as one
+    /// created by a blocking publisher
+    ///
+    /// The UID and type of the CursorOnTarget are set to those of the marker. Its XML detail
+    /// block is replaced by the marker's details, so elements set before, such as with
+    /// [`CursorOnTarget::set_xml_detail`], are discarded. Add them to the marker with
+    /// [`with_detail`](Self::with_detail) instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `cot` - CursorOnTarget to write the marker to
+    ///
+    pub fn apply(&self, cot: &mut CursorOnTarget) {
+        cot.set_uid(&self.uid);
+        cot.r#type = self.r#type.clone();
+        if let Some(callsign) = &self.callsign {
+            cot.set_contact(Some(callsign), None);
+        }
+        if let Some((lat, lng)) = self.position {
+            cot.set_position(lat, lng);
+        }
+
+        let mut details = Vec::new();
+        if self.archive {
+            details.push(DetailElement::new("archive"));
+        }
+        if let Some(parent) = &self.parent {
+            details.push(parent.clone());
+        }
+        if let Some(remarks) = &self.remarks {
+            details.push(DetailElement::new("remarks").with_text(remarks));
+        }
+        if let Some(color) = self.color {
+            details.push(DetailElement::new("color").with_attribute("argb", color.to_argb()));
+        }
+        let icon = self.icon.clone().or_else(|| {
+            (self.r#type == SPOT_MAP_TYPE).then(|| {
+                let color = self.color.unwrap_or(Color::WHITE);
+                format!("COT_MAPPING_SPOTMAP/{SPOT_MAP_TYPE}/{}", color.to_argb())
+            })
+        });
+        if let Some(icon) = icon {
+            details.push(DetailElement::new("usericon").with_attribute("iconsetpath", icon));
+        }
+        details.extend(self.details.iter().cloned());
+
+        cot.set_detail(&details);
+    }
+}
+
+impl Color {
+    /// Opaque white, the default colour of ATAK markers
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+
+    /// Creates a colour from its components
+    ///
+    /// # Arguments
+    ///
+    /// * `alpha` - Opacity, 255 is opaque
+    /// * `red` - Red component
+    /// * `green` - Green component
+    /// * `blue` - Blue component
+    ///
+    pub const fn new(alpha: u8, red: u8, green: u8, blue: u8) -> Self {
+        Self {
+            alpha,
+            red,
+            green,
+            blue,
+        }
+    }
+
+    /// Creates an opaque colour
+    ///
+    /// # Arguments
+    ///
+    /// * `red` - Red component
+    /// * `green` - Green component
+    /// * `blue` - Blue component
+    ///
+    pub const fn rgb(red: u8, green: u8, blue: u8) -> Self {
+        Self::new(255, red, green, blue)
+    }
+
+    /// Creates a colour from the signed 32 bit ARGB value used by ATAK, e.g. `-65536` for red
+    ///
+    /// # Arguments
+    ///
+    /// * `argb` - ARGB value
+    ///
+    pub const fn from_argb(argb: i32) -> Self {
+        let [alpha, red, green, blue] = argb.to_be_bytes();
+        Self::new(alpha, red, green, blue)
+    }
+
+    /// Signed 32 bit ARGB value used by ATAK, e.g. `-65536` for red
+    pub const fn to_argb(&self) -> i32 {
+        i32::from_be_bytes([self.alpha, self.red, self.green, self.blue])
+    }
+}
+
+impl fmt::Display for Color {
+    /// Formats the colour as `#AARRGGBB`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{:02X}{:02X}{:02X}{:02X}",
+            self.alpha, self.red, self.green, self.blue
+        )
+    }
+}
+
+impl FromStr for Color {
+    type Err = std::io::Error;
+
+    /// Parses a colour from `#AARRGGBB`, `#RRGGBB` (opaque), `0xAARRGGBB`, or a signed or
+    /// unsigned decimal ARGB value such as `-65536`
+    fn from_str(color: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid ARGB colour {color}"),
+            )
+        };
+
+        let color = color.trim();
+        let hex = color
+            .strip_prefix('#')
+            .or_else(|| color.strip_prefix("0x"))
+            .or_else(|| color.strip_prefix("0X"));
+        let argb = match hex {
+            Some(hex) if !hex.chars().all(|c| c.is_ascii_hexdigit()) => return Err(invalid()),
+            Some(hex) if hex.len() == 8 => u32::from_str_radix(hex, 16).map_err(|_| invalid())?,
+            Some(hex) if hex.len() == 6 => {
+                0xFF00_0000 | u32::from_str_radix(hex, 16).map_err(|_| invalid())?
+            }
+            Some(_) => return Err(invalid()),
+            None => {
+                let argb: i64 = color.parse().map_err(|_| invalid())?;
+                if argb < i64::from(i32::MIN) || argb > i64::from(u32::MAX) {
+                    return Err(invalid());
+                }
+                argb as u32
+            }
+        };
+        Ok(Self::from_argb(argb as i32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// XML detail block of a marker applied to an empty CursorOnTarget
+    fn xml_detail(marker: &Marker) -> String {
+        let mut cot = CursorOnTarget::default();
+        marker.apply(&mut cot);
+        cot.xml_detail.unwrap_or_default()
+    }
+
+    #[test]
+    fn parse_colors() {
+        let red = Color::rgb(255, 0, 0);
+        for color in [
+            "#FF0000",
+            "#ff0000",
+            "#FFFF0000",
+            "0xFFFF0000",
+            "-65536",
+            "4294901760",
+        ] {
+            assert_eq!(color.parse::<Color>().unwrap(), red, "{color}");
+        }
+        assert_eq!(
+            "#8000FF7F".parse::<Color>().unwrap(),
+            Color::new(128, 0, 255, 127)
+        );
+        assert_eq!(" -1 ".parse::<Color>().unwrap(), Color::WHITE);
+        assert_eq!("0".parse::<Color>().unwrap(), Color::new(0, 0, 0, 0));
+        assert_eq!(
+            "-2147483648".parse::<Color>().unwrap(),
+            Color::new(128, 0, 0, 0)
+        );
+
+        for color in [
+            "4294967296",
+            "-2147483649",
+            "#FFF",
+            "#FF00000",
+            "#FFFF00000",
+            "#GG0000",
+            "#+F0000",
+            "red",
+            "",
+        ] {
+            let error = color.parse::<Color>().unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput, "{color}");
+        }
+    }
+
+    #[test]
+    fn argb_values() {
+        let red = Color::rgb(255, 0, 0);
+        assert_eq!(red.to_argb(), -65536);
+        assert_eq!(Color::from_argb(-65536), red);
+        assert_eq!(Color::WHITE.to_argb(), -1);
+        assert_eq!(red.to_string(), "#FFFF0000");
+
+        let translucent = Color::new(0x40, 0x12, 0xAB, 0x09);
+        assert_eq!(translucent.to_string(), "#4012AB09");
+        assert_eq!(
+            translucent.to_string().parse::<Color>().unwrap(),
+            translucent
+        );
+        assert_eq!(Color::from_argb(translucent.to_argb()), translucent);
+    }
+
+    #[test]
+    fn spot_icon_path() {
+        assert_eq!(
+            xml_detail(&Marker::spot("spot-1")),
+            "<usericon iconsetpath=\"COT_MAPPING_SPOTMAP/b-m-p-s-m/-1\"/>"
+        );
+        assert_eq!(
+            xml_detail(&Marker::spot("spot-1").with_color(Color::rgb(255, 0, 0))),
+            "<color argb=\"-65536\"/>\
+             <usericon iconsetpath=\"COT_MAPPING_SPOTMAP/b-m-p-s-m/-65536\"/>"
+        );
+
+        // A set icon replaces the spot map icon, other markers have no default icon
+        let icon = "34ae1613-9645-4222-a9d2-e5f243dea2865/Military/soldier6.png";
+        assert_eq!(
+            xml_detail(&Marker::spot("spot-1").with_icon(icon)),
+            format!("<usericon iconsetpath=\"{icon}\"/>")
+        );
+        assert_eq!(xml_detail(&Marker::new("marker-1", "a-u-G")), "");
+    }
+
+    #[test]
+    fn apply_replaces_xml_detail() {
+        let mut cot = CursorOnTarget::default();
+        cot.set_xml_detail(Some("<remarks>Old</remarks><link uid=\"old\"/>"));
+        Marker::new("marker-1", "a-u-G")
+            .with_callsign("Marker")
+            .with_remarks("New & improved")
+            .with_archive(true)
+            .with_parent("ANDROID-1", Some("a-f-G-U-C"), Some("Alpha"))
+            .with_detail(DetailElement::new("height").with_text("12"))
+            .apply(&mut cot);
+
+        assert_eq!(cot.uid, "marker-1");
+        assert_eq!(cot.r#type, "a-u-G");
+        assert_eq!(cot.contact.as_ref().unwrap().callsign, "Marker");
+        assert_eq!(
+            cot.xml_detail.as_deref(),
+            Some(
+                "<archive/>\
+                 <link uid=\"ANDROID-1\" relation=\"p-p\" type=\"a-f-G-U-C\" parent_callsign=\"Alpha\"/>\
+                 <remarks>New &amp; improved</remarks><height>12</height>"
+            )
+        );
+    }
+}
@@ -0,0 +1,406 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module converts between MIL-STD-2525B/C symbol identification codes (SIDC) and COT
+//! types.
+//!
+//! A SIDC has 15 positions: coding scheme, affiliation, battle dimension, status, a function id
+//! of 6 positions, and 5 positions of modifiers, country and order of battle, with unused
+//! positions filled with `-`. Warfighting symbols (coding scheme `S`) map to COT atom types with
+//! the same affiliation and dimension, and a COT atom for each position of the function id,
+//! e.g. `SFGPUCI--------` and `a-f-G-U-C-I`. The status and the trailing positions have no
+//! COT type equivalent, and are kept by sending the SIDC as the `__milsym` detail.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Affiliation, CotType, Dimension};
+
+/// Length of a SIDC
+const SIDC_LENGTH: usize = 15;
+/// Length of the function id of a SIDC
+const FUNCTION_ID_LENGTH: usize = 6;
+/// Coding scheme of warfighting symbols
+const WARFIGHTING: char = 'S';
+/// Fill of unused SIDC positions
+const FILL: char = '-';
+
+/// MIL-STD-2525B/C symbol identification code, validated to 15 positions in upper case
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Sidc(String);
+
+/// Status of the entity a symbol represents, the fourth position of a SIDC
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SymbolStatus {
+    Anticipated,
+    #[default]
+    Present,
+    FullyCapable,
+    Damaged,
+    Destroyed,
+    FullToCapacity,
+}
+
+impl Sidc {
+    /// Converts a COT atom type to the SIDC of a warfighting symbol
+    ///
+    /// The affiliation `x` (other) has no SIDC equivalent and is converted to unknown.
+    ///
+    /// # Arguments
+    ///
+    /// * `cot_type` - COT atom type, e.g. `a-f-G-U-C-I`
+    /// * `status` - Status of the entity
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if the type is not an atom type with affiliation and
+    /// dimension, or its function codes are not single letters or digits fitting the 6
+    /// positions of the function id
+    ///
+    pub fn from_cot_type(cot_type: &CotType, status: SymbolStatus) -> Result<Self, std::io::Error> {
+        let invalid = |reason: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("No SIDC for COT type {cot_type}: {reason}"),
+            )
+        };
+
+        let affiliation = cot_type
+            .affiliation()
+            .ok_or_else(|| invalid("not an atom type with affiliation"))?;
+        let dimension = cot_type
+            .dimension()
+            .ok_or_else(|| invalid("no battle dimension"))?;
+
+        let mut sidc = String::with_capacity(SIDC_LENGTH);
+        sidc.push(WARFIGHTING);
+        sidc.push(affiliation_code(affiliation));
+        sidc.push_str(dimension.as_str());
+        sidc.push(status.code());
+
+        let function_codes: Vec<&str> = cot_type.atoms().skip(3).collect();
+        if function_codes.len() > FUNCTION_ID_LENGTH {
+            return Err(invalid("function id longer than 6 positions"));
+        }
+        for code in function_codes {
+            match code.chars().next() {
+                Some(c) if code.len() == 1 && (c.is_ascii_uppercase() || c.is_ascii_digit()) => {
+                    sidc.push(c)
+                }
+                _ => {
+                    return Err(invalid(&format!(
+                        "function code {code} is not a SIDC position"
+                    )));
+                }
+            }
+        }
+
+        Ok(Self(pad(sidc)))
+    }
+
+    /// Converts the SIDC of a warfighting symbol to a COT atom type
+    ///
+    /// Exercise affiliations are converted to their real world equivalent, and the unknown
+    /// dimension `Z` to other. The function id ends at the first unused position.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if the coding scheme is not warfighting (`S`), or the
+    /// affiliation or dimension is unknown
+    ///
+    pub fn to_cot_type(&self) -> Result<CotType, std::io::Error> {
+        let invalid = |reason: String| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("No COT type for SIDC {self}: {reason}"),
+            )
+        };
+
+        if self.coding_scheme() != WARFIGHTING {
+            return Err(invalid(format!(
+                "coding scheme {} is not warfighting",
+                self.coding_scheme()
+            )));
+        }
+        let affiliation = self
+            .affiliation()
+            .ok_or_else(|| invalid(format!("unknown affiliation {}", self.position(1))))?;
+        let dimension = self
+            .dimension()
+            .ok_or_else(|| invalid(format!("unknown dimension {}", self.position(2))))?;
+
+        let function_id: Vec<String> = self
+            .function_id()
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .map(String::from)
+            .collect();
+
+        let cot_type = CotType::atom(affiliation, dimension);
+        if function_id.is_empty() {
+            return Ok(cot_type);
+        }
+        cot_type.function(&function_id.join("-"))
+    }
+
+    /// The SIDC as used in `__milsym` details
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Coding scheme, the first position, e.g. `S` for warfighting
+    pub fn coding_scheme(&self) -> char {
+        self.position(0)
+    }
+
+    /// Affiliation, with exercise affiliations converted to their real world equivalent
+    pub fn affiliation(&self) -> Option<Affiliation> {
+        Some(match self.position(1) {
+            'P' | 'G' => Affiliation::Pending,
+            'U' | 'W' => Affiliation::Unknown,
+            'A' | 'M' => Affiliation::AssumedFriend,
+            'F' | 'D' => Affiliation::Friend,
+            'N' | 'L' => Affiliation::Neutral,
+            'S' => Affiliation::Suspect,
+            'H' => Affiliation::Hostile,
+            'J' => Affiliation::Joker,
+            'K' => Affiliation::Faker,
+            'O' => Affiliation::None,
+            _ => return None,
+        })
+    }
+
+    /// Battle dimension, with the unknown dimension `Z` converted to other
+    pub fn dimension(&self) -> Option<Dimension> {
+        match self.position(2) {
+            'Z' => Some(Dimension::Other),
+            c => c.to_string().parse().ok(),
+        }
+    }
+
+    /// Status of the entity, `None` when not set
+    pub fn status(&self) -> Option<SymbolStatus> {
+        SymbolStatus::ALL
+            .into_iter()
+            .find(|status| status.code() == self.position(3))
+    }
+
+    /// Function id of 6 positions, e.g. `UCI---`
+    pub fn function_id(&self) -> &str {
+        &self.0[4..4 + FUNCTION_ID_LENGTH]
+    }
+
+    /// Returns the SIDC with the status replaced
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - New status of the entity
+    ///
+    pub fn with_status(self, status: SymbolStatus) -> Self {
+        let mut sidc = self.0;
+        sidc.replace_range(3..4, &status.code().to_string());
+        Self(sidc)
+    }
+
+    /// Character at a position of the SIDC, which only contains ASCII characters
+    fn position(&self, index: usize) -> char {
+        self.0.as_bytes()[index] as char
+    }
+}
+
+impl SymbolStatus {
+    /// All statuses
+    pub const ALL: [SymbolStatus; 6] = [
+        SymbolStatus::Anticipated,
+        SymbolStatus::Present,
+        SymbolStatus::FullyCapable,
+        SymbolStatus::Damaged,
+        SymbolStatus::Destroyed,
+        SymbolStatus::FullToCapacity,
+    ];
+
+    /// Code of the status in a SIDC, e.g. `P` for present
+    pub fn code(&self) -> char {
+        match self {
+            SymbolStatus::Anticipated => 'A',
+            SymbolStatus::Present => 'P',
+            SymbolStatus::FullyCapable => 'C',
+            SymbolStatus::Damaged => 'D',
+            SymbolStatus::Destroyed => 'X',
+            SymbolStatus::FullToCapacity => 'F',
+        }
+    }
+}
+
+impl fmt::Display for Sidc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Sidc {
+    type Err = std::io::Error;
+
+    /// Parses a SIDC, ignoring case
+    ///
+    /// Missing trailing positions are filled with `-`, as are positions marked `*` in symbol
+    /// tables.
+    fn from_str(sidc: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid SIDC {sidc}: {reason}"),
+            )
+        };
+
+        let normalised: String = sidc
+            .trim()
+            .chars()
+            .map(|c| match c {
+                '*' => FILL,
+                c => c.to_ascii_uppercase(),
+            })
+            .collect();
+
+        if normalised.len() < 3 || normalised.len() > SIDC_LENGTH {
+            return Err(invalid("length is not 3 to 15 positions"));
+        }
+        if !normalised
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == FILL)
+        {
+            return Err(invalid("positions are not letters, digits or -"));
+        }
+        if !normalised[..3].chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(invalid("coding scheme, affiliation or dimension not set"));
+        }
+
+        Ok(Self(pad(normalised)))
+    }
+}
+
+/// SIDC code of an affiliation, with other converted to unknown
+fn affiliation_code(affiliation: Affiliation) -> char {
+    match affiliation {
+        Affiliation::Other => 'U',
+        affiliation => affiliation.as_str().to_ascii_uppercase().as_bytes()[0] as char,
+    }
+}
+
+/// Fills the missing trailing positions of a SIDC
+fn pad(mut sidc: String) -> String {
+    while sidc.len() < SIDC_LENGTH {
+        sidc.push(FILL);
+    }
+    sidc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a SIDC
+    fn sidc(sidc: &str) -> Sidc {
+        sidc.parse().unwrap()
+    }
+
+    /// Parses a COT type
+    fn cot_type(cot_type: &str) -> CotType {
+        cot_type.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_sidc() {
+        assert_eq!(sidc("sfgpuci").as_str(), "SFGPUCI--------");
+        assert_eq!(sidc(" SFGP*UCI****** ").as_str(), "SFGP-UCI-------");
+        assert_eq!(sidc("SFGPUCI----").function_id(), "UCI---");
+
+        for invalid in [
+            "",
+            "SF",
+            "SFGPUCIXXXXXXXXX",
+            "SFG PUCI",
+            "S-GPUCI",
+            "1FGPUCI",
+        ] {
+            let error = invalid.parse::<Sidc>().unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn sidc_to_cot_type() {
+        for (sidc_code, expected) in [
+            ("SFGPUCI--------", "a-f-G-U-C-I"),
+            ("SHAPMF---------", "a-h-A-M-F"),
+            ("SNSP-----------", "a-n-S"),
+            ("SUUPS1---------", "a-u-U-S-1"),
+            ("SFGPUCI-AB--USG", "a-f-G-U-C-I"),
+            // Exercise affiliations and the unknown dimension
+            ("SDGPUCI--------", "a-f-G-U-C-I"),
+            ("SGZP-----------", "a-p-X"),
+        ] {
+            assert_eq!(
+                sidc(sidc_code).to_cot_type().unwrap().as_str(),
+                expected,
+                "{sidc_code}"
+            );
+        }
+
+        // Only warfighting symbols with a known affiliation and dimension have a COT type
+        for invalid in ["GFGPGPP--------", "SQGPUCI--------", "SFQPUCI--------"] {
+            assert!(sidc(invalid).to_cot_type().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn cot_type_to_sidc() {
+        for (cot_type_code, status, expected) in [
+            ("a-f-G-U-C-I", SymbolStatus::Present, "SFGPUCI--------"),
+            ("a-h-A-M-F", SymbolStatus::Damaged, "SHADMF---------"),
+            ("a-n-S", SymbolStatus::Anticipated, "SNSA-----------"),
+            ("a-x-G", SymbolStatus::Present, "SUGP-----------"),
+            (
+                "a-f-G-1-2-3-4-5-6",
+                SymbolStatus::Present,
+                "SFGP123456-----",
+            ),
+        ] {
+            assert_eq!(
+                Sidc::from_cot_type(&cot_type(cot_type_code), status)
+                    .unwrap()
+                    .as_str(),
+                expected,
+                "{cot_type_code}"
+            );
+        }
+
+        for invalid in ["b-m-p-s-m", "a-f", "a-f-G-U-C-I-1-2-3-4", "a-f-G-Uc"] {
+            assert!(
+                Sidc::from_cot_type(&cot_type(invalid), SymbolStatus::Present).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for code in ["a-f-G-U-C-I", "a-h-A-M-F-Q", "a-s-P", "a-j-F-A", "a-o-U-S"] {
+            let sidc = Sidc::from_cot_type(&cot_type(code), SymbolStatus::Present).unwrap();
+            assert_eq!(sidc.to_cot_type().unwrap().as_str(), code);
+            assert_eq!(sidc.to_string().parse::<Sidc>().unwrap(), sidc);
+        }
+    }
+
+    #[test]
+    fn status() {
+        let sidc = sidc("SFGPUCI");
+        assert_eq!(sidc.status(), Some(SymbolStatus::Present));
+        for status in SymbolStatus::ALL {
+            let sidc = sidc.clone().with_status(status);
+            assert_eq!(sidc.status(), Some(status));
+            assert_eq!(sidc.to_cot_type().unwrap().as_str(), "a-f-G-U-C-I");
+        }
+        assert_eq!(self::sidc("SFG-UCI").status(), None);
+    }
+}
@@ -0,0 +1,627 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! Certificates and TLS servers generated for the unit tests, so that no key material is kept in
+//! the repository, and a TAK server the publishers connect to.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::ocsp::{OcspCertId, OcspCertStatus, OcspRequest};
+use openssl::pkey::{HasPublic, PKey, PKeyRef, Private};
+use openssl::sign::Signer;
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509, X509Builder, X509Name, X509NameBuilder, X509NameRef, X509ReqRef};
+use prost::Message;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    ClientConfig, ClientConnection, Connection, RootCertStore, ServerConfig, ServerConnection,
+};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use url::Url;
+
+use crate::{CursorOnTarget, tak_proto};
+
+use crate::keys::{Credentials, Source};
+
+/// Host name, and IP address, the certificates issued by a [`TestCa`] are valid for
+pub(crate) const TEST_HOST: &str = "localhost";
+
+/// Certificate authority issuing the certificates of a test
+pub(crate) struct TestCa {
+    pub(crate) cert: X509,
+    key: PKey<Private>,
+}
+
+/// Certificate issued by a [`TestCa`] with its private key
+pub(crate) struct TestIdentity {
+    pub(crate) cert: X509,
+    pub(crate) key: PKey<Private>,
+}
+
+impl TestCa {
+    /// Creates a self-signed CA with a new P-256 key
+    pub(crate) fn new(name: &str) -> Self {
+        let key = ec_key();
+        let name = subject_name(name);
+        let cert = certificate(&name, &key, (&name, &key), YEAR, |builder| {
+            let constraints = BasicConstraints::new().critical().ca().build().unwrap();
+            let usage = KeyUsage::new()
+                .critical()
+                .key_cert_sign()
+                .crl_sign()
+                .digital_signature()
+                .build()
+                .unwrap();
+            builder.append_extension(constraints).unwrap();
+            builder.append_extension(usage).unwrap();
+        });
+        Self { cert, key }
+    }
+
+    /// Issues a certificate for [`TEST_HOST`] and 127.0.0.1, usable by servers and clients
+    pub(crate) fn issue(&self, name: &str) -> TestIdentity {
+        self.issue_expiring(name, YEAR)
+    }
+
+    /// Issues a certificate for [`TEST_HOST`] and 127.0.0.1 which expires after a number of
+    /// seconds, or has expired for a negative number
+    pub(crate) fn issue_expiring(&self, name: &str, expires_in: i64) -> TestIdentity {
+        let key = ec_key();
+        let cert = self.sign(&subject_name(name), &key, expires_in);
+        TestIdentity { cert, key }
+    }
+
+    /// Issues a certificate for the subject and key of a certificate signing request
+    pub(crate) fn sign_request(&self, request: &X509ReqRef) -> X509 {
+        self.sign(request.subject_name(), &request.public_key().unwrap(), YEAR)
+    }
+
+    /// Issues a certificate for [`TEST_HOST`] and 127.0.0.1 to a subject
+    fn sign<T: HasPublic>(
+        &self,
+        subject: &X509NameRef,
+        public_key: &PKeyRef<T>,
+        expires_in: i64,
+    ) -> X509 {
+        let issuer = (self.cert.subject_name(), self.key.as_ref());
+        certificate(subject, public_key, issuer, expires_in, |builder| {
+            let san = SubjectAlternativeName::new()
+                .dns(TEST_HOST)
+                .ip("127.0.0.1")
+                .build(&builder.x509v3_context(Some(&self.cert), None))
+                .unwrap();
+            let usage = ExtendedKeyUsage::new()
+                .server_auth()
+                .client_auth()
+                .build()
+                .unwrap();
+            builder.append_extension(san).unwrap();
+            builder.append_extension(usage).unwrap();
+        })
+    }
+
+    pub(crate) fn cert_pem(&self) -> String {
+        String::from_utf8(self.cert.to_pem().unwrap()).unwrap()
+    }
+
+    pub(crate) fn cert_der(&self) -> CertificateDer<'static> {
+        CertificateDer::from(self.cert.to_der().unwrap())
+    }
+
+    /// Builds a DER encoded v2 CRL revoking the given certificates
+    pub(crate) fn crl(&self, revoked: &[&X509]) -> Vec<u8> {
+        let entries: Vec<u8> = revoked
+            .iter()
+            .flat_map(|cert| sequence(&[&serial(cert), &der(UTC_TIME, THIS_UPDATE_UTC)]))
+            .collect();
+        let crl_number = sequence(&[&der(OID, OID_CRL_NUMBER), &der(OCTET_STRING, &[2, 1, 1])]);
+        let tbs = sequence(&[
+            &der(INTEGER, &[1]),
+            &sequence(&[&der(OID, OID_ECDSA_SHA256)]),
+            &self.cert.subject_name().to_der().unwrap(),
+            &der(UTC_TIME, THIS_UPDATE_UTC),
+            &der(GENERALIZED_TIME, NEXT_UPDATE),
+            &der(SEQUENCE, &entries),
+            &der(0xa0, &sequence(&[&crl_number])),
+        ]);
+        self.signed(tbs, &[])
+    }
+
+    /// Builds a DER encoded OCSP response, signed by the CA, reporting the status of a
+    /// certificate it issued
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - Certificate the response covers
+    /// * `digest` - Digest of the CertID identifying the certificate
+    /// * `status` - Status reported for the certificate
+    ///
+    pub(crate) fn ocsp_response(
+        &self,
+        subject: &X509,
+        digest: MessageDigest,
+        status: OcspCertStatus,
+    ) -> Vec<u8> {
+        // The CertID is taken from a request, as OpenSSL does not encode it on its own. A request
+        // is a sequence of the TBSRequest, holding the list of requests for each CertID
+        let mut request = OcspRequest::new().unwrap();
+        request
+            .add_id(OcspCertId::from_cert(digest, subject, &self.cert).unwrap())
+            .unwrap();
+        let request = request.to_der().unwrap();
+        let (_, tbs_request, _) = split(&request);
+        let (_, request_list, _) = split(tbs_request);
+        let (_, single_request, _) = split(request_list);
+        let (_, cert_id, _) = split(single_request);
+        let (cert_id, _, _) = split(cert_id);
+
+        let status = match status {
+            OcspCertStatus::GOOD => vec![0x80, 0],
+            OcspCertStatus::REVOKED => der(0xa1, &der(GENERALIZED_TIME, THIS_UPDATE)),
+            _ => vec![0x82, 0],
+        };
+        let single = sequence(&[
+            cert_id,
+            &status,
+            &der(GENERALIZED_TIME, THIS_UPDATE),
+            &der(0xa0, &der(GENERALIZED_TIME, NEXT_UPDATE)),
+        ]);
+        let tbs = sequence(&[
+            &der(0xa1, &self.cert.subject_name().to_der().unwrap()),
+            &der(GENERALIZED_TIME, THIS_UPDATE),
+            &sequence(&[&single]),
+        ]);
+        let certs = der(0xa0, &sequence(&[&self.cert.to_der().unwrap()]));
+        let basic = self.signed(tbs, &certs);
+
+        let response_bytes = sequence(&[&der(OID, OID_OCSP_BASIC), &der(OCTET_STRING, &basic)]);
+        sequence(&[&der(ENUMERATED, &[0]), &der(0xa0, &response_bytes)])
+    }
+
+    /// Signs a to-be-signed structure, returning the signed structure with any trailing fields
+    fn signed(&self, tbs: Vec<u8>, trailing: &[u8]) -> Vec<u8> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+        let signature = [&[0u8][..], &signer.sign_oneshot_to_vec(&tbs).unwrap()].concat();
+        sequence(&[
+            &tbs,
+            &sequence(&[&der(OID, OID_ECDSA_SHA256)]),
+            &der(BIT_STRING, &signature),
+            trailing,
+        ])
+    }
+}
+
+impl TestIdentity {
+    pub(crate) fn cert_pem(&self) -> String {
+        String::from_utf8(self.cert.to_pem().unwrap()).unwrap()
+    }
+
+    pub(crate) fn key_pem(&self) -> String {
+        String::from_utf8(self.key.private_key_to_pem_pkcs8().unwrap()).unwrap()
+    }
+
+    pub(crate) fn cert_der(&self) -> CertificateDer<'static> {
+        CertificateDer::from(self.cert.to_der().unwrap())
+    }
+
+    pub(crate) fn key_der(&self) -> PrivateKeyDer<'static> {
+        PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+            self.key.private_key_to_pkcs8().unwrap(),
+        ))
+    }
+
+    /// Client credentials holding the certificate and key
+    pub(crate) fn credentials(&self) -> Credentials<'static> {
+        Credentials::from_unencrypted_pem(
+            Source::String(self.cert_pem()),
+            Source::String(self.key_pem()),
+        )
+        .unwrap()
+    }
+}
+
+/// Builds the TLS configuration of a test server
+///
+/// # Arguments
+///
+/// * `identity` - Certificate and key of the server
+/// * `client_ca` - CA which must have issued the client certificate, when mutual TLS is required
+/// * `ocsp` - OCSP response stapled by the server, empty for none
+///
+pub(crate) fn server_config(
+    identity: &TestIdentity,
+    client_ca: Option<&TestCa>,
+    ocsp: Vec<u8>,
+) -> ServerConfig {
+    let provider = crate::connection::default_crypto_provider().unwrap();
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap();
+    let builder = match client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            roots.add(ca.cert_der()).unwrap();
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .unwrap();
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert_with_ocsp(vec![identity.cert_der()], identity.key_der(), ocsp)
+        .unwrap()
+}
+
+/// Runs a TLS handshake between a client and a server in memory, returning the first error
+/// either side reports
+pub(crate) fn handshake(client: ClientConfig, server: ServerConfig) -> Result<(), rustls::Error> {
+    handshake_connection(client, server).map(|_| ())
+}
+
+/// Runs a TLS handshake between a client and a server in memory, returning the client side of
+/// the established connection
+pub(crate) fn handshake_connection(
+    client: ClientConfig,
+    server: ServerConfig,
+) -> Result<Connection, rustls::Error> {
+    let server_name = ServerName::try_from(TEST_HOST).unwrap();
+    let mut client = Connection::from(ClientConnection::new(Arc::new(client), server_name)?);
+    let mut server = Connection::from(ServerConnection::new(Arc::new(server))?);
+
+    for _ in 0..16 {
+        if !client.is_handshaking() && !server.is_handshaking() {
+            return Ok(client);
+        }
+        transfer(&mut client, &mut server)?;
+        transfer(&mut server, &mut client)?;
+    }
+    Err(rustls::Error::General("Handshake did not complete".into()))
+}
+
+/// Moves the pending TLS records of one side of a connection to the other
+fn transfer(from: &mut Connection, to: &mut Connection) -> Result<(), rustls::Error> {
+    let mut records = Vec::new();
+    while from.wants_write() {
+        from.write_tls(&mut records).unwrap();
+    }
+    let mut records = records.as_slice();
+    while !records.is_empty() {
+        to.read_tls(&mut records).unwrap();
+        to.process_new_packets()?;
+    }
+    Ok(())
+}
+
+/// Answer of the TAK server accepting the switch to the TAK protocol
+const TAK_RESPONSE: &str = "<?xml version='1.0' encoding='UTF-8'?>\
+    <event version='2.0' uid='protouid' type='t-x-takp-r' how='m-g'>\
+    <point lat='0.0' lon='0.0' hae='0.0' ce='999999' le='999999'/>\
+    <detail><TakControl><TakResponse status='true'/></TakControl></detail></event>";
+
+/// TAK server on 127.0.0.1 accepting TCP or TLS connections, which answers the protocol
+/// negotiation and reports the COT events it receives, stopped when dropped
+pub(crate) struct TestTakServer {
+    pub(crate) address: SocketAddr,
+    events: mpsc::UnboundedReceiver<ReceivedEvent>,
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+/// COT event received by a [`TestTakServer`]
+pub(crate) struct ReceivedEvent {
+    /// Client certificate presented on a TLS connection
+    pub(crate) client_certificate: Option<CertificateDer<'static>>,
+    pub(crate) event: tak_proto::CotEvent,
+}
+
+impl TestTakServer {
+    /// Starts a server accepting plain TCP connections on a free port, which answers pings
+    pub(crate) async fn start() -> Self {
+        Self::start_on("127.0.0.1:0".parse().unwrap(), None, Some(Duration::ZERO)).await
+    }
+
+    /// Starts a server
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address to listen on, such as the address of a server which was stopped
+    /// * `tls` - TLS configuration of the server, plain TCP when `None`
+    /// * `pong_delay` - Delay before pings are answered, confirming the messages sent before
+    ///   them, or `None` to leave them unanswered
+    ///
+    pub(crate) async fn start_on(
+        address: SocketAddr,
+        tls: Option<ServerConfig>,
+        pong_delay: Option<Duration>,
+    ) -> Self {
+        let listener = TcpListener::bind(address).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let acceptor = tls.map(|config| tokio_rustls::TlsAcceptor::from(Arc::new(config)));
+        let (sender, events) = mpsc::unbounded_channel();
+
+        let task = tokio::spawn(async move {
+            // Dropping the connections with the accepting task closes them
+            let mut connections = tokio::task::JoinSet::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                let Some(acceptor) = acceptor.clone() else {
+                    connections.spawn(serve_tak(stream, None, pong_delay, sender.clone()));
+                    continue;
+                };
+
+                let sender = sender.clone();
+                connections.spawn(async move {
+                    let stream = acceptor.accept(stream).await?;
+                    let client_certificate = stream
+                        .get_ref()
+                        .1
+                        .peer_certificates()
+                        .and_then(|certificates| certificates.first())
+                        .map(|certificate| certificate.clone().into_owned());
+                    serve_tak(stream, client_certificate, pong_delay, sender).await
+                });
+            }
+        });
+
+        Self {
+            address,
+            events,
+            task: Some(task),
+        }
+    }
+
+    /// URL of the server
+    pub(crate) fn url(&self) -> Url {
+        Url::parse(&format!("tcp://{}", self.address)).unwrap()
+    }
+
+    /// Waits for the next COT event received by the server
+    pub(crate) async fn next_event(&mut self) -> ReceivedEvent {
+        tokio::time::timeout(Duration::from_secs(10), self.events.recv())
+            .await
+            .expect("TAK server received no event")
+            .unwrap()
+    }
+
+    /// COT events received by the server which have not been taken yet
+    pub(crate) fn received_events(&mut self) -> Vec<ReceivedEvent> {
+        std::iter::from_fn(|| self.events.try_recv().ok()).collect()
+    }
+
+    /// Stops the server, closing its connections and freeing its address
+    pub(crate) async fn stop(mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for TestTakServer {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Answers the protocol negotiation of a connection and reports the COT events received on it
+async fn serve_tak<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    client_certificate: Option<CertificateDer<'static>>,
+    pong_delay: Option<Duration>,
+    sender: mpsc::UnboundedSender<ReceivedEvent>,
+) -> Result<(), io::Error> {
+    let mut stream = BufReader::new(stream);
+    let request = read_xml_event(&mut stream).await?;
+    assert!(request.contains("<TakRequest"), "{request}");
+    stream.write_all(TAK_RESPONSE.as_bytes()).await?;
+
+    // Skip the line breaks following the request up to the first message
+    loop {
+        let buffer = stream.fill_buf().await?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        match buffer.iter().position(|byte| *byte == crate::TCP_MAGIC[0]) {
+            Some(start) => {
+                stream.consume(start);
+                break;
+            }
+            None => {
+                let skipped = buffer.len();
+                stream.consume(skipped);
+            }
+        }
+    }
+
+    loop {
+        let payload = read_frame(&mut stream).await?;
+        let Some(event) = tak_proto::TakMessage::decode(payload.as_slice())?.cot_event else {
+            continue;
+        };
+
+        if event.r#type == "t-x-c-t" {
+            if let Some(delay) = pong_delay {
+                tokio::time::sleep(delay).await;
+                let mut pong = CursorOnTarget::default();
+                pong.uid = "takserver".into();
+                pong.r#type = "t-x-c-t-r".into();
+                let pong = crate::rpc_from_cot(&pong).encode_to_vec();
+                stream.write_all(&crate::framing::frame(&pong)).await?;
+            }
+            continue;
+        }
+
+        let received = ReceivedEvent {
+            client_certificate: client_certificate.clone(),
+            event,
+        };
+        if sender.send(received).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads an XML event, such as the request switching to the TAK protocol, from a stream
+pub(crate) async fn read_xml_event<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> Result<String, io::Error> {
+    let mut event = Vec::new();
+    while !event.ends_with(b"</event>") {
+        event.push(stream.read_u8().await?);
+    }
+    String::from_utf8(event).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Reads a framed TAK protocol message from a stream, returning its payload
+pub(crate) async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, io::Error> {
+    let mut message = vec![stream.read_u8().await?];
+    loop {
+        if let Some(payload) = crate::framing::decode_frame(&message)? {
+            return Ok(message[payload].to_vec());
+        }
+        message.push(stream.read_u8().await?);
+    }
+}
+
+/// Generates a P-256 key
+fn ec_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+/// Builds a subject name with a common name
+fn subject_name(common_name: &str) -> X509Name {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+        .unwrap();
+    name.build()
+}
+
+/// Builds a certificate valid from a day before it is issued, or before it expires when it has
+/// already expired
+///
+/// # Arguments
+///
+/// * `subject` - Subject name of the certificate
+/// * `public_key` - Public key of the subject
+/// * `issuer` - Name and key of the issuer, the subject's own for a self-signed certificate
+/// * `expires_in` - Seconds from now until the certificate expires
+/// * `extensions` - Adds the extensions of the certificate
+///
+fn certificate<T: HasPublic>(
+    subject: &X509NameRef,
+    public_key: &PKeyRef<T>,
+    issuer: (&X509NameRef, &PKeyRef<Private>),
+    expires_in: i64,
+    extensions: impl FnOnce(&mut X509Builder),
+) -> X509 {
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(63, MsbOption::MAYBE_ZERO, false).unwrap();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&serial.to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(subject).unwrap();
+    builder.set_issuer_name(issuer.0).unwrap();
+    builder.set_pubkey(public_key).unwrap();
+    let not_after = now() + expires_in;
+    let not_before = openssl::asn1::Asn1Time::from_unix(now().min(not_after) - 86_400).unwrap();
+    let not_after = openssl::asn1::Asn1Time::from_unix(not_after).unwrap();
+    builder.set_not_before(&not_before).unwrap();
+    builder.set_not_after(&not_after).unwrap();
+    extensions(&mut builder);
+    builder.sign(issuer.1, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+/// Seconds in a year, the validity of certificates issued without an expiry
+const YEAR: i64 = 365 * 86_400;
+
+/// Seconds since the Unix epoch
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+// DER tags and values used to build CRLs and OCSP responses
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OID: u8 = 0x06;
+const ENUMERATED: u8 = 0x0a;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+/// 1.2.840.10045.4.3.2
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+/// 1.3.6.1.5.5.7.48.1.1
+const OID_OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+/// 2.5.29.20
+const OID_CRL_NUMBER: &[u8] = &[0x55, 0x1d, 0x14];
+const THIS_UPDATE: &[u8] = b"20250101000000Z";
+const THIS_UPDATE_UTC: &[u8] = b"250101000000Z";
+const NEXT_UPDATE: &[u8] = b"20991231000000Z";
+
+/// Encodes a DER element
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+    if contents.len() < 0x80 {
+        element.push(contents.len() as u8);
+    } else {
+        let length = contents.len().to_be_bytes();
+        let length: Vec<u8> = length.into_iter().skip_while(|b| *b == 0).collect();
+        element.push(0x80 | length.len() as u8);
+        element.extend(length);
+    }
+    element.extend_from_slice(contents);
+    element
+}
+
+/// Encodes a DER sequence of encoded elements
+fn sequence(elements: &[&[u8]]) -> Vec<u8> {
+    der(SEQUENCE, &elements.concat())
+}
+
+/// Encodes the serial number of a certificate as a DER integer
+fn serial(cert: &X509) -> Vec<u8> {
+    let serial = cert.serial_number().to_bn().unwrap().to_vec();
+    if serial.first().is_some_and(|b| b & 0x80 != 0) {
+        der(INTEGER, &[&[0], serial.as_slice()].concat())
+    } else {
+        der(INTEGER, &serial)
+    }
+}
+
+/// Splits the first DER element of the input into the whole element, its contents and the rest
+/// of the input
+fn split(input: &[u8]) -> (&[u8], &[u8], &[u8]) {
+    let (header, length) = match input[1] {
+        length if length < 0x80 => (2, length as usize),
+        length => {
+            let bytes = (length & 0x7f) as usize;
+            let length = input[2..2 + bytes]
+                .iter()
+                .fold(0, |length, b| length << 8 | *b as usize);
+            (2 + bytes, length)
+        }
+    };
+    let (element, rest) = input.split_at(header + length);
+    (element, &element[header..], rest)
+}
@@ -0,0 +1,291 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides the track (speed and course) of a moving COT entity, which ATAK shows
+//! as a heading arrow and speed leader.
+//!
+//! Distances and bearings are calculated on a sphere with the mean radius of the earth, which
+//! is accurate to within 0.5% and sufficient between consecutive position fixes.
+
+use std::time::{Duration, SystemTime};
+
+use crate::Position;
+
+/// Mean radius of the earth in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+/// Meters per second in a knot
+const KNOT: f64 = 1852.0 / 3600.0;
+/// Meters per second in a kilometer per hour
+const KILOMETER_PER_HOUR: f64 = 1000.0 / 3600.0;
+/// Meters per second in a mile per hour
+const MILE_PER_HOUR: f64 = 1609.344 / 3600.0;
+
+/// Speed and course of a moving COT entity
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Track {
+    /// Speed in meters per second
+    pub speed: f64,
+    /// Course over ground in degrees clockwise from true north, 0 to 360
+    pub course: f64,
+}
+
+impl Track {
+    /// Creates a track from the speed in meters per second
+    ///
+    /// # Arguments
+    ///
+    /// * `speed` - Speed in meters per second
+    /// * `course` - Course in degrees clockwise from true north
+    ///
+    pub fn new(speed: f64, course: f64) -> Self {
+        Self {
+            speed,
+            course: course.rem_euclid(360.0),
+        }
+    }
+
+    /// Creates a track from the speed in knots
+    ///
+    /// # Arguments
+    ///
+    /// * `knots` - Speed in knots
+    /// * `course` - Course in degrees clockwise from true north
+    ///
+    pub fn from_knots(knots: f64, course: f64) -> Self {
+        Self::new(knots * KNOT, course)
+    }
+
+    /// Creates a track from the speed in kilometers per hour
+    ///
+    /// # Arguments
+    ///
+    /// * `kmh` - Speed in kilometers per hour
+    /// * `course` - Course in degrees clockwise from true north
+    ///
+    pub fn from_kmh(kmh: f64, course: f64) -> Self {
+        Self::new(kmh * KILOMETER_PER_HOUR, course)
+    }
+
+    /// Creates a track from the speed in miles per hour
+    ///
+    /// # Arguments
+    ///
+    /// * `mph` - Speed in miles per hour
+    /// * `course` - Course in degrees clockwise from true north
+    ///
+    pub fn from_mph(mph: f64, course: f64) -> Self {
+        Self::new(mph * MILE_PER_HOUR, course)
+    }
+
+    /// Whether the speed and course are finite, tracks which are not are left out of COT
+    /// messages
+    pub fn is_finite(&self) -> bool {
+        self.speed.is_finite() && self.course.is_finite()
+    }
+
+    /// Speed in knots
+    pub fn knots(&self) -> f64 {
+        self.speed / KNOT
+    }
+
+    /// Speed in kilometers per hour
+    pub fn kmh(&self) -> f64 {
+        self.speed / KILOMETER_PER_HOUR
+    }
+
+    /// Speed in miles per hour
+    pub fn mph(&self) -> f64 {
+        self.speed / MILE_PER_HOUR
+    }
+
+    /// Calculates the track between two timestamped position fixes, from the great circle
+    /// distance and the initial bearing between them
+    ///
+    /// Returns `None` when the second fix is not later than the first.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Earlier position fix
+    /// * `from_time` - Time of the earlier fix
+    /// * `to` - Later position fix
+    /// * `to_time` - Time of the later fix
+    ///
+    pub fn between(
+        from: &Position,
+        from_time: SystemTime,
+        to: &Position,
+        to_time: SystemTime,
+    ) -> Option<Self> {
+        let elapsed = to_time.duration_since(from_time).ok()?.as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+
+        let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+        let delta_lat = lat2 - lat1;
+        let delta_lng = (to.lng - from.lng).to_radians();
+
+        // Haversine distance
+        let a = (delta_lat / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * (delta_lng / 2.0).sin().powi(2);
+        let distance = 2.0 * EARTH_RADIUS * a.sqrt().atan2((1.0 - a).sqrt());
+
+        // Initial bearing
+        let y = delta_lng.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * delta_lng.cos();
+        let course = y.atan2(x).to_degrees();
+
+        Some(Self::new(distance / elapsed, course))
+    }
+
+    /// Predicts the position after travelling along the track for the elapsed time, following
+    /// the great circle from the last fix
+    ///
+    /// The height and accuracy of the last fix are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Last position fix
+    /// * `elapsed` - Time since the last fix
+    ///
+    pub fn dead_reckon(&self, from: &Position, elapsed: Duration) -> Position {
+        let angular_distance = self.speed * elapsed.as_secs_f64() / EARTH_RADIUS;
+        let course = self.course.to_radians();
+        let lat1 = from.lat.to_radians();
+        let lng1 = from.lng.to_radians();
+
+        let lat2 = (lat1.sin() * angular_distance.cos()
+            + lat1.cos() * angular_distance.sin() * course.cos())
+        .asin();
+        let lng2 = lng1
+            + (course.sin() * angular_distance.sin() * lat1.cos())
+                .atan2(angular_distance.cos() - lat1.sin() * lat2.sin());
+
+        Position {
+            lat: lat2.to_degrees(),
+            // Normalise to -180 to 180 when crossing the antimeridian
+            lng: (lng2.to_degrees() + 540.0).rem_euclid(360.0) - 180.0,
+            hae: from.hae,
+            ce: from.ce,
+            le: from.le,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Position at sea level with the given latitude and longitude
+    fn position(lat: f64, lng: f64) -> Position {
+        Position {
+            lat,
+            lng,
+            hae: 0.0,
+            ce: 10.0,
+            le: 10.0,
+        }
+    }
+
+    /// Asserts that two values are equal within a tolerance
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn unit_conversions() {
+        assert_near(Track::from_knots(1.0, 0.0).speed, 1852.0 / 3600.0, 1e-12);
+        assert_near(Track::from_kmh(36.0, 0.0).speed, 10.0, 1e-12);
+        assert_near(Track::from_mph(1.0, 0.0).speed, 0.44704, 1e-12);
+
+        assert_near(Track::from_knots(12.5, 0.0).knots(), 12.5, 1e-9);
+        assert_near(Track::from_kmh(80.0, 0.0).kmh(), 80.0, 1e-9);
+        assert_near(Track::from_mph(55.0, 0.0).mph(), 55.0, 1e-9);
+        assert_near(Track::from_knots(10.0, 0.0).kmh(), 18.52, 1e-9);
+    }
+
+    #[test]
+    fn course_normalised() {
+        assert_eq!(Track::new(1.0, 370.0).course, 10.0);
+        assert_eq!(Track::new(1.0, -90.0).course, 270.0);
+        assert_eq!(Track::new(1.0, 360.0).course, 0.0);
+        assert_eq!(Track::new(1.0, 45.0).course, 45.0);
+    }
+
+    #[test]
+    fn between_requires_later_fix() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let (from, to) = (position(0.0, 0.0), position(1.0, 0.0));
+        assert_eq!(Track::between(&from, time, &to, time), None);
+        assert_eq!(
+            Track::between(&from, time, &to, time - Duration::from_secs(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn between_known_fixes() {
+        // Baghdad to Osaka, 7872 km at an initial bearing of 60.16 degrees
+        let time = SystemTime::UNIX_EPOCH;
+        let elapsed = Duration::from_secs(10_000);
+        let track = Track::between(
+            &position(35.0, 45.0),
+            time,
+            &position(35.0, 135.0),
+            time + elapsed,
+        )
+        .unwrap();
+        assert_near(track.speed * elapsed.as_secs_f64(), 7_871_780.0, 1.0);
+        assert_near(track.course, 60.1624, 1e-4);
+
+        // One degree due south along a meridian
+        let track = Track::between(
+            &position(0.0, 0.0),
+            time,
+            &position(-1.0, 0.0),
+            time + Duration::from_secs(100),
+        )
+        .unwrap();
+        assert_near(track.speed, 1_111.950_8, 1e-3);
+        assert_near(track.course, 180.0, 1e-9);
+    }
+
+    #[test]
+    fn dead_reckon_follows_track() {
+        let from = position(35.0, 45.0);
+        let track = Track::new(250.0, 60.0);
+        let elapsed = Duration::from_secs(600);
+        let predicted = track.dead_reckon(&from, elapsed);
+        assert_eq!(
+            (predicted.hae, predicted.ce, predicted.le),
+            (0.0, 10.0, 10.0)
+        );
+
+        let back = Track::between(
+            &from,
+            SystemTime::UNIX_EPOCH,
+            &predicted,
+            SystemTime::UNIX_EPOCH + elapsed,
+        )
+        .unwrap();
+        assert_near(back.speed, track.speed, 1e-6);
+        assert_near(back.course, track.course, 1e-6);
+    }
+
+    #[test]
+    fn dead_reckon_across_antimeridian() {
+        // One degree of longitude along the equator takes 1000 s
+        let track = Track::new(EARTH_RADIUS * 1.0_f64.to_radians() / 1_000.0, 90.0);
+        let predicted = track.dead_reckon(&position(0.0, 179.5), Duration::from_secs(1_000));
+        assert_near(predicted.lat, 0.0, 1e-9);
+        assert_near(predicted.lng, -179.5, 1e-9);
+
+        let track = Track::new(track.speed, 270.0);
+        let predicted = track.dead_reckon(&position(0.0, -179.5), Duration::from_secs(1_000));
+        assert_near(predicted.lat, 0.0, 1e-9);
+        assert_near(predicted.lng, 179.5, 1e-9);
+    }
+}
@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2021-2025 Martyn P <martyn@datasync.dev>
+
+//! This module provides the settings for publishing to UDP unicast receivers, such as the UDP
+//! input port of a TAK server.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Encoding of the COT messages sent in each datagram
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// TAK protocol version 1 (protobuf) with the UDP magic header
+    #[default]
+    TakProtocol,
+    /// Plain COT XML event, accepted by receivers which do not support the TAK protocol
+    Xml,
+}
+
+/// UDP unicast publisher settings
+#[derive(Debug, Clone, Default)]
+pub struct UdpSetting {
+    /// Encoding of the COT messages
+    pub payload: PayloadFormat,
+    /// Interval after which the host name is resolved again before sending, when `None` the
+    /// host is only resolved again after a failed resolution
+    pub resolve_interval: Option<Duration>,
+    /// Optional local IP address to bind to, defaults to the unspecified address of the
+    /// resolved destination's address family
+    pub bind_address: Option<IpAddr>,
+}